@@ -0,0 +1,32 @@
+-- Publishes every new row on a channel named after its table for the live feed.
+CREATE OR REPLACE FUNCTION notify_volume_data() RETURNS trigger AS $$
+BEGIN
+    PERFORM pg_notify('volume_data', json_build_object(
+        'exchange_id', NEW.exchange_id,
+        'token_symbol', NEW.token_symbol,
+        'price', NEW.price,
+        'total_volume', NEW.total_volume,
+        'day_total_volume', NEW.day_total_volume,
+        'timestamp', EXTRACT(EPOCH FROM NEW.timestamp)::BIGINT
+    )::text);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION notify_balance_data() RETURNS trigger AS $$
+BEGIN
+    PERFORM pg_notify('balance_data', json_build_object(
+        'exchange_id', NEW.exchange_id,
+        'token_symbol', NEW.token_symbol,
+        'wallet_balance', NEW.wallet_balance,
+        'transfer_balance', NEW.transfer_balance,
+        'timestamp', EXTRACT(EPOCH FROM NEW.timestamp)::BIGINT
+    )::text);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER volume_data_notify AFTER INSERT ON volume_data
+    FOR EACH ROW EXECUTE FUNCTION notify_volume_data();
+CREATE TRIGGER balance_data_notify AFTER INSERT ON balance_data
+    FOR EACH ROW EXECUTE FUNCTION notify_balance_data();
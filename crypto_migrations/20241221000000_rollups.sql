@@ -0,0 +1,55 @@
+-- Pre-aggregated 5m/1h/1d buckets of volume_data and balance_data, kept
+-- current by the server's rollup job. Buckets are aligned to the Unix epoch.
+-- Columns hold partial aggregates (sums and counts rather than averages) so
+-- that coarser buckets can be recombined from them exactly.
+CREATE TABLE volume_data_5m (
+    exchange_id INTEGER NOT NULL,
+    token_symbol VARCHAR(32) NOT NULL,
+    bucket_start TIMESTAMPTZ NOT NULL,
+    total_volume DOUBLE PRECISION NOT NULL,
+    price_sum DOUBLE PRECISION NOT NULL,
+    price_count BIGINT NOT NULL,
+    day_volume_max DOUBLE PRECISION,
+    day_volume_sum DOUBLE PRECISION NOT NULL,
+    day_volume_count BIGINT NOT NULL,
+    PRIMARY KEY (token_symbol, exchange_id, bucket_start)
+);
+CREATE TABLE volume_data_1h (LIKE volume_data_5m INCLUDING ALL);
+CREATE TABLE volume_data_1d (LIKE volume_data_5m INCLUDING ALL);
+
+CREATE TABLE balance_data_5m (
+    exchange_id INTEGER NOT NULL,
+    token_symbol VARCHAR(32) NOT NULL,
+    bucket_start TIMESTAMPTZ NOT NULL,
+    first_wallet DOUBLE PRECISION NOT NULL,
+    first_transfer DOUBLE PRECISION NOT NULL,
+    first_at TIMESTAMPTZ NOT NULL,
+    last_wallet DOUBLE PRECISION NOT NULL,
+    last_transfer DOUBLE PRECISION NOT NULL,
+    last_at TIMESTAMPTZ NOT NULL,
+    wallet_sum DOUBLE PRECISION NOT NULL,
+    transfer_sum DOUBLE PRECISION NOT NULL,
+    sample_count BIGINT NOT NULL,
+    wallet_min DOUBLE PRECISION NOT NULL,
+    wallet_max DOUBLE PRECISION NOT NULL,
+    transfer_min DOUBLE PRECISION NOT NULL,
+    transfer_max DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (token_symbol, exchange_id, bucket_start)
+);
+CREATE TABLE balance_data_1h (LIKE balance_data_5m INCLUDING ALL);
+CREATE TABLE balance_data_1d (LIKE balance_data_5m INCLUDING ALL);
+
+-- Progress of each rollup table. Buckets before high_water_mark are complete.
+-- last_id is replaced by ingested_through in
+-- 20241224000000_rollup_ingested_at.sql, which finds late rows by the time
+-- they were ingested rather than by row id.
+CREATE TABLE rollup_state (
+    table_name VARCHAR(64) PRIMARY KEY,
+    last_id BIGINT NOT NULL,
+    high_water_mark TIMESTAMPTZ NOT NULL,
+    refreshed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- The refresh recomputes buckets by time range.
+CREATE INDEX IF NOT EXISTS volume_data_timestamp_idx ON volume_data (timestamp);
+CREATE INDEX IF NOT EXISTS balance_data_timestamp_idx ON balance_data (timestamp);
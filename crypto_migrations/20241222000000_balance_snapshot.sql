@@ -0,0 +1,3 @@
+-- Point-in-time snapshots look up the last row of every exchange and token.
+CREATE INDEX IF NOT EXISTS balance_data_exchange_token_timestamp_idx
+    ON balance_data (exchange_id, token_symbol, timestamp DESC);
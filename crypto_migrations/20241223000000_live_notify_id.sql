@@ -0,0 +1,30 @@
+-- Adds the row id to live feed notifications, so that SSE replay can tell
+-- replayed rows from live ones.
+CREATE OR REPLACE FUNCTION notify_volume_data() RETURNS trigger AS $$
+BEGIN
+    PERFORM pg_notify('volume_data', json_build_object(
+        'id', NEW.id,
+        'exchange_id', NEW.exchange_id,
+        'token_symbol', NEW.token_symbol,
+        'price', NEW.price,
+        'total_volume', NEW.total_volume,
+        'day_total_volume', NEW.day_total_volume,
+        'timestamp', EXTRACT(EPOCH FROM NEW.timestamp)::BIGINT
+    )::text);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION notify_balance_data() RETURNS trigger AS $$
+BEGIN
+    PERFORM pg_notify('balance_data', json_build_object(
+        'id', NEW.id,
+        'exchange_id', NEW.exchange_id,
+        'token_symbol', NEW.token_symbol,
+        'wallet_balance', NEW.wallet_balance,
+        'transfer_balance', NEW.transfer_balance,
+        'timestamp', EXTRACT(EPOCH FROM NEW.timestamp)::BIGINT
+    )::text);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,15 @@
+-- Records when each raw row was written, so the rollup refresh can find rows
+-- that arrived late. Row ids are not usable for this: sequence values are
+-- handed out when a transaction inserts, not when it commits, so a row with a
+-- smaller id can become visible after one with a larger id.
+--
+-- NOW() is the start of the inserting transaction, and the refresh re-scans
+-- a trailing window (INGEST_OVERLAP_MINUTES in src/utils/rollup.rs) to cover
+-- rows that commit after a later refresh has started. Adding a column with a
+-- stable default does not rewrite the table; existing rows read the
+-- migration time. The ingested_at indexes are built by the next migration.
+ALTER TABLE volume_data ADD COLUMN IF NOT EXISTS ingested_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+ALTER TABLE balance_data ADD COLUMN IF NOT EXISTS ingested_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+
+ALTER TABLE rollup_state ADD COLUMN IF NOT EXISTS ingested_through TIMESTAMPTZ NOT NULL DEFAULT NOW();
+ALTER TABLE rollup_state DROP COLUMN IF EXISTS last_id;
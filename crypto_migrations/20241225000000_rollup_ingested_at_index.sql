@@ -0,0 +1,6 @@
+-- no-transaction
+-- The rollup refresh looks up rows by ingested_at. CONCURRENTLY keeps the
+-- ingester writing while the indexes build, and cannot run inside the
+-- transaction migrations normally get.
+CREATE INDEX CONCURRENTLY IF NOT EXISTS volume_data_ingested_at_idx ON volume_data (ingested_at);
+CREATE INDEX CONCURRENTLY IF NOT EXISTS balance_data_ingested_at_idx ON balance_data (ingested_at);
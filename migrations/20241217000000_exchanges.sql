@@ -0,0 +1,22 @@
+CREATE TABLE IF NOT EXISTS exchanges (
+    id INTEGER PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    slug VARCHAR(64) UNIQUE NOT NULL,
+    -- 'per_row': total_volume holds the volume traded since the previous row.
+    -- 'rolling_24h': day_total_volume holds the exchange's rolling 24h total.
+    volume_semantics VARCHAR(32) NOT NULL DEFAULT 'per_row',
+    -- Currency volumes are reported in: 'base' or 'quote'.
+    quote_convention VARCHAR(16) NOT NULL DEFAULT 'base',
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- The exchanges the ingester wrote before this table existed. Rename them as needed.
+INSERT INTO exchanges (id, name, slug, volume_semantics, quote_convention) VALUES
+    (0, 'Exchange 0', 'exchange-0', 'per_row', 'base'),
+    (1, 'Exchange 1', 'exchange-1', 'rolling_24h', 'quote'),
+    (2, 'Exchange 2', 'exchange-2', 'per_row', 'base'),
+    (3, 'Exchange 3', 'exchange-3', 'per_row', 'base'),
+    (4, 'Exchange 4', 'exchange-4', 'per_row', 'base')
+ON CONFLICT (id) DO NOTHING;
@@ -0,0 +1,30 @@
+CREATE TABLE IF NOT EXISTS alert_rules (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(255),
+    -- balance, balance_change_pct, volume, price or price_change_pct
+    metric VARCHAR(32) NOT NULL,
+    symbol VARCHAR(32) NOT NULL,
+    -- NULL sums (or for prices averages) over all exchanges.
+    exchange_id INTEGER,
+    -- gt, gte, lt or lte
+    comparator VARCHAR(8) NOT NULL,
+    threshold DOUBLE PRECISION NOT NULL,
+    window_secs INTEGER NOT NULL DEFAULT 3600,
+    cooldown_secs INTEGER NOT NULL DEFAULT 3600,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    last_triggered_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX IF NOT EXISTS alert_rules_user_id_idx ON alert_rules (user_id);
+
+CREATE TABLE IF NOT EXISTS alert_events (
+    id BIGSERIAL PRIMARY KEY,
+    rule_id BIGINT NOT NULL REFERENCES alert_rules(id) ON DELETE CASCADE,
+    value DOUBLE PRECISION NOT NULL,
+    threshold DOUBLE PRECISION NOT NULL,
+    notified BOOLEAN NOT NULL DEFAULT FALSE,
+    triggered_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX IF NOT EXISTS alert_events_rule_id_idx ON alert_events (rule_id, triggered_at);
@@ -0,0 +1,19 @@
+CREATE TABLE IF NOT EXISTS watchlists (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX IF NOT EXISTS watchlists_user_id_idx ON watchlists (user_id);
+
+CREATE TABLE IF NOT EXISTS watchlist_entries (
+    id BIGSERIAL PRIMARY KEY,
+    watchlist_id BIGINT NOT NULL REFERENCES watchlists(id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    symbol VARCHAR(32) NOT NULL,
+    -- NULL covers all exchanges.
+    exchange_id INTEGER,
+    unit VARCHAR(16),
+    UNIQUE (watchlist_id, position)
+);
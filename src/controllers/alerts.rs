@@ -0,0 +1,166 @@
+use crate::{
+    dto::request::AlertRuleRequest,
+    utils::{
+        alerts::{AlertEvent, AlertRule},
+        errors::ApiError,
+        jwt::UserClaims,
+    },
+    AppState,
+};
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+
+const DEFAULT_WINDOW_SECS: i32 = 3600;
+const DEFAULT_COOLDOWN_SECS: i32 = 3600;
+const MAX_WINDOW_SECS: i32 = 30 * 86400;
+
+fn validate(req: &AlertRuleRequest) -> Result<(i32, i32), ApiError> {
+    if req.symbol.trim().is_empty() {
+        return Err(ApiError::InvalidRequest("symbol is required".to_string()));
+    }
+    if !req.threshold.is_finite() {
+        return Err(ApiError::InvalidRequest(
+            "threshold must be a finite number".to_string(),
+        ));
+    }
+    if let Some(exchange_id) = req.exchange_id {
+        if exchange_id < 0 {
+            return Err(ApiError::InvalidRequest(format!(
+                "Invalid exchange_id: {}",
+                exchange_id
+            )));
+        }
+    }
+    let window_secs = req.window_secs.unwrap_or(DEFAULT_WINDOW_SECS);
+    if !(1..=MAX_WINDOW_SECS).contains(&window_secs) {
+        return Err(ApiError::InvalidRequest(format!(
+            "window_secs must be between 1 and {}",
+            MAX_WINDOW_SECS
+        )));
+    }
+    let cooldown_secs = req.cooldown_secs.unwrap_or(DEFAULT_COOLDOWN_SECS);
+    if cooldown_secs < 0 {
+        return Err(ApiError::InvalidRequest(
+            "cooldown_secs must not be negative".to_string(),
+        ));
+    }
+    Ok((window_secs, cooldown_secs))
+}
+
+async fn find_rule(state: &AppState, user: &UserClaims, id: i64) -> Result<AlertRule, ApiError> {
+    sqlx::query_as::<_, AlertRule>("SELECT * FROM alert_rules WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user.uid)
+        .fetch_optional(&state.db)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Alert rule {} not found", id)))
+}
+
+pub async fn list_alerts(
+    State(state): State<Arc<AppState>>,
+    user: UserClaims,
+) -> Result<Response, ApiError> {
+    let rules =
+        sqlx::query_as::<_, AlertRule>("SELECT * FROM alert_rules WHERE user_id = $1 ORDER BY id")
+            .bind(user.uid)
+            .fetch_all(&state.db)
+            .await?;
+    Ok(Json(rules).into_response())
+}
+
+pub async fn create_alert(
+    State(state): State<Arc<AppState>>,
+    user: UserClaims,
+    Json(req): Json<AlertRuleRequest>,
+) -> Result<Response, ApiError> {
+    let (window_secs, cooldown_secs) = validate(&req)?;
+    let rule = sqlx::query_as::<_, AlertRule>(
+        "INSERT INTO alert_rules (user_id, name, metric, symbol, exchange_id, comparator, threshold, window_secs, cooldown_secs, enabled) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) RETURNING *",
+    )
+    .bind(user.uid)
+    .bind(req.name)
+    .bind(req.metric)
+    .bind(req.symbol.trim())
+    .bind(req.exchange_id)
+    .bind(req.comparator)
+    .bind(req.threshold)
+    .bind(window_secs)
+    .bind(cooldown_secs)
+    .bind(req.enabled.unwrap_or(true))
+    .fetch_one(&state.db)
+    .await?;
+    Ok((StatusCode::CREATED, Json(rule)).into_response())
+}
+
+pub async fn get_alert(
+    State(state): State<Arc<AppState>>,
+    user: UserClaims,
+    Path(id): Path<i64>,
+) -> Result<Response, ApiError> {
+    Ok(Json(find_rule(&state, &user, id).await?).into_response())
+}
+
+pub async fn update_alert(
+    State(state): State<Arc<AppState>>,
+    user: UserClaims,
+    Path(id): Path<i64>,
+    Json(req): Json<AlertRuleRequest>,
+) -> Result<Response, ApiError> {
+    let (window_secs, cooldown_secs) = validate(&req)?;
+    let rule = sqlx::query_as::<_, AlertRule>(
+        "UPDATE alert_rules SET name = $3, metric = $4, symbol = $5, exchange_id = $6, comparator = $7, threshold = $8, \
+         window_secs = $9, cooldown_secs = $10, enabled = $11, updated_at = NOW() \
+         WHERE id = $1 AND user_id = $2 RETURNING *",
+    )
+    .bind(id)
+    .bind(user.uid)
+    .bind(req.name)
+    .bind(req.metric)
+    .bind(req.symbol.trim())
+    .bind(req.exchange_id)
+    .bind(req.comparator)
+    .bind(req.threshold)
+    .bind(window_secs)
+    .bind(cooldown_secs)
+    .bind(req.enabled.unwrap_or(true))
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or_else(|| ApiError::NotFound(format!("Alert rule {} not found", id)))?;
+    Ok(Json(rule).into_response())
+}
+
+pub async fn delete_alert(
+    State(state): State<Arc<AppState>>,
+    user: UserClaims,
+    Path(id): Path<i64>,
+) -> Result<Response, ApiError> {
+    let result = sqlx::query("DELETE FROM alert_rules WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user.uid)
+        .execute(&state.db)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!("Alert rule {} not found", id)));
+    }
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+pub async fn get_alert_events(
+    State(state): State<Arc<AppState>>,
+    user: UserClaims,
+    Path(id): Path<i64>,
+) -> Result<Response, ApiError> {
+    let rule = find_rule(&state, &user, id).await?;
+    let events = sqlx::query_as::<_, AlertEvent>(
+        "SELECT * FROM alert_events WHERE rule_id = $1 ORDER BY triggered_at DESC LIMIT 100",
+    )
+    .bind(rule.id)
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(events).into_response())
+}
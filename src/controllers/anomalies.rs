@@ -0,0 +1,367 @@
+use crate::{
+    dto::{
+        request::{GetAnomaliesRequest, OutputFormatRequest},
+        response::{AnomaliesResponse, AnomalyPoint},
+    },
+    utils::{
+        anomalies::{detect, Method},
+        errors::ApiError,
+        exchanges::VolumeSemantics,
+        export::{envelope, export_rows, OutputFormat},
+        rollup::RollupSource,
+        time_window::TimeWindow,
+    },
+    AppState,
+};
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use sqlx::{postgres::PgRow, Postgres, QueryBuilder, Row};
+use std::sync::Arc;
+
+const DEFAULT_WINDOW: usize = 24;
+const MAX_WINDOW: usize = 500;
+const DEFAULT_SENSITIVITY: f64 = 3.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Metric {
+    /// Volume traded per bucket, or the rolling 24h total of exchanges that
+    /// report one.
+    Volume,
+    /// Change of the balance since the previous bucket, so that drains and
+    /// inflows stand out rather than the balance level.
+    WalletBalance,
+    TransferBalance,
+}
+
+impl Metric {
+    fn parse(value: Option<&str>) -> Result<Self, ApiError> {
+        match value.unwrap_or("volume") {
+            "volume" => Ok(Metric::Volume),
+            "wallet_balance" => Ok(Metric::WalletBalance),
+            "transfer_balance" => Ok(Metric::TransferBalance),
+            other => Err(ApiError::InvalidRequest(format!(
+                "Unknown metric '{}', expected volume, wallet_balance or transfer_balance",
+                other
+            ))),
+        }
+    }
+
+    fn source(&self) -> RollupSource {
+        match self {
+            Metric::Volume => RollupSource::Volume,
+            Metric::WalletBalance | Metric::TransferBalance => RollupSource::Balance,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Metric::Volume => "volume",
+            Metric::WalletBalance => "wallet_balance",
+            Metric::TransferBalance => "transfer_balance",
+        }
+    }
+}
+
+/// Pushes one value per exchange, symbol and bucket, ordered by series and
+/// time. `filters` adds conditions on the source rows given the name of the
+/// time column, like [`Rollups::push_source`].
+///
+/// [`Rollups::push_source`]: crate::utils::rollup::Rollups::push_source
+fn push_anomaly_query(
+    query_builder: &mut QueryBuilder<'static, Postgres>,
+    state: &AppState,
+    query: &GetAnomaliesRequest,
+    metric: Metric,
+    window: &TimeWindow,
+    filters: impl Fn(&mut QueryBuilder<'static, Postgres>, &str),
+) {
+    let values = match metric {
+        Metric::Volume => {
+            "SUM(total_volume) AS value, SUM(day_volume_sum) / NULLIF(SUM(day_volume_count), 0)::DOUBLE PRECISION AS day_value"
+        }
+        Metric::WalletBalance => {
+            "(array_agg(last_wallet ORDER BY last_at DESC))[1] AS value, NULL::DOUBLE PRECISION AS day_value"
+        }
+        Metric::TransferBalance => {
+            "(array_agg(last_transfer ORDER BY last_at DESC))[1] AS value, NULL::DOUBLE PRECISION AS day_value"
+        }
+    };
+    let exchange_ids: Vec<i32> = state.exchanges.enabled().map(|e| e.id).collect();
+    query_builder.push("SELECT exchange_id, token_symbol, ");
+    query_builder.push(values);
+    query_builder.push(", ");
+    query_builder.push(window.bucket.sql_expr("ts"));
+    query_builder.push(" AS time_interval FROM ");
+    state.rollups.push_source(
+        query_builder,
+        metric.source(),
+        window,
+        |query_builder, column| {
+            query_builder.push(" WHERE exchange_id = ANY(");
+            query_builder.push_bind(exchange_ids.clone());
+            query_builder.push(")");
+            if let Some(symbol) = &query.symbol {
+                query_builder.push(" AND token_symbol=");
+                query_builder.push_bind(symbol.clone());
+            }
+            if let Some(exchange_id) = query.exchange_id {
+                query_builder.push(" AND exchange_id=");
+                query_builder.push_bind(exchange_id);
+            }
+            window.push_filter(query_builder, column);
+            filters(query_builder, column);
+        },
+    );
+    query_builder.push(
+        " GROUP BY exchange_id, token_symbol, time_interval ORDER BY exchange_id, token_symbol, time_interval",
+    );
+}
+
+/// Values of every bucket in `window`.
+fn build_anomaly_query(
+    state: &AppState,
+    query: &GetAnomaliesRequest,
+    metric: Metric,
+    window: &TimeWindow,
+) -> QueryBuilder<'static, Postgres> {
+    let mut query_builder = QueryBuilder::new("");
+    push_anomaly_query(&mut query_builder, state, query, metric, window, |_, _| {});
+    query_builder
+}
+
+/// Values of the last `count` non-empty buckets before `from` of each of
+/// `series`, see [`Bucket::push_warm_up_starts`].
+///
+/// [`Bucket::push_warm_up_starts`]: crate::utils::time_window::Bucket::push_warm_up_starts
+fn build_warm_up_query(
+    state: &AppState,
+    query: &GetAnomaliesRequest,
+    metric: Metric,
+    window: &TimeWindow,
+    from: DateTime<Utc>,
+    series: &[(i32, String)],
+    count: usize,
+) -> QueryBuilder<'static, Postgres> {
+    let mut query_builder = QueryBuilder::new("");
+    window.bucket.push_warm_up_starts(
+        &mut query_builder,
+        metric.source().raw_table(),
+        series,
+        from,
+        count,
+    );
+    let before = TimeWindow {
+        from: None,
+        to: Some(from),
+        ..*window
+    };
+    push_anomaly_query(
+        &mut query_builder,
+        state,
+        query,
+        metric,
+        &before,
+        |query_builder, column| {
+            query_builder.push(format!(
+                " AND {} >= (SELECT warm_from FROM starts AS w(w_exchange_id, w_token_symbol, warm_from) \
+                 WHERE w_exchange_id = exchange_id AND w_token_symbol = token_symbol)",
+                column
+            ));
+        },
+    );
+    query_builder
+}
+
+struct Series {
+    exchange_id: i32,
+    symbol: String,
+    timestamps: Vec<DateTime<Utc>>,
+    values: Vec<f64>,
+}
+
+/// Flags the buckets of one series that overlap the requested range. Each
+/// bucket is compared with the `size` buckets with data before it.
+fn series_anomalies(
+    state: &AppState,
+    series: Series,
+    metric: Metric,
+    window: &TimeWindow,
+    size: usize,
+    method: Method,
+    sensitivity: f64,
+) -> Vec<AnomalyPoint> {
+    let (timestamps, values): (Vec<DateTime<Utc>>, Vec<f64>) = match metric {
+        // A bucket still being filled would read as a drop in volume.
+        Metric::Volume => {
+            let now = Utc::now();
+            series
+                .timestamps
+                .into_iter()
+                .zip(series.values)
+                .filter(|(timestamp, _)| *timestamp + window.bucket.duration() <= now)
+                .unzip()
+        }
+        Metric::WalletBalance | Metric::TransferBalance => (
+            series.timestamps.into_iter().skip(1).collect(),
+            series.values.windows(2).map(|w| w[1] - w[0]).collect(),
+        ),
+    };
+    detect(&values, size, method, sensitivity)
+        .into_iter()
+        .filter(|anomaly| {
+            window
+                .from
+                .is_none_or(|from| timestamps[anomaly.index] + window.bucket.duration() > from)
+        })
+        .map(|anomaly| AnomalyPoint {
+            timestamp: timestamps[anomaly.index].timestamp(),
+            exchange_id: series.exchange_id,
+            exchange: state.exchanges.name(series.exchange_id),
+            symbol: series.symbol.clone(),
+            value: values[anomaly.index],
+            score: anomaly.score,
+            zscore: anomaly.zscore,
+            mad_score: anomaly.mad_score,
+            mean: anomaly.baseline.mean,
+            std_dev: anomaly.baseline.std_dev,
+            median: anomaly.baseline.median,
+            mad: anomaly.baseline.mad,
+        })
+        .collect()
+}
+
+/// Groups rows of the anomaly query into series, skipping buckets without a
+/// value.
+fn collect_series(state: &AppState, metric: Metric, rows: &[PgRow]) -> Vec<Series> {
+    let mut series: Vec<Series> = vec![];
+    for row in rows {
+        let exchange_id = row.get::<i32, _>("exchange_id");
+        let symbol = row.get::<String, _>("token_symbol");
+        let value = match (metric, state.exchanges.volume_semantics(exchange_id)) {
+            (Metric::Volume, VolumeSemantics::Rolling24h) => row.get("day_value"),
+            _ => row.get::<Option<f64>, _>("value"),
+        };
+        let Some(value) = value else {
+            continue;
+        };
+        if series
+            .last()
+            .is_none_or(|s| s.exchange_id != exchange_id || s.symbol != symbol)
+        {
+            series.push(Series {
+                exchange_id,
+                symbol,
+                timestamps: vec![],
+                values: vec![],
+            });
+        }
+        if let Some(current) = series.last_mut() {
+            current
+                .timestamps
+                .push(row.get::<DateTime<Utc>, _>("time_interval"));
+            current.values.push(value);
+        }
+    }
+    series
+}
+
+/// Rolling z-score / MAD anomalies per exchange and symbol. The non-empty
+/// buckets before the requested start are fetched as baseline so that the
+/// first ones are scored.
+pub async fn query_anomalies(
+    state: &AppState,
+    query: &GetAnomaliesRequest,
+) -> Result<AnomaliesResponse, ApiError> {
+    let window = TimeWindow::resolve(
+        query.interval.as_deref(),
+        query.from.as_deref(),
+        query.to.as_deref(),
+        query.bucket.as_deref(),
+    )?;
+    let metric = Metric::parse(query.metric.as_deref())?;
+    let method: Method = query.method.as_deref().unwrap_or("mad").parse()?;
+    let size = query.window.unwrap_or(DEFAULT_WINDOW);
+    if !(2..=MAX_WINDOW).contains(&size) {
+        return Err(ApiError::InvalidRequest(format!(
+            "window must be between 2 and {}",
+            MAX_WINDOW
+        )));
+    }
+    let sensitivity = query.sensitivity.unwrap_or(DEFAULT_SENSITIVITY);
+    if !(sensitivity > 0.0 && sensitivity.is_finite()) {
+        return Err(ApiError::InvalidRequest(
+            "sensitivity must be a positive number".to_string(),
+        ));
+    }
+    // The first bucket is fetched whole, so the baseline ends where it starts.
+    let from = window.from.map(|from| window.bucket.floor(from));
+    let rows = build_anomaly_query(state, query, metric, &TimeWindow { from, ..window })
+        .build()
+        .fetch_all(&state.crypto_data_db)
+        .await?;
+    let mut series = collect_series(state, metric, &rows);
+
+    if let (Some(from), false) = (from, series.is_empty()) {
+        let keys: Vec<(i32, String)> = series
+            .iter()
+            .map(|s| (s.exchange_id, s.symbol.clone()))
+            .collect();
+        // One more bucket for balances, whose first change needs a previous value.
+        let count = match metric {
+            Metric::Volume => size,
+            Metric::WalletBalance | Metric::TransferBalance => size + 1,
+        };
+        let rows = build_warm_up_query(state, query, metric, &window, from, &keys, count)
+            .build()
+            .fetch_all(&state.crypto_data_db)
+            .await?;
+        let mut history = collect_series(state, metric, &rows);
+        for current in &mut series {
+            if let Some(mut earlier) = history
+                .iter()
+                .position(|s| s.exchange_id == current.exchange_id && s.symbol == current.symbol)
+                .map(|i| history.swap_remove(i))
+            {
+                earlier.timestamps.append(&mut current.timestamps);
+                earlier.values.append(&mut current.values);
+                current.timestamps = earlier.timestamps;
+                current.values = earlier.values;
+            }
+        }
+    }
+    let mut data: Vec<AnomalyPoint> = series
+        .into_iter()
+        .flat_map(|series| {
+            series_anomalies(state, series, metric, &window, size, method, sensitivity)
+        })
+        .collect();
+    data.sort_by_key(|point| (point.timestamp, point.exchange_id));
+
+    Ok(AnomaliesResponse {
+        symbol: query.symbol.clone(),
+        metric: metric.as_str().to_string(),
+        bucket: window.bucket.as_str().to_string(),
+        from: window.from.map(|t| t.timestamp()),
+        to: window.to.map(|t| t.timestamp()),
+        window: size,
+        sensitivity,
+        method: method.as_str().to_string(),
+        data,
+    })
+}
+
+pub async fn get_anomalies(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(output): Query<OutputFormatRequest>,
+    Query(query): Query<GetAnomaliesRequest>,
+) -> Result<Response, ApiError> {
+    let format = OutputFormat::negotiate(output.format.as_deref(), &headers)?;
+    let mut anomalies = query_anomalies(&state, &query).await?;
+    let data = std::mem::take(&mut anomalies.data);
+    Ok(export_rows(format, envelope(&anomalies), data))
+}
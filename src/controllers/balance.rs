@@ -1,127 +1,913 @@
-use crate::{dto::request::*, utils::errors::ApiError, AppState};
+use crate::{
+    dto::{
+        request::*,
+        response::{
+            BalanceDiffPoint, BalanceDiffResponse, BalanceFlowPoint, BalanceFlowSeriesResponse,
+            BalancePoint, BalanceSeriesResponse, BalanceSnapshotPoint, BalanceSnapshotResponse,
+            LatestBalanceResponse,
+        },
+    },
+    utils::{
+        conversion::Converter,
+        downsample::Downsample,
+        errors::ApiError,
+        exchanges::ExchangeRegistry,
+        export::{envelope, export_rows, stream_rows, vary_accept, OutputFormat},
+        pagination::{with_next_cursor, Page},
+        rollup::{RollupSource, Rollups},
+        symbol::parse_symbols,
+        time_window::{parse_timestamp, TimeWindow},
+    },
+    AppState,
+};
 use axum::{
     extract::{Query, State},
-    response::IntoResponse,
+    http::{header, HeaderMap, HeaderValue},
+    response::{IntoResponse, Response},
     Json,
 };
-use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
 use serde_json::json;
-use sqlx::{QueryBuilder, Row};
-use std::sync::Arc;
-use tracing::info;
-pub async fn get_balance_data(
-    State(state): State<Arc<AppState>>,
-    Query(query): Query<GetBalanceDataRequest>,
-) -> Result<impl IntoResponse, ApiError> {
-    let mut query_builder = QueryBuilder::new("SELECT wallet_balance, transfer_balance, token_symbol, exchange_id, date_trunc('hour', timestamp)");
-
-    let mut start_time = Utc::now();
-    let mut now_time = start_time.clone();
-    match query.interval.as_str() {
-        "1D" => {
-            start_time = start_time - Duration::days(1);
-            query_builder.push(" + INTERVAL '1 minute' * (floor(EXTRACT(MINUTE FROM timestamp) / 5) * 5) AS time_interval");
-        }
-        "7D" => {
-            start_time = start_time - Duration::days(7);
-            query_builder.push(" + INTERVAL '1 minute' * (floor(EXTRACT(MINUTE FROM timestamp) / 5) * 5) AS time_interval");
-        }
-        "1M" => {
-            let naive_date = start_time.naive_utc().date();
-            let year = naive_date.year();
-            let month = naive_date.month();
-            let new_month = if month == 1 { 12 } else { month - 1 };
-            let new_year = if month == 1 { year - 1 } else { year };
-            let month_days = (Utc.ymd(year, month, 1) - Duration::hours(24)).day();
-            let mut new_day = naive_date.day();
-            if new_day > month_days {
-                new_day = month_days;
+use sqlx::{postgres::PgRow, FromRow, Postgres, QueryBuilder, Row};
+use std::{collections::HashMap, sync::Arc};
+
+/// `None` if `converter` has no price for the row's time.
+fn balance_point(
+    state: &AppState,
+    item: &PgRow,
+    time_column: &str,
+    converter: Option<&Converter>,
+) -> Option<BalancePoint> {
+    let exchange_id = item.get::<i32, _>("exchange_id");
+    let timestamp = item.get::<DateTime<Utc>, _>(time_column).timestamp();
+    let rate = match converter {
+        Some(converter) => converter.rate_at(timestamp)?,
+        None => 1.0,
+    };
+    Some(BalancePoint {
+        timestamp,
+        exchange_id,
+        exchange: state.exchanges.name(exchange_id),
+        token: item.get::<String, _>("token_symbol"),
+        wallet_balance: item.get::<f64, _>("wallet_balance") * rate,
+        transfer_balance: item.get::<f64, _>("transfer_balance") * rate,
+    })
+}
+
+fn balance_rows(data: Vec<BalancePoint>) -> Vec<Vec<String>> {
+    data.into_iter()
+        .map(|point| {
+            vec![
+                point.timestamp.to_string(),
+                point.exchange_id.to_string(),
+                point.token,
+                point.wallet_balance.to_string(),
+                point.transfer_balance.to_string(),
+            ]
+        })
+        .collect()
+}
+
+fn balance_window(query: &GetBalanceDataRequest) -> Result<TimeWindow, ApiError> {
+    TimeWindow::resolve(
+        query.interval.as_deref(),
+        query.from.as_deref(),
+        query.to.as_deref(),
+        query.bucket.as_deref(),
+    )
+}
+
+fn balance_downsample(query: &GetBalanceDataRequest) -> Result<Option<Downsample>, ApiError> {
+    Downsample::from_request(query.max_points, query.downsample.as_deref())
+}
+
+fn balance_page(query: &GetBalanceDataRequest) -> Result<Option<Page>, ApiError> {
+    let page = Page::from_request(query.cursor.as_deref(), query.limit)?;
+    if page.is_some() && query.max_points.is_some() {
+        return Err(ApiError::InvalidRequest(
+            "cursor and limit cannot be combined with max_points".to_string(),
+        ));
+    }
+    Ok(page)
+}
+
+/// Aggregate expression that reduces the `wallet` or `transfer` balance of
+/// the rows of a [`RollupSource::Balance`] source to one value per bucket.
+fn balance_agg_expr(agg: &str, balance: &str) -> Result<String, ApiError> {
+    match agg {
+        "last" => Ok(format!(
+            "(array_agg(last_{} ORDER BY last_at DESC))[1]",
+            balance
+        )),
+        "first" => Ok(format!(
+            "(array_agg(first_{} ORDER BY first_at ASC))[1]",
+            balance
+        )),
+        "avg" => Ok(format!(
+            "SUM({}_sum) / SUM(sample_count)::DOUBLE PRECISION",
+            balance
+        )),
+        "min" => Ok(format!("MIN({}_min)", balance)),
+        "max" => Ok(format!("MAX({}_max)", balance)),
+        _ => Err(ApiError::InvalidRequest(format!(
+            "Unknown agg '{}', expected last, first, avg, min or max",
+            agg
+        ))),
+    }
+}
+
+fn balance_agg(query: &GetBalanceDataRequest) -> &str {
+    query.agg.as_deref().unwrap_or("last")
+}
+
+fn build_balance_query(
+    rollups: &Rollups,
+    query: &GetBalanceDataRequest,
+    window: &TimeWindow,
+    page: Option<&Page>,
+) -> Result<QueryBuilder<'static, Postgres>, ApiError> {
+    let agg = balance_agg(query);
+    let mut query_builder = match page {
+        Some(_) => Page::begin(),
+        None => QueryBuilder::new(""),
+    };
+    query_builder.push(format!(
+        "SELECT {} AS wallet_balance, {} AS transfer_balance, token_symbol, exchange_id, ",
+        balance_agg_expr(agg, "wallet")?,
+        balance_agg_expr(agg, "transfer")?
+    ));
+    query_builder.push(window.bucket.sql_expr("ts"));
+    query_builder.push(" AS time_interval FROM ");
+    rollups.push_source(
+        &mut query_builder,
+        RollupSource::Balance,
+        window,
+        |query_builder, column| {
+            query_builder.push(" WHERE token_symbol=");
+            query_builder.push_bind(query.symbol.clone());
+            if let Some(exchange_id) = query.exchange_id {
+                query_builder.push(" AND exchange_id=");
+                query_builder.push_bind(exchange_id);
             }
-            start_time = Utc.ymd(new_year, new_month, new_day).and_hms(0, 0, 0);
-            query_builder.push(" AS time_interval");
-        }
-        "1Y" => {
-            let naive_date = start_time.naive_utc().date();
-
-            let month = if naive_date.month() + 1 == 13 {
-                1
-            } else {
-                naive_date.month()
-            };
-            let year = if month == 1 {
-                naive_date.year()
-            } else {
-                naive_date.year() - 1
-            };
-            let month_days = (Utc.ymd(year, month, 1) - Duration::hours(24)).day();
-            let mut day = naive_date.day();
-            if day > month_days {
-                day = month_days;
+            window.push_filter(query_builder, column);
+            if let Some(page) = page {
+                page.push_filter(query_builder, column);
             }
-            start_time = Utc
-                .ymd(naive_date.year() - 1, naive_date.month(), day)
-                .and_hms(0, 0, 0);
-            query_builder.push(" AS time_interval");
+        },
+    );
+    query_builder.push(" GROUP BY time_interval, exchange_id, token_symbol");
+    match page {
+        Some(page) => page.finish(&mut query_builder),
+        None => {
+            query_builder.push(" ORDER BY time_interval ASC, exchange_id ASC");
         }
-        "All" => {
-            query_builder.push(" AS time_interval");
+    }
+    Ok(query_builder)
+}
+
+/// `None` unless a `unit` other than the token itself was requested.
+async fn balance_converter(
+    state: &AppState,
+    query: &GetBalanceDataRequest,
+    window: &TimeWindow,
+) -> Result<Option<Converter>, ApiError> {
+    match query.unit.as_deref() {
+        Some(unit) if unit != query.symbol => Ok(Some(
+            Converter::load(state, &query.symbol, unit, window).await?,
+        )),
+        _ => Ok(None),
+    }
+}
+
+fn balance_series_meta(
+    query: &GetBalanceDataRequest,
+    window: &TimeWindow,
+    converter: Option<&Converter>,
+) -> BalanceSeriesResponse {
+    BalanceSeriesResponse {
+        symbol: query.symbol.clone(),
+        unit: query.unit.clone().unwrap_or_else(|| query.symbol.clone()),
+        bucket: window.bucket.as_str().to_string(),
+        agg: balance_agg(query).to_string(),
+        from: window.from.map(|t| t.timestamp()),
+        to: window.to.map(|t| t.timestamp()),
+        next_cursor: None,
+        conversion: converter.map(Converter::info),
+        data: vec![],
+    }
+}
+
+pub async fn query_balance_series(
+    state: &AppState,
+    query: &GetBalanceDataRequest,
+) -> Result<BalanceSeriesResponse, ApiError> {
+    let window = balance_window(query)?;
+    let page = balance_page(query)?;
+    let converter = balance_converter(state, query, &window).await?;
+    let mut query_builder = build_balance_query(&state.rollups, query, &window, page.as_ref())?;
+    let query_result = query_builder
+        .build()
+        .fetch_all(&state.crypto_data_db)
+        .await?;
+    let mut series = balance_series_meta(query, &window, converter.as_ref());
+    let query_result = match page {
+        Some(page) => {
+            let (rows, next_cursor) = page.split(query_result);
+            series.next_cursor = next_cursor;
+            rows
         }
-        _ => {
-            return Ok(Json(json!([])));
+        None => query_result,
+    };
+    series.data = query_result
+        .iter()
+        .filter_map(|item| balance_point(state, item, "time_interval", converter.as_ref()))
+        .collect();
+    if let Some(downsample) = balance_downsample(query)? {
+        series.data = downsample.apply(
+            series.data,
+            |point| (point.exchange_id, point.token.clone()),
+            |point| point.timestamp as f64,
+            |point| point.wallet_balance,
+        );
+    }
+    Ok(series)
+}
+
+async fn stream_balance_series(
+    state: Arc<AppState>,
+    query: GetBalanceDataRequest,
+    format: OutputFormat,
+) -> Result<Response, ApiError> {
+    let window = balance_window(&query)?;
+    let converter = balance_converter(&state, &query, &window).await?;
+    let meta = envelope(&balance_series_meta(&query, &window, converter.as_ref()));
+    let mut query_builder = build_balance_query(&state.rollups, &query, &window, None)?;
+    Ok(stream_rows(format, meta, move |mut sink| async move {
+        let mut rows = query_builder.build().fetch(&state.crypto_data_db);
+        while let Some(item) = rows.try_next().await? {
+            if let Some(point) = balance_point(&state, &item, "time_interval", converter.as_ref()) {
+                if !sink.send(point).await {
+                    break;
+                }
+            }
         }
+        Ok(sink)
+    }))
+}
+
+/// Streams the series unless it has to be collected first for downsampling or
+/// paging. `legacy_json` keeps the v1 nested-array JSON shape.
+async fn respond_balance_series(
+    state: Arc<AppState>,
+    query: GetBalanceDataRequest,
+    format: OutputFormat,
+    legacy_json: bool,
+) -> Result<Response, ApiError> {
+    let legacy_json = legacy_json && format == OutputFormat::Json;
+    let collect = balance_downsample(&query)?.is_some() || balance_page(&query)?.is_some();
+    if !legacy_json && !collect {
+        return stream_balance_series(state, query, format).await;
+    }
+    let mut series = query_balance_series(&state, &query).await?;
+    let next_cursor = series.next_cursor.clone();
+    let data = std::mem::take(&mut series.data);
+    let response = if legacy_json {
+        vary_accept(Json(json!(balance_rows(data))).into_response())
+    } else {
+        export_rows(format, envelope(&series), data)
+    };
+    Ok(with_next_cursor(response, next_cursor))
+}
+
+pub async fn get_balance_data(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(output): Query<OutputFormatRequest>,
+    Query(query): Query<GetBalanceDataRequest>,
+) -> Result<Response, ApiError> {
+    let format = OutputFormat::negotiate(output.format.as_deref(), &headers)?;
+    respond_balance_series(state, query, format, true).await
+}
+
+pub async fn get_balance_series(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(output): Query<OutputFormatRequest>,
+    Query(query): Query<GetBalanceDataRequest>,
+) -> Result<Response, ApiError> {
+    let format = OutputFormat::negotiate(output.format.as_deref(), &headers)?;
+    respond_balance_series(state, query, format, false).await
+}
+
+/// SQL expression for the balance a flow query is computed on.
+fn flow_balance_expr(balance: &str) -> Result<&'static str, ApiError> {
+    match balance {
+        "wallet" => Ok("wallet_balance"),
+        "transfer" => Ok("transfer_balance"),
+        "total" => Ok("wallet_balance + transfer_balance"),
+        _ => Err(ApiError::InvalidRequest(format!(
+            "Unknown balance '{}', expected wallet, transfer or total",
+            balance
+        ))),
     }
-    query_builder.push(" FROM balance_data WHERE token_symbol=");
-    query_builder.push_bind(query.symbol);
+}
+
+fn push_flow_source(
+    query_builder: &mut QueryBuilder<'_, Postgres>,
+    query: &GetBalanceFlowsRequest,
+    select: &str,
+) {
+    query_builder.push(select);
+    query_builder.push(
+        " exchange_id, token_symbol, timestamp, wallet_balance, transfer_balance FROM balance_data WHERE token_symbol=",
+    );
+    query_builder.push_bind(query.symbol.clone());
     if let Some(exchange_id) = query.exchange_id {
         query_builder.push(" AND exchange_id=");
         query_builder.push_bind(exchange_id);
     }
-    if start_time != now_time {
-        query_builder.push(" AND timestamp>=");
-        query_builder.push_bind(start_time);
+}
+
+/// Flows are the differences between consecutive snapshots of each exchange
+/// and token. The last snapshot before the window is included so the first
+/// change inside the window is counted too.
+fn build_flow_query(
+    query: &GetBalanceFlowsRequest,
+    window: &TimeWindow,
+    balance_expr: &str,
+    sum_exchanges: bool,
+) -> QueryBuilder<'static, Postgres> {
+    let mut query_builder = QueryBuilder::new(if sum_exchanges {
+        "SELECT NULL::INTEGER AS exchange_id, "
+    } else {
+        "SELECT exchange_id, "
+    });
+    query_builder.push(
+        "token_symbol, time_interval, SUM(GREATEST(delta, 0)) AS inflow, SUM(GREATEST(-delta, 0)) AS outflow, SUM(delta) AS net_flow FROM (SELECT exchange_id, token_symbol, ",
+    );
+    query_builder.push(window.bucket.sql_expr("timestamp"));
+    query_builder.push(format!(
+        " AS time_interval, ({0}) - LAG({0}) OVER (PARTITION BY exchange_id, token_symbol ORDER BY timestamp) AS delta FROM (",
+        balance_expr
+    ));
+    push_flow_source(&mut query_builder, query, "SELECT");
+    window.push_filter(&mut query_builder, "timestamp");
+    if let Some(from) = window.from {
+        query_builder.push(" UNION ALL (");
+        push_flow_source(
+            &mut query_builder,
+            query,
+            "SELECT DISTINCT ON (exchange_id, token_symbol)",
+        );
+        query_builder.push(" AND timestamp<");
+        query_builder.push_bind(from);
+        query_builder.push(" ORDER BY exchange_id, token_symbol, timestamp DESC)");
+    }
+    query_builder.push(") s) d WHERE delta IS NOT NULL GROUP BY time_interval, token_symbol");
+    if sum_exchanges {
+        query_builder.push(" ORDER BY time_interval ASC");
+    } else {
+        query_builder.push(", exchange_id ORDER BY time_interval ASC, exchange_id ASC");
     }
     query_builder
-        .push(" GROUP BY time_interval, exchange_id, token_symbol, wallet_balance, transfer_balance ORDER BY time_interval ASC");
-    let sql_query = query_builder.build();
-    let query_result = sql_query.fetch_all(&state.crypto_data_db).await?;
-    let mut res: Vec<Vec<String>> = vec![];
-    for item in query_result {
-        let timestamp = item.get::<DateTime<Utc>, _>("time_interval");
-        let exchange_id = item.get::<i32, _>("exchange_id");
+}
+
+pub async fn query_balance_flows(
+    state: &AppState,
+    query: &GetBalanceFlowsRequest,
+) -> Result<BalanceFlowSeriesResponse, ApiError> {
+    let window = TimeWindow::resolve(
+        query.interval.as_deref(),
+        query.from.as_deref(),
+        query.to.as_deref(),
+        query.bucket.as_deref(),
+    )?;
+    let balance = query.balance.as_deref().unwrap_or("total");
+    let balance_expr = flow_balance_expr(balance)?;
+    let sum_exchanges = query.sum_exchanges.unwrap_or(false);
+    let converter = match query.unit.as_deref() {
+        Some(unit) if unit != query.symbol => {
+            Some(Converter::load(state, &query.symbol, unit, &window).await?)
+        }
+        _ => None,
+    };
+    let mut query_builder = build_flow_query(query, &window, balance_expr, sum_exchanges);
+    let query_result = query_builder
+        .build()
+        .fetch_all(&state.crypto_data_db)
+        .await?;
+
+    let mut cumulative: HashMap<(Option<i32>, String), f64> = HashMap::new();
+    let mut data = vec![];
+    for item in &query_result {
+        let timestamp = item.get::<DateTime<Utc>, _>("time_interval").timestamp();
+        let rate = match &converter {
+            Some(converter) => match converter.rate_at(timestamp) {
+                Some(rate) => rate,
+                None => continue,
+            },
+            None => 1.0,
+        };
+        let exchange_id = item.get::<Option<i32>, _>("exchange_id");
         let token = item.get::<String, _>("token_symbol");
-        let wallet_balance = item.get::<f64, _>("wallet_balance");
-        let transfer_balance = item.get::<f64, _>("transfer_balance");
-        let mut res_item: Vec<String> = vec![];
-        res_item.push(timestamp.timestamp().to_string());
-        res_item.push(exchange_id.to_string());
-        res_item.push(token);
-        res_item.push(wallet_balance.to_string());
-        res_item.push(transfer_balance.to_string());
-        res.push(res_item);
-    }
-    return Ok(Json(json!(res)));
+        let net_flow = item.get::<f64, _>("net_flow") * rate;
+        let cumulative_net_flow = cumulative.entry((exchange_id, token.clone())).or_default();
+        *cumulative_net_flow += net_flow;
+        data.push(BalanceFlowPoint {
+            timestamp,
+            exchange_id,
+            exchange: exchange_id.and_then(|exchange_id| state.exchanges.name(exchange_id)),
+            token,
+            inflow: item.get::<f64, _>("inflow") * rate,
+            outflow: item.get::<f64, _>("outflow") * rate,
+            net_flow,
+            cumulative_net_flow: *cumulative_net_flow,
+        });
+    }
+    Ok(BalanceFlowSeriesResponse {
+        symbol: query.symbol.clone(),
+        unit: query.unit.clone().unwrap_or_else(|| query.symbol.clone()),
+        balance: balance.to_string(),
+        bucket: window.bucket.as_str().to_string(),
+        from: window.from.map(|t| t.timestamp()),
+        to: window.to.map(|t| t.timestamp()),
+        conversion: converter.map(|converter| converter.info()),
+        data,
+    })
 }
-pub async fn get_latest_balance_data(
+
+pub async fn get_balance_flows(
     State(state): State<Arc<AppState>>,
-    Query(query): Query<GetLatestBalanceDataRequest>,
-) -> Result<impl IntoResponse, ApiError> {
+    headers: HeaderMap,
+    Query(output): Query<OutputFormatRequest>,
+    Query(query): Query<GetBalanceFlowsRequest>,
+) -> Result<Response, ApiError> {
+    let format = OutputFormat::negotiate(output.format.as_deref(), &headers)?;
+    let mut flows = query_balance_flows(&state, &query).await?;
+    let data = std::mem::take(&mut flows.data);
+    Ok(export_rows(format, envelope(&flows), data))
+}
+
+pub async fn query_latest_balance(
+    state: &AppState,
+    query: &GetLatestBalanceDataRequest,
+) -> Result<LatestBalanceResponse, ApiError> {
     let mut query_builder = QueryBuilder::new(format!("SELECT * FROM balance_data WHERE exchange_id = {} AND timestamp = ( SELECT MAX(timestamp) FROM balance_data WHERE exchange_id = {} )", query.exchange_id, query.exchange_id));
     let sql_query = query_builder.build();
     let query_result = sql_query.fetch_all(&state.crypto_data_db).await?;
-    let mut res: Vec<Vec<String>> = vec![];
-    for item in query_result {
-        let timestamp = item.get::<DateTime<Utc>, _>("timestamp");
-        let exchange_id = item.get::<i32, _>("exchange_id");
-        let token = item.get::<String, _>("token_symbol");
-        let wallet_balance = item.get::<f64, _>("wallet_balance");
-        let transfer_balance = item.get::<f64, _>("transfer_balance");
-        let mut res_item: Vec<String> = vec![];
-        res_item.push(timestamp.timestamp().to_string());
-        res_item.push(exchange_id.to_string());
-        res_item.push(token);
-        res_item.push(wallet_balance.to_string());
-        res_item.push(transfer_balance.to_string());
-        res.push(res_item);
-    }
-    return Ok(Json(json!(res)));
+    let data: Vec<BalancePoint> = query_result
+        .iter()
+        .filter_map(|item| balance_point(state, item, "timestamp", None))
+        .collect();
+    let exchange_id = query.exchange_id as i32;
+    Ok(LatestBalanceResponse {
+        exchange_id,
+        exchange: state.exchanges.name(exchange_id),
+        timestamp: data.first().map(|point| point.timestamp),
+        data,
+    })
+}
+
+pub async fn get_latest_balance_data(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(output): Query<OutputFormatRequest>,
+    Query(query): Query<GetLatestBalanceDataRequest>,
+) -> Result<Response, ApiError> {
+    let format = OutputFormat::negotiate(output.format.as_deref(), &headers)?;
+    let latest = query_latest_balance(&state, &query).await?;
+    if format != OutputFormat::Json {
+        return Ok(export_rows(format, None, latest.data));
+    }
+    Ok(vary_accept(
+        Json(json!(balance_rows(latest.data))).into_response(),
+    ))
+}
+
+pub async fn get_latest_balance_summary(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(output): Query<OutputFormatRequest>,
+    Query(query): Query<GetLatestBalanceDataRequest>,
+) -> Result<Response, ApiError> {
+    let format = OutputFormat::negotiate(output.format.as_deref(), &headers)?;
+    let mut latest = query_latest_balance(&state, &query).await?;
+    let data = std::mem::take(&mut latest.data);
+    Ok(export_rows(format, envelope(&latest), data))
+}
+
+const MAX_SNAPSHOT_SYMBOLS: usize = 100;
+
+/// Last row at or before `at` of every enabled exchange and token matching the
+/// request. The (exchange, token) keys come from `symbols`, or otherwise from a
+/// skip scan of the exchange/token index, and each key then reads one row with
+/// `ORDER BY timestamp DESC LIMIT 1`. History before that row is never read.
+fn push_snapshot(
+    query_builder: &mut QueryBuilder<'static, Postgres>,
+    exchange_ids: Vec<i32>,
+    symbols: Option<&[String]>,
+    at: DateTime<Utc>,
+) {
+    query_builder.push("WITH RECURSIVE snapshot_keys AS (");
+    match symbols {
+        Some(symbols) => {
+            query_builder
+                .push("SELECT e.exchange_id, s.token_symbol::VARCHAR AS token_symbol FROM unnest(");
+            query_builder.push_bind(exchange_ids);
+            query_builder.push(") AS e(exchange_id) CROSS JOIN unnest(");
+            query_builder.push_bind(symbols.to_vec());
+            query_builder.push(") AS s(token_symbol)");
+        }
+        None => {
+            query_builder.push(
+                "SELECT e.exchange_id, (SELECT token_symbol FROM balance_data \
+                 WHERE exchange_id = e.exchange_id ORDER BY token_symbol LIMIT 1) AS token_symbol \
+                 FROM unnest(",
+            );
+            query_builder.push_bind(exchange_ids);
+            query_builder.push(
+                ") AS e(exchange_id) \
+                 UNION ALL \
+                 SELECT k.exchange_id, (SELECT token_symbol FROM balance_data \
+                 WHERE exchange_id = k.exchange_id AND token_symbol > k.token_symbol \
+                 ORDER BY token_symbol LIMIT 1) \
+                 FROM snapshot_keys k WHERE k.token_symbol IS NOT NULL",
+            );
+        }
+    }
+    query_builder.push(
+        ") SELECT k.exchange_id, k.token_symbol, b.wallet_balance, b.transfer_balance, b.timestamp \
+         FROM snapshot_keys k CROSS JOIN LATERAL ( \
+         SELECT wallet_balance, transfer_balance, timestamp FROM balance_data \
+         WHERE exchange_id = k.exchange_id AND token_symbol = k.token_symbol AND timestamp<=",
+    );
+    query_builder.push_bind(at);
+    query_builder.push(
+        " ORDER BY timestamp DESC LIMIT 1) b \
+         WHERE k.token_symbol IS NOT NULL ORDER BY k.exchange_id, k.token_symbol",
+    );
+}
+
+/// Snapshots at `from` and `to` side by side, one row per key of the `to`
+/// snapshot. A key with rows at or before `from` also has rows before `to`,
+/// so only keys first reported in between lack `from_` values.
+fn build_diff_query(
+    exchange_ids: Vec<i32>,
+    symbols: Option<&[String]>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> QueryBuilder<'static, Postgres> {
+    let mut query_builder = QueryBuilder::new("WITH snapshot_to AS (");
+    push_snapshot(&mut query_builder, exchange_ids.clone(), symbols, to);
+    query_builder.push("), snapshot_from AS (");
+    push_snapshot(&mut query_builder, exchange_ids, symbols, from);
+    query_builder.push(
+        ") SELECT t.exchange_id, t.token_symbol, \
+         f.wallet_balance AS from_wallet_balance, t.wallet_balance AS to_wallet_balance, \
+         f.transfer_balance AS from_transfer_balance, t.transfer_balance AS to_transfer_balance, \
+         f.timestamp AS from_timestamp, t.timestamp AS to_timestamp \
+         FROM snapshot_to t LEFT JOIN snapshot_from f \
+         ON f.exchange_id = t.exchange_id AND f.token_symbol = t.token_symbol \
+         ORDER BY t.exchange_id, t.token_symbol",
+    );
+    query_builder
+}
+
+/// Enabled exchanges, narrowed to the requested one.
+fn snapshot_exchange_ids(
+    exchanges: &ExchangeRegistry,
+    query: &GetBalanceSnapshotRequest,
+) -> Vec<i32> {
+    exchanges
+        .enabled()
+        .map(|exchange| exchange.id)
+        .filter(|id| {
+            query
+                .exchange_id
+                .is_none_or(|exchange_id| exchange_id == *id as i64)
+        })
+        .collect()
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct SnapshotRow {
+    exchange_id: i32,
+    token_symbol: String,
+    wallet_balance: f64,
+    transfer_balance: f64,
+    timestamp: DateTime<Utc>,
+}
+
+fn snapshot_point(
+    exchanges: &ExchangeRegistry,
+    row: SnapshotRow,
+    at: DateTime<Utc>,
+) -> BalanceSnapshotPoint {
+    BalanceSnapshotPoint {
+        exchange_id: row.exchange_id,
+        exchange: exchanges.name(row.exchange_id),
+        token: row.token_symbol,
+        wallet_balance: row.wallet_balance,
+        transfer_balance: row.transfer_balance,
+        timestamp: row.timestamp.timestamp(),
+        staleness_secs: (at - row.timestamp).num_seconds(),
+    }
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct DiffRow {
+    exchange_id: i32,
+    token_symbol: String,
+    from_wallet_balance: Option<f64>,
+    to_wallet_balance: f64,
+    from_transfer_balance: Option<f64>,
+    to_transfer_balance: f64,
+    from_timestamp: Option<DateTime<Utc>>,
+    to_timestamp: DateTime<Utc>,
+}
+
+fn diff_point(
+    exchanges: &ExchangeRegistry,
+    row: DiffRow,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> BalanceDiffPoint {
+    BalanceDiffPoint {
+        exchange_id: row.exchange_id,
+        exchange: exchanges.name(row.exchange_id),
+        token: row.token_symbol,
+        from_wallet_balance: row.from_wallet_balance,
+        to_wallet_balance: row.to_wallet_balance,
+        wallet_change: row
+            .from_wallet_balance
+            .map(|from| row.to_wallet_balance - from),
+        from_transfer_balance: row.from_transfer_balance,
+        to_transfer_balance: row.to_transfer_balance,
+        transfer_change: row
+            .from_transfer_balance
+            .map(|from| row.to_transfer_balance - from),
+        from_timestamp: row.from_timestamp.map(|timestamp| timestamp.timestamp()),
+        to_timestamp: row.to_timestamp.timestamp(),
+        from_staleness_secs: row
+            .from_timestamp
+            .map(|timestamp| (from - timestamp).num_seconds()),
+        to_staleness_secs: (to - row.to_timestamp).num_seconds(),
+    }
+}
+
+fn snapshot_params(
+    query: &GetBalanceSnapshotRequest,
+) -> Result<(DateTime<Utc>, Option<Vec<String>>), ApiError> {
+    let at = query
+        .at
+        .as_deref()
+        .map(parse_timestamp)
+        .transpose()?
+        .unwrap_or_else(Utc::now);
+    let symbols = query
+        .symbols
+        .as_deref()
+        .map(|symbols| parse_symbols(symbols, MAX_SNAPSHOT_SYMBOLS))
+        .transpose()?;
+    Ok((at, symbols))
+}
+
+/// Last known balance of every exchange and token at `at`, with how long
+/// before `at` each was reported.
+pub async fn query_balance_snapshot(
+    state: &AppState,
+    query: &GetBalanceSnapshotRequest,
+) -> Result<BalanceSnapshotResponse, ApiError> {
+    let (at, symbols) = snapshot_params(query)?;
+    let mut query_builder = QueryBuilder::new("");
+    push_snapshot(
+        &mut query_builder,
+        snapshot_exchange_ids(&state.exchanges, query),
+        symbols.as_deref(),
+        at,
+    );
+    let data = query_builder
+        .build()
+        .fetch_all(&state.crypto_data_db)
+        .await?
+        .iter()
+        .map(|item| {
+            Ok(snapshot_point(
+                &state.exchanges,
+                SnapshotRow::from_row(item)?,
+                at,
+            ))
+        })
+        .collect::<Result<_, sqlx::Error>>()?;
+    Ok(BalanceSnapshotResponse {
+        at: at.timestamp(),
+        exchange_id: query.exchange_id,
+        symbols,
+        data,
+    })
+}
+
+/// Snapshots at `compare_to` and `at` side by side, in a single query. Tokens
+/// first reported in between have no `from_` values.
+pub async fn query_balance_diff(
+    state: &AppState,
+    query: &GetBalanceSnapshotRequest,
+    compare_to: &str,
+) -> Result<BalanceDiffResponse, ApiError> {
+    let (to, symbols) = snapshot_params(query)?;
+    let from = parse_timestamp(compare_to)?;
+    if from >= to {
+        return Err(ApiError::InvalidRequest(
+            "compare_to must be before at".to_string(),
+        ));
+    }
+    let mut query_builder = build_diff_query(
+        snapshot_exchange_ids(&state.exchanges, query),
+        symbols.as_deref(),
+        from,
+        to,
+    );
+    let data = query_builder
+        .build()
+        .fetch_all(&state.crypto_data_db)
+        .await?
+        .iter()
+        .map(|item| {
+            Ok(diff_point(
+                &state.exchanges,
+                DiffRow::from_row(item)?,
+                from,
+                to,
+            ))
+        })
+        .collect::<Result<_, sqlx::Error>>()?;
+    Ok(BalanceDiffResponse {
+        from: from.timestamp(),
+        to: to.timestamp(),
+        exchange_id: query.exchange_id,
+        symbols,
+        data,
+    })
+}
+
+pub async fn get_balance_snapshot(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(output): Query<OutputFormatRequest>,
+    Query(query): Query<GetBalanceSnapshotRequest>,
+) -> Result<Response, ApiError> {
+    let format = OutputFormat::negotiate(output.format.as_deref(), &headers)?;
+    let mut response = match query.compare_to.as_deref() {
+        Some(compare_to) => {
+            let mut diff = query_balance_diff(&state, &query, compare_to).await?;
+            let data = std::mem::take(&mut diff.data);
+            export_rows(format, envelope(&diff), data)
+        }
+        None => {
+            let mut snapshot = query_balance_snapshot(&state, &query).await?;
+            let data = std::mem::take(&mut snapshot.data);
+            export_rows(format, envelope(&snapshot), data)
+        }
+    };
+    // Staleness is measured from the request time, so a snapshot of "now"
+    // is out of date as soon as it is sent.
+    if query.at.is_none() {
+        response
+            .headers_mut()
+            .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::exchanges::Exchange;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(secs, 0).unwrap()
+    }
+
+    fn registry() -> ExchangeRegistry {
+        let exchange = |id, enabled| Exchange {
+            id,
+            name: format!("Exchange {}", id),
+            slug: format!("exchange-{}", id),
+            volume_semantics: Default::default(),
+            quote_convention: Default::default(),
+            enabled,
+        };
+        ExchangeRegistry::new(vec![
+            exchange(1, true),
+            exchange(2, false),
+            exchange(3, true),
+        ])
+    }
+
+    fn snapshot_request(exchange_id: Option<i64>) -> GetBalanceSnapshotRequest {
+        GetBalanceSnapshotRequest {
+            at: None,
+            compare_to: None,
+            exchange_id,
+            symbols: None,
+        }
+    }
+
+    #[test]
+    fn snapshot_keys_come_from_symbols_or_a_skip_scan() {
+        let symbols = vec!["BTC".to_string(), "ETH".to_string()];
+        let mut query_builder = QueryBuilder::new("");
+        push_snapshot(&mut query_builder, vec![1, 3], Some(&symbols), at(1000));
+        let sql = query_builder.sql();
+        assert!(sql.contains("FROM unnest($1) AS e(exchange_id) CROSS JOIN unnest($2)"));
+        assert!(!sql.contains("token_symbol > k.token_symbol"));
+        assert!(sql.contains("timestamp<=$3 ORDER BY timestamp DESC LIMIT 1"));
+
+        let mut query_builder = QueryBuilder::new("");
+        push_snapshot(&mut query_builder, vec![1, 3], None, at(1000));
+        let sql = query_builder.sql();
+        assert!(!sql.contains("CROSS JOIN unnest"));
+        assert!(sql.contains("token_symbol > k.token_symbol"));
+        assert!(sql.contains("timestamp<=$2 ORDER BY timestamp DESC LIMIT 1"));
+    }
+
+    #[test]
+    fn snapshots_cover_enabled_exchanges() {
+        let exchanges = registry();
+        assert_eq!(
+            snapshot_exchange_ids(&exchanges, &snapshot_request(None)),
+            vec![1, 3]
+        );
+        assert_eq!(
+            snapshot_exchange_ids(&exchanges, &snapshot_request(Some(3))),
+            vec![3]
+        );
+        // A disabled or unknown exchange yields an empty snapshot.
+        assert!(snapshot_exchange_ids(&exchanges, &snapshot_request(Some(2))).is_empty());
+        assert!(snapshot_exchange_ids(&exchanges, &snapshot_request(Some(9))).is_empty());
+    }
+
+    #[test]
+    fn snapshot_staleness_is_measured_from_at() {
+        let point = snapshot_point(
+            &registry(),
+            SnapshotRow {
+                exchange_id: 1,
+                token_symbol: "BTC".to_string(),
+                wallet_balance: 2.0,
+                transfer_balance: 0.5,
+                timestamp: at(1000),
+            },
+            at(1090),
+        );
+        assert_eq!(point.exchange.as_deref(), Some("Exchange 1"));
+        assert_eq!(point.timestamp, 1000);
+        assert_eq!(point.staleness_secs, 90);
+    }
+
+    #[test]
+    fn diff_joins_the_from_snapshot_onto_the_to_snapshot() {
+        let sql = build_diff_query(vec![1], None, at(1000), at(2000))
+            .sql()
+            .to_string();
+        assert!(sql.starts_with("WITH snapshot_to AS (WITH RECURSIVE snapshot_keys AS ("));
+        assert!(sql.contains("), snapshot_from AS (WITH RECURSIVE snapshot_keys AS ("));
+        // `to` is bound in the first snapshot and `from` in the second.
+        assert!(sql.contains("timestamp<=$2 ORDER BY"));
+        assert!(sql.contains("timestamp<=$4 ORDER BY"));
+        assert!(sql.contains("FROM snapshot_to t LEFT JOIN snapshot_from f"));
+    }
+
+    #[test]
+    fn diff_of_keys_present_at_one_or_both_times() {
+        let exchanges = registry();
+        let row = |from: Option<(f64, i64)>| DiffRow {
+            exchange_id: 3,
+            token_symbol: "ETH".to_string(),
+            from_wallet_balance: from.map(|(balance, _)| balance),
+            to_wallet_balance: 7.0,
+            from_transfer_balance: from.map(|(balance, _)| balance / 2.0),
+            to_transfer_balance: 1.0,
+            from_timestamp: from.map(|(_, timestamp)| at(timestamp)),
+            to_timestamp: at(1950),
+        };
+
+        let both = diff_point(&exchanges, row(Some((10.0, 900))), at(1000), at(2000));
+        assert_eq!(both.wallet_change, Some(-3.0));
+        assert_eq!(both.transfer_change, Some(-4.0));
+        assert_eq!(both.from_timestamp, Some(900));
+        assert_eq!(both.from_staleness_secs, Some(100));
+        assert_eq!(both.to_staleness_secs, 50);
+
+        // First reported between the two times.
+        let new = diff_point(&exchanges, row(None), at(1000), at(2000));
+        assert_eq!(new.from_wallet_balance, None);
+        assert_eq!(new.to_wallet_balance, 7.0);
+        assert_eq!(new.wallet_change, None);
+        assert_eq!(new.transfer_change, None);
+        assert_eq!(new.from_timestamp, None);
+        assert_eq!(new.from_staleness_secs, None);
+        assert_eq!(new.to_timestamp, 1950);
+    }
 }
@@ -0,0 +1,159 @@
+use crate::{
+    controllers::{
+        balance::query_balance_series,
+        candles::query_candles,
+        summary::query_summary,
+        volume::{query_volume_series, query_volume_shares},
+    },
+    dto::{
+        request::{
+            BatchQueryRequest, BatchRequest, GetBalanceDataRequest, GetCandlesRequest,
+            GetSummaryRequest, GetVolumeDataRequest,
+        },
+        response::{BatchResponse, BatchResult},
+    },
+    utils::errors::ApiError,
+    AppState,
+};
+use axum::{
+    extract::{Json, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use futures::{stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::{collections::HashSet, sync::Arc};
+
+const MAX_QUERIES: usize = 50;
+
+fn validate(batch: &BatchRequest) -> Result<(), ApiError> {
+    if batch.queries.is_empty() || batch.queries.len() > MAX_QUERIES {
+        return Err(ApiError::InvalidRequest(format!(
+            "Between 1 and {} queries are required",
+            MAX_QUERIES
+        )));
+    }
+    let mut ids = HashSet::new();
+    for query in &batch.queries {
+        if query.id.trim().is_empty() {
+            return Err(ApiError::InvalidRequest(
+                "Every query needs an id".to_string(),
+            ));
+        }
+        if !ids.insert(query.id.as_str()) {
+            return Err(ApiError::InvalidRequest(format!(
+                "Duplicate query id '{}'",
+                query.id
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn params<T: DeserializeOwned>(query: BatchQueryRequest) -> Result<T, ApiError> {
+    serde_json::from_value(Value::Object(query.params))
+        .map_err(|e| ApiError::InvalidRequest(format!("Invalid params: {}", e)))
+}
+
+/// Runs one query through the same code as its endpoint and returns the JSON
+/// body that endpoint would respond with.
+async fn run_query(state: &AppState, query: BatchQueryRequest) -> Result<Value, ApiError> {
+    match query.kind.as_str() {
+        "volume" => {
+            let query: GetVolumeDataRequest = params(query)?;
+            if query.aggregate.unwrap_or(false) {
+                Ok(json!(query_volume_shares(state, &query).await?))
+            } else {
+                Ok(json!(query_volume_series(state, &query).await?))
+            }
+        }
+        "balance" => {
+            let query: GetBalanceDataRequest = params(query)?;
+            Ok(json!(query_balance_series(state, &query).await?))
+        }
+        "candles" => {
+            let query: GetCandlesRequest = params(query)?;
+            Ok(json!(query_candles(state, &query).await?))
+        }
+        "summary" => {
+            let query: GetSummaryRequest = params(query)?;
+            Ok(json!(query_summary(state, &query).await?))
+        }
+        other => Err(ApiError::InvalidRequest(format!(
+            "Unknown query type '{}', expected volume, balance, candles or summary",
+            other
+        ))),
+    }
+}
+
+fn batch_result(result: Result<Value, ApiError>) -> BatchResult {
+    match result {
+        Ok(data) => BatchResult {
+            status: StatusCode::OK.as_u16(),
+            data: Some(data),
+            error: None,
+        },
+        Err(e) => {
+            let (status, message) = e.into_status();
+            BatchResult {
+                status: status.as_u16(),
+                data: None,
+                error: Some(message),
+            }
+        }
+    }
+}
+
+/// Runs the queries of a batch concurrently, within the process-wide limit of
+/// `BATCH_CONCURRENCY` queries across all batches. A failing query only fails
+/// its own result.
+pub async fn run_batch(
+    State(state): State<Arc<AppState>>,
+    Json(batch): Json<BatchRequest>,
+) -> Result<Response, ApiError> {
+    validate(&batch)?;
+    let state = state.as_ref();
+    let results = stream::iter(batch.queries)
+        .map(|query| async move {
+            let id = query.id.clone();
+            // The semaphore is never closed.
+            let _permit = state.batch_permits.acquire().await.ok();
+            (id, batch_result(run_query(state, query).await))
+        })
+        .buffer_unordered(MAX_QUERIES)
+        .collect()
+        .await;
+    Ok(Json(BatchResponse { results }).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn batch(ids: &[&str]) -> BatchRequest {
+        BatchRequest {
+            queries: ids
+                .iter()
+                .map(|id| BatchQueryRequest {
+                    id: id.to_string(),
+                    kind: "summary".to_string(),
+                    params: Default::default(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn validates_query_ids_and_count() {
+        assert!(validate(&batch(&["a", "b"])).is_ok());
+        assert!(validate(&batch(&["a", "b", "a"])).is_err());
+        assert!(validate(&batch(&["a", " "])).is_err());
+        assert!(validate(&batch(&[""])).is_err());
+        assert!(validate(&batch(&[])).is_err());
+        let ids: Vec<String> = (0..=MAX_QUERIES).map(|i| i.to_string()).collect();
+        let ids: Vec<&str> = ids.iter().map(String::as_str).collect();
+        assert!(validate(&batch(&ids[..MAX_QUERIES])).is_ok());
+        assert!(validate(&batch(&ids)).is_err());
+    }
+}
@@ -0,0 +1,313 @@
+use crate::{
+    dto::{
+        request::*,
+        response::{CandlePoint, CandleSeriesResponse},
+    },
+    utils::{
+        errors::ApiError,
+        exchanges::ExchangeRegistry,
+        export::{envelope, export_rows, stream_rows, vary_accept, OutputFormat},
+        pagination::{with_next_cursor, Page},
+        symbol::split_symbol,
+        time_window::TimeWindow,
+    },
+    AppState,
+};
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use serde_json::json;
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use std::sync::Arc;
+
+fn candle_window(query: &GetCandlesRequest) -> Result<TimeWindow, ApiError> {
+    TimeWindow::resolve(
+        query.interval.as_deref(),
+        query.from.as_deref(),
+        query.to.as_deref(),
+        query.bucket.as_deref(),
+    )
+}
+
+fn candle_page(query: &GetCandlesRequest) -> Result<Option<Page>, ApiError> {
+    Page::from_request(query.cursor.as_deref(), query.limit)
+}
+
+fn build_candle_query(
+    query: &GetCandlesRequest,
+    window: &TimeWindow,
+    page: Option<&Page>,
+) -> QueryBuilder<'static, Postgres> {
+    let mut query_builder = match page {
+        Some(_) => Page::begin(),
+        None => QueryBuilder::new(""),
+    };
+    // Open and close are the first and last rows of each bucket by timestamp.
+    query_builder.push(
+        "SELECT exchange_id, time_interval, (array_agg(price ORDER BY timestamp ASC))[1] AS open, MAX(price) AS high, MIN(price) AS low, (array_agg(price ORDER BY timestamp DESC))[1] AS close, SUM(total_volume) AS volume, SUM(total_volume / NULLIF(price, 0)) AS volume_over_price, COUNT(*) AS trade_rows FROM (SELECT exchange_id, price, total_volume, timestamp, ",
+    );
+    query_builder.push(window.bucket.sql_expr("timestamp"));
+    query_builder.push(" AS time_interval FROM volume_data WHERE token_symbol=");
+    query_builder.push_bind(query.symbol.clone());
+    if let Some(exchange_id) = query.exchange_id {
+        query_builder.push(" AND exchange_id=");
+        query_builder.push_bind(exchange_id);
+    }
+    window.push_filter(&mut query_builder, "timestamp");
+    if let Some(page) = page {
+        page.push_filter(&mut query_builder, "timestamp");
+    }
+    query_builder.push(") v GROUP BY time_interval, exchange_id");
+    match page {
+        Some(page) => page.finish(&mut query_builder),
+        None => {
+            query_builder.push(" ORDER BY time_interval ASC, exchange_id ASC");
+        }
+    }
+    query_builder
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct CandleRow {
+    exchange_id: i32,
+    time_interval: DateTime<Utc>,
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+    /// `total_volume` summed as reported, and divided by price, see
+    /// [`ExchangeRegistry::base_volume`].
+    volume: f64,
+    volume_over_price: Option<f64>,
+    trade_rows: i64,
+}
+
+fn candle_point(exchanges: &ExchangeRegistry, row: CandleRow) -> CandlePoint {
+    CandlePoint {
+        timestamp: row.time_interval.timestamp(),
+        exchange_id: row.exchange_id,
+        exchange: exchanges.name(row.exchange_id),
+        open: row.open,
+        high: row.high,
+        low: row.low,
+        close: row.close,
+        volume: exchanges.base_volume(row.exchange_id, row.volume, row.volume_over_price),
+        trade_rows: row.trade_rows,
+    }
+}
+
+/// Prices are in the quote currency and volumes in the base currency.
+fn candle_series_meta(query: &GetCandlesRequest, window: &TimeWindow) -> CandleSeriesResponse {
+    let (base, quote) = split_symbol(&query.symbol).unwrap_or((&query.symbol, ""));
+    CandleSeriesResponse {
+        symbol: query.symbol.clone(),
+        price_unit: quote.to_string(),
+        volume_unit: base.to_string(),
+        bucket: window.bucket.as_str().to_string(),
+        from: window.from.map(|t| t.timestamp()),
+        to: window.to.map(|t| t.timestamp()),
+        next_cursor: None,
+        data: vec![],
+    }
+}
+
+pub async fn query_candles(
+    state: &AppState,
+    query: &GetCandlesRequest,
+) -> Result<CandleSeriesResponse, ApiError> {
+    let window = candle_window(query)?;
+    let page = candle_page(query)?;
+    let mut query_builder = build_candle_query(query, &window, page.as_ref());
+    let query_result = query_builder
+        .build()
+        .fetch_all(&state.crypto_data_db)
+        .await?;
+    let mut series = candle_series_meta(query, &window);
+    let query_result = match page {
+        Some(page) => {
+            let (rows, next_cursor) = page.split(query_result);
+            series.next_cursor = next_cursor;
+            rows
+        }
+        None => query_result,
+    };
+    series.data = query_result
+        .iter()
+        .map(|item| Ok(candle_point(&state.exchanges, CandleRow::from_row(item)?)))
+        .collect::<Result<_, sqlx::Error>>()?;
+    Ok(series)
+}
+
+fn stream_candles(
+    state: Arc<AppState>,
+    query: GetCandlesRequest,
+    format: OutputFormat,
+) -> Result<Response, ApiError> {
+    let window = candle_window(&query)?;
+    let meta = envelope(&candle_series_meta(&query, &window));
+    Ok(stream_rows(format, meta, move |mut sink| async move {
+        let mut query_builder = build_candle_query(&query, &window, None);
+        let mut rows = query_builder.build().fetch(&state.crypto_data_db);
+        while let Some(item) = rows.try_next().await? {
+            if !sink
+                .send(candle_point(&state.exchanges, CandleRow::from_row(&item)?))
+                .await
+            {
+                break;
+            }
+        }
+        Ok(sink)
+    }))
+}
+
+/// Streams the candles unless a page was requested. `legacy_json` keeps the
+/// v1 nested-array JSON shape.
+async fn respond_candles(
+    state: Arc<AppState>,
+    query: GetCandlesRequest,
+    format: OutputFormat,
+    legacy_json: bool,
+) -> Result<Response, ApiError> {
+    let legacy_json = legacy_json && format == OutputFormat::Json;
+    if !legacy_json && candle_page(&query)?.is_none() {
+        return stream_candles(state, query, format);
+    }
+    let mut series = query_candles(&state, &query).await?;
+    let next_cursor = series.next_cursor.clone();
+    let data = std::mem::take(&mut series.data);
+    let response = if legacy_json {
+        let res: Vec<Vec<String>> = data
+            .into_iter()
+            .map(|candle| {
+                vec![
+                    candle.timestamp.to_string(),
+                    candle.exchange_id.to_string(),
+                    candle.open.to_string(),
+                    candle.high.to_string(),
+                    candle.low.to_string(),
+                    candle.close.to_string(),
+                    candle.volume.unwrap_or_default().to_string(),
+                    candle.trade_rows.to_string(),
+                ]
+            })
+            .collect();
+        vary_accept(Json(json!(res)).into_response())
+    } else {
+        export_rows(format, envelope(&series), data)
+    };
+    Ok(with_next_cursor(response, next_cursor))
+}
+
+pub async fn get_candles(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(output): Query<OutputFormatRequest>,
+    Query(query): Query<GetCandlesRequest>,
+) -> Result<Response, ApiError> {
+    let format = OutputFormat::negotiate(output.format.as_deref(), &headers)?;
+    respond_candles(state, query, format, true).await
+}
+
+pub async fn get_candle_series(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(output): Query<OutputFormatRequest>,
+    Query(query): Query<GetCandlesRequest>,
+) -> Result<Response, ApiError> {
+    let format = OutputFormat::negotiate(output.format.as_deref(), &headers)?;
+    respond_candles(state, query, format, false).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{
+        exchanges::{Exchange, QuoteConvention, VolumeSemantics},
+        pagination::Cursor,
+        time_window::Bucket,
+    };
+
+    fn request(exchange_id: Option<i64>) -> GetCandlesRequest {
+        GetCandlesRequest {
+            symbol: "BTC-USDT".to_string(),
+            interval: None,
+            from: None,
+            to: None,
+            bucket: None,
+            cursor: None,
+            limit: None,
+            exchange_id,
+        }
+    }
+
+    fn window() -> TimeWindow {
+        TimeWindow {
+            from: DateTime::from_timestamp(1730764800, 0),
+            to: None,
+            bucket: Bucket::OneHour,
+        }
+    }
+
+    #[test]
+    fn candle_query_filters_and_pages() {
+        let sql = build_candle_query(&request(None), &window(), None)
+            .sql()
+            .to_string();
+        assert!(sql.contains("AS volume_over_price"));
+        assert!(!sql.contains("exchange_id="));
+        assert!(sql.ends_with(" ORDER BY time_interval ASC, exchange_id ASC"));
+
+        let page = Page {
+            after: Some(Cursor {
+                time: DateTime::from_timestamp(1730768400, 0).unwrap(),
+                exchange_id: 2,
+            }),
+            limit: 10,
+        };
+        let sql = build_candle_query(&request(Some(2)), &window(), Some(&page))
+            .sql()
+            .to_string();
+        assert!(sql.starts_with("SELECT * FROM (SELECT exchange_id, time_interval"));
+        assert!(sql.contains(" AND exchange_id=$2"));
+        assert!(sql.contains(") page WHERE (time_interval, exchange_id) > ("));
+    }
+
+    #[test]
+    fn candle_volume_is_in_the_base_currency() {
+        let exchange = |id, volume_semantics, quote_convention| Exchange {
+            id,
+            name: format!("Exchange {}", id),
+            slug: format!("exchange-{}", id),
+            volume_semantics,
+            quote_convention,
+            enabled: true,
+        };
+        let exchanges = ExchangeRegistry::new(vec![
+            exchange(0, VolumeSemantics::PerRow, QuoteConvention::Base),
+            exchange(1, VolumeSemantics::Rolling24h, QuoteConvention::Quote),
+            exchange(2, VolumeSemantics::PerRow, QuoteConvention::Quote),
+        ]);
+        let row = |exchange_id| CandleRow {
+            exchange_id,
+            time_interval: DateTime::from_timestamp(1730764800, 0).unwrap(),
+            open: 50.0,
+            high: 70.0,
+            low: 50.0,
+            close: 70.0,
+            volume: 120.0,
+            volume_over_price: Some(2.0),
+            trade_rows: 2,
+        };
+        let point = candle_point(&exchanges, row(0));
+        assert_eq!(point.timestamp, 1730764800);
+        assert_eq!(point.exchange.as_deref(), Some("Exchange 0"));
+        assert_eq!(point.volume, Some(120.0));
+        assert_eq!(candle_point(&exchanges, row(2)).volume, Some(2.0));
+        assert_eq!(candle_point(&exchanges, row(1)).volume, None);
+    }
+}
@@ -0,0 +1,11 @@
+use crate::{utils::errors::ApiError, AppState};
+use axum::{
+    extract::State,
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::sync::Arc;
+
+pub async fn get_exchanges(State(state): State<Arc<AppState>>) -> Result<Response, ApiError> {
+    Ok(Json(state.exchanges.all()).into_response())
+}
@@ -0,0 +1,263 @@
+use crate::{
+    dto::{
+        request::GetIndicatorsRequest,
+        response::{ExchangeIndicators, IndicatorSeries, IndicatorsResponse},
+    },
+    utils::{
+        errors::ApiError,
+        indicators::{Bar, Indicator},
+        symbol::split_symbol,
+        time_window::TimeWindow,
+    },
+    AppState,
+};
+use axum::{
+    extract::{Query, State},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use sqlx::{postgres::PgRow, Postgres, QueryBuilder, Row};
+use std::sync::Arc;
+
+/// Bucket starts and bars of one exchange, in time order.
+type ExchangeBars = (i32, Vec<DateTime<Utc>>, Vec<Bar>);
+
+/// Pushes the bars of the rows selected by `filters`, which is appended to
+/// `... FROM volume_data v WHERE v.token_symbol=$1`. Volumes are summed both as
+/// reported and divided by price, so that either can be turned into base
+/// volume once the exchange's quote convention is known.
+fn push_bars(
+    query_builder: &mut QueryBuilder<'static, Postgres>,
+    symbol: &str,
+    window: &TimeWindow,
+    from: &str,
+    filters: impl FnOnce(&mut QueryBuilder<'static, Postgres>),
+) {
+    query_builder.push(
+        "SELECT exchange_id, time_interval, (array_agg(price ORDER BY timestamp DESC))[1] AS close, \
+         SUM(total_volume) AS volume, SUM(total_volume / NULLIF(price, 0)) AS volume_over_price, \
+         SUM(price * total_volume) AS price_volume \
+         FROM (SELECT v.exchange_id, price, total_volume, timestamp, ",
+    );
+    query_builder.push(window.bucket.sql_expr("timestamp"));
+    query_builder.push(" AS time_interval FROM ");
+    query_builder.push(from);
+    query_builder.push(" WHERE v.token_symbol=");
+    query_builder.push_bind(symbol.to_string());
+    filters(query_builder);
+    query_builder.push(
+        ") v GROUP BY exchange_id, time_interval ORDER BY exchange_id ASC, time_interval ASC",
+    );
+}
+
+/// Bars of every bucket in `window`, ordered by exchange and time.
+fn build_bar_query(
+    query: &GetIndicatorsRequest,
+    window: &TimeWindow,
+) -> QueryBuilder<'static, Postgres> {
+    let mut query_builder = QueryBuilder::new("");
+    push_bars(
+        &mut query_builder,
+        &query.symbol,
+        window,
+        "volume_data v",
+        |query_builder| {
+            if let Some(exchange_id) = query.exchange_id {
+                query_builder.push(" AND exchange_id=");
+                query_builder.push_bind(exchange_id);
+            }
+            window.push_filter(query_builder, "timestamp");
+        },
+    );
+    query_builder
+}
+
+/// Bars of the last `count` non-empty buckets before `from` for each of
+/// `exchange_ids`, see [`Bucket::push_warm_up_starts`].
+///
+/// [`Bucket::push_warm_up_starts`]: crate::utils::time_window::Bucket::push_warm_up_starts
+fn build_warm_up_query(
+    symbol: &str,
+    window: &TimeWindow,
+    from: DateTime<Utc>,
+    exchange_ids: Vec<i32>,
+    count: usize,
+) -> QueryBuilder<'static, Postgres> {
+    let series: Vec<(i32, String)> = exchange_ids
+        .into_iter()
+        .map(|exchange_id| (exchange_id, symbol.to_string()))
+        .collect();
+    let mut query_builder = QueryBuilder::new("");
+    window
+        .bucket
+        .push_warm_up_starts(&mut query_builder, "volume_data", &series, from, count);
+    push_bars(
+        &mut query_builder,
+        symbol,
+        window,
+        "volume_data v JOIN starts ON starts.exchange_id = v.exchange_id",
+        |query_builder| {
+            query_builder.push(" AND timestamp >= starts.warm_from AND timestamp<");
+            query_builder.push_bind(from);
+        },
+    );
+    query_builder
+}
+
+/// Base volume and price-volume of a bar, see [`ExchangeRegistry::base_volume`].
+///
+/// [`ExchangeRegistry::base_volume`]: crate::utils::exchanges::ExchangeRegistry::base_volume
+fn bar_volume(state: &AppState, exchange_id: i32, row: &PgRow) -> (Option<f64>, Option<f64>) {
+    let volume = row.get::<f64, _>("volume");
+    (
+        state.exchanges.base_volume(
+            exchange_id,
+            volume,
+            row.get::<Option<f64>, _>("volume_over_price"),
+        ),
+        state
+            .exchanges
+            .quote_volume(exchange_id, volume, row.get::<f64, _>("price_volume")),
+    )
+}
+
+/// Appends bar rows, ordered by exchange and time, to the per-exchange series.
+fn collect_bars(state: &AppState, rows: &[PgRow], exchanges: &mut Vec<ExchangeBars>) {
+    for row in rows {
+        let exchange_id = row.get::<i32, _>("exchange_id");
+        if exchanges.last().is_none_or(|(id, _, _)| *id != exchange_id) {
+            exchanges.push((exchange_id, vec![], vec![]));
+        }
+        if let Some((_, timestamps, bars)) = exchanges.last_mut() {
+            let (volume, price_volume) = bar_volume(state, exchange_id, row);
+            timestamps.push(row.get::<DateTime<Utc>, _>("time_interval"));
+            bars.push(Bar {
+                close: row.get::<f64, _>("close"),
+                volume,
+                price_volume,
+            });
+        }
+    }
+}
+
+/// Indicators over the consecutive buckets of one exchange, trimmed to the
+/// buckets that overlap the requested range. Empty buckets are skipped rather
+/// than filled.
+fn exchange_indicators(
+    state: &AppState,
+    exchange_id: i32,
+    window: &TimeWindow,
+    indicators: &[Indicator],
+    timestamps: Vec<DateTime<Utc>>,
+    bars: Vec<Bar>,
+) -> ExchangeIndicators {
+    let start = match window.from {
+        Some(from) => timestamps
+            .iter()
+            .position(|timestamp| *timestamp + window.bucket.duration() > from)
+            .unwrap_or(timestamps.len()),
+        None => 0,
+    };
+    let series = indicators
+        .iter()
+        .flat_map(|indicator| {
+            indicator
+                .names()
+                .into_iter()
+                .zip(indicator.compute(&bars, start))
+        })
+        .map(|(name, values)| IndicatorSeries {
+            name,
+            values: values[start..].to_vec(),
+        })
+        .collect();
+    ExchangeIndicators {
+        exchange_id,
+        exchange: state.exchanges.name(exchange_id),
+        timestamps: timestamps[start..]
+            .iter()
+            .map(|timestamp| timestamp.timestamp())
+            .collect(),
+        close: bars[start..].iter().map(|bar| bar.close).collect(),
+        volume: bars[start..].iter().map(|bar| bar.volume).collect(),
+        series,
+    }
+}
+
+/// Indicator series per exchange. The non-empty buckets before the requested
+/// start are fetched as warm-up so that the first returned values are
+/// already correct.
+pub async fn query_indicators(
+    state: &AppState,
+    query: &GetIndicatorsRequest,
+) -> Result<IndicatorsResponse, ApiError> {
+    let window = TimeWindow::resolve(
+        query.interval.as_deref(),
+        query.from.as_deref(),
+        query.to.as_deref(),
+        query.bucket.as_deref(),
+    )?;
+    let indicators = Indicator::parse_list(&query.indicators)?;
+    let warm_up = indicators
+        .iter()
+        .map(Indicator::warm_up)
+        .max()
+        .unwrap_or_default();
+    // The first bucket is fetched whole, so the warm-up ends where it starts.
+    let from = window.from.map(|from| window.bucket.floor(from));
+    let rows = build_bar_query(query, &TimeWindow { from, ..window })
+        .build()
+        .fetch_all(&state.crypto_data_db)
+        .await?;
+    let mut exchanges: Vec<ExchangeBars> = vec![];
+    collect_bars(state, &rows, &mut exchanges);
+
+    if let (Some(from), true) = (from, warm_up > 0 && !exchanges.is_empty()) {
+        let exchange_ids = exchanges.iter().map(|(id, _, _)| *id).collect();
+        let rows = build_warm_up_query(&query.symbol, &window, from, exchange_ids, warm_up)
+            .build()
+            .fetch_all(&state.crypto_data_db)
+            .await?;
+        let mut history = vec![];
+        collect_bars(state, &rows, &mut history);
+        for (exchange_id, timestamps, bars) in &mut exchanges {
+            if let Some((_, mut earlier_timestamps, mut earlier_bars)) = history
+                .iter()
+                .position(|(id, _, _)| id == exchange_id)
+                .map(|i| history.swap_remove(i))
+            {
+                earlier_timestamps.append(timestamps);
+                earlier_bars.append(bars);
+                *timestamps = earlier_timestamps;
+                *bars = earlier_bars;
+            }
+        }
+    }
+
+    let data = exchanges
+        .into_iter()
+        .map(|(exchange_id, timestamps, bars)| {
+            exchange_indicators(state, exchange_id, &window, &indicators, timestamps, bars)
+        })
+        .collect();
+
+    let (base, quote) = split_symbol(&query.symbol).unwrap_or((&query.symbol, ""));
+    Ok(IndicatorsResponse {
+        symbol: query.symbol.clone(),
+        price_unit: quote.to_string(),
+        volume_unit: base.to_string(),
+        bucket: window.bucket.as_str().to_string(),
+        from: window.from.map(|t| t.timestamp()),
+        to: window.to.map(|t| t.timestamp()),
+        indicators: indicators.iter().flat_map(Indicator::names).collect(),
+        data,
+    })
+}
+
+pub async fn get_indicators(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<GetIndicatorsRequest>,
+) -> Result<Response, ApiError> {
+    Ok(Json(query_indicators(&state, &query).await?).into_response())
+}
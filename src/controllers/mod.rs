@@ -1,4 +1,16 @@
+pub mod alerts;
+pub mod anomalies;
 pub mod balance;
+pub mod batch;
+pub mod cache;
+pub mod candles;
+pub mod exchanges;
+pub mod indicators;
 pub mod oauth;
+pub mod stream;
+pub mod summary;
+pub mod symbols;
 pub mod user;
 pub mod volume;
+pub mod watchlists;
+pub mod webhooks;
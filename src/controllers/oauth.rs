@@ -23,7 +23,7 @@ pub async fn get_google_auth_url(
         "{}?response_type=code&client_id={}&include_granted_scopes=true&redirect_uri={}&scope=email%20profile",
         AUTH_URL, state.env.client_id, state.env.redirect_url
     );
-    return Ok(auth_url);
+    Ok(auth_url)
 }
 pub async fn oauth_callback(
     State(state): State<Arc<AppState>>,
@@ -52,11 +52,11 @@ pub async fn oauth_callback(
         let (access_token, refresh_token) = generate_token_pair(state, user_id.0)?;
         let response = Json(JWTTokenResponse {
             api_token: access_token,
-            refreshToken: refresh_token,
+            refresh_token,
             access_token: "".to_string(),
         })
         .into_response();
         return Ok(response);
     }
-    return Err(ApiError::Unauthorized);
+    Err(ApiError::Unauthorized)
 }
@@ -0,0 +1,472 @@
+use crate::{
+    dto::{request::*, response::StreamMessage},
+    utils::{
+        errors::ApiError,
+        live::{channel_matches, is_valid_channel, BalanceTick, LiveEvent, VolumeTick},
+    },
+    AppState,
+};
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    http::HeaderMap,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Response,
+    },
+};
+use chrono::{DateTime, Utc};
+use futures::{future, stream, stream::BoxStream, StreamExt};
+use sqlx::{postgres::PgRow, Postgres, QueryBuilder, Row};
+use std::{collections::HashSet, convert::Infallible, sync::Arc, time::Duration};
+use tokio::sync::broadcast::error::RecvError;
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+
+const MAX_SUBSCRIPTIONS: usize = 100;
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+/// Rows replayed per connection. A longer gap ends with a `truncated` event.
+const REPLAY_LIMIT: usize = 50000;
+const REPLAY_PAGE_ROWS: i64 = 5000;
+const LAST_EVENT_ID_HEADER: &str = "last-event-id";
+
+pub async fn get_stream(ws: WebSocketUpgrade, State(state): State<Arc<AppState>>) -> Response {
+    ws.on_upgrade(move |socket| handle_stream(socket, state))
+}
+
+fn handle_command(subscriptions: &mut HashSet<String>, text: &str) -> StreamMessage {
+    let command: StreamCommand = match serde_json::from_str(text) {
+        Ok(command) => command,
+        Err(e) => {
+            return StreamMessage::Error {
+                message: format!("Invalid command: {}", e),
+            }
+        }
+    };
+    if let Some(channel) = command.channels.iter().find(|c| !is_valid_channel(c)) {
+        return StreamMessage::Error {
+            message: format!("Invalid channel '{}'", channel),
+        };
+    }
+    match command.action {
+        StreamAction::Subscribe => {
+            let added: HashSet<&String> = command
+                .channels
+                .iter()
+                .filter(|c| !subscriptions.contains(*c))
+                .collect();
+            if subscriptions.len() + added.len() > MAX_SUBSCRIPTIONS {
+                return StreamMessage::Error {
+                    message: format!("At most {} subscriptions per connection", MAX_SUBSCRIPTIONS),
+                };
+            }
+            subscriptions.extend(command.channels.iter().cloned());
+            StreamMessage::Subscribed {
+                channels: command.channels,
+            }
+        }
+        StreamAction::Unsubscribe => {
+            for channel in &command.channels {
+                subscriptions.remove(channel);
+            }
+            StreamMessage::Unsubscribed {
+                channels: command.channels,
+            }
+        }
+    }
+}
+
+async fn send(socket: &mut WebSocket, message: &StreamMessage) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(message).unwrap_or_default();
+    socket.send(Message::Text(text)).await
+}
+
+async fn handle_stream(mut socket: WebSocket, state: Arc<AppState>) {
+    let mut events = state.live.subscribe();
+    let mut subscriptions: HashSet<String> = HashSet::new();
+    loop {
+        let message = tokio::select! {
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Text(text))) => handle_command(&mut subscriptions, &text),
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                // Pings are answered by axum.
+                Some(Ok(_)) => continue,
+            },
+            event = events.recv() => match event {
+                Ok(event) => {
+                    let channel = event.channel();
+                    if !subscriptions.iter().any(|pattern| channel_matches(pattern, &channel)) {
+                        continue;
+                    }
+                    update_message(&state, event)
+                }
+                Err(RecvError::Lagged(skipped)) => StreamMessage::Lagged { skipped },
+                Err(RecvError::Closed) => break,
+            },
+        };
+        if send(&mut socket, &message).await.is_err() {
+            break;
+        }
+    }
+}
+
+fn update_message(state: &AppState, event: LiveEvent) -> StreamMessage {
+    StreamMessage::Update {
+        channel: event.channel(),
+        exchange: state.exchanges.name(event.exchange_id()),
+        data: event,
+    }
+}
+
+/// Event ids are the row timestamp in Unix seconds, except for `truncated`
+/// events, see [`ReplayFrom`].
+fn sse_event(state: &AppState, event: LiveEvent) -> Event {
+    Event::default()
+        .id(event.timestamp().to_string())
+        .event(event.kind())
+        .json_data(update_message(state, event))
+        .unwrap_or_default()
+}
+
+/// Pushes `(pattern OR pattern ...)` for the patterns of one kind, so rows
+/// matched by overlapping patterns are only returned once.
+fn push_channel_filter(query_builder: &mut QueryBuilder<'_, Postgres>, patterns: &[Vec<&str>]) {
+    query_builder.push(" AND (");
+    for (i, parts) in patterns.iter().enumerate() {
+        if i > 0 {
+            query_builder.push(" OR ");
+        }
+        query_builder.push("(TRUE");
+        if let Ok(exchange_id) = parts[1].parse::<i32>() {
+            query_builder.push(" AND exchange_id=");
+            query_builder.push_bind(exchange_id);
+        }
+        if let Some(symbol) = parts.get(2).filter(|symbol| **symbol != "*") {
+            query_builder.push(" AND token_symbol=");
+            query_builder.push_bind(symbol.to_string());
+        }
+        query_builder.push(")");
+    }
+    query_builder.push(")");
+}
+
+/// Pushes the rows of one kind after the `(timestamp, kind, id)` keyset
+/// position `after`, or from `since` on the first page.
+fn push_replay_kind(
+    query_builder: &mut QueryBuilder<'static, Postgres>,
+    kind: &'static str,
+    patterns: &[Vec<&str>],
+    since: DateTime<Utc>,
+    after: Option<&(DateTime<Utc>, &'static str, i32)>,
+) {
+    query_builder.push(match kind {
+        "volume" => {
+            "SELECT 'volume' AS kind, id, exchange_id, token_symbol, price, total_volume, \
+             day_total_volume, NULL::DOUBLE PRECISION AS wallet_balance, \
+             NULL::DOUBLE PRECISION AS transfer_balance, timestamp FROM volume_data WHERE timestamp>="
+        }
+        _ => {
+            "SELECT 'balance' AS kind, id, exchange_id, token_symbol, NULL::DOUBLE PRECISION AS price, \
+             NULL::DOUBLE PRECISION AS total_volume, NULL::DOUBLE PRECISION AS day_total_volume, \
+             wallet_balance, transfer_balance, timestamp FROM balance_data WHERE timestamp>="
+        }
+    });
+    query_builder.push_bind(since);
+    push_channel_filter(query_builder, patterns);
+    if let Some((timestamp, after_kind, id)) = after {
+        query_builder.push(format!(" AND (timestamp, '{}'::TEXT, id) > (", kind));
+        query_builder.push_bind(*timestamp);
+        query_builder.push(", ");
+        query_builder.push_bind(after_kind.to_string());
+        query_builder.push(", ");
+        query_builder.push_bind(*id);
+        query_builder.push(")");
+    }
+}
+
+fn replay_event(row: &PgRow) -> LiveEvent {
+    let id = Some(row.get::<i32, _>("id"));
+    let exchange_id = row.get::<i32, _>("exchange_id");
+    let token_symbol = row.get::<String, _>("token_symbol");
+    let timestamp = row.get::<DateTime<Utc>, _>("timestamp").timestamp();
+    match row.get::<&str, _>("kind") {
+        "volume" => LiveEvent::Volume(VolumeTick {
+            id,
+            exchange_id,
+            token_symbol,
+            price: row.get::<f64, _>("price"),
+            total_volume: row.get::<f64, _>("total_volume"),
+            day_total_volume: row.get::<Option<f64>, _>("day_total_volume"),
+            timestamp,
+        }),
+        _ => LiveEvent::Balance(BalanceTick {
+            id,
+            exchange_id,
+            token_symbol,
+            wallet_balance: row.get::<f64, _>("wallet_balance"),
+            transfer_balance: row.get::<f64, _>("transfer_balance"),
+            timestamp,
+        }),
+    }
+}
+
+type ReplayKeyset = (DateTime<Utc>, &'static str, i32);
+
+/// Where a replay starts. A plain event id is a Unix second, replayed from its
+/// start. A `truncated` event's id is the `(timestamp, kind, id)` keyset of the
+/// last replayed row, as `<timestamp in microseconds>:<kind>:<id>`, so the next
+/// replay continues right after it even when a whole replay fell into a single
+/// second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReplayFrom {
+    Since(DateTime<Utc>),
+    After(ReplayKeyset),
+}
+
+impl ReplayFrom {
+    fn parse(value: &str) -> Result<Self, ApiError> {
+        let invalid = || ApiError::InvalidRequest("Invalid Last-Event-ID".to_string());
+        let mut parts = value.trim().split(':');
+        let time = parts
+            .next()
+            .and_then(|time| time.parse::<i64>().ok())
+            .ok_or_else(invalid)?;
+        let Some(kind) = parts.next() else {
+            return DateTime::from_timestamp(time, 0)
+                .map(ReplayFrom::Since)
+                .ok_or_else(invalid);
+        };
+        let kind = match kind {
+            "volume" => "volume",
+            "balance" => "balance",
+            _ => return Err(invalid()),
+        };
+        let id = parts
+            .next()
+            .and_then(|id| id.parse::<i32>().ok())
+            .ok_or_else(invalid)?;
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        let time = DateTime::from_timestamp_micros(time).ok_or_else(invalid)?;
+        Ok(ReplayFrom::After((time, kind, id)))
+    }
+
+    fn event_id(&self) -> String {
+        match self {
+            ReplayFrom::Since(time) => time.timestamp().to_string(),
+            ReplayFrom::After((time, kind, id)) => {
+                format!("{}:{}:{}", time.timestamp_micros(), kind, id)
+            }
+        }
+    }
+}
+
+struct Replay {
+    events: Vec<LiveEvent>,
+    /// Set when `REPLAY_LIMIT` rows were replayed before catching up: the
+    /// Last-Event-ID to resume from.
+    truncated: Option<ReplayFrom>,
+}
+
+/// Rows from `from` on the subscribed channels, oldest first, read in keyset
+/// pages until the live feed is reached or `REPLAY_LIMIT` rows were read.
+async fn replay_events(
+    state: &AppState,
+    patterns: &[String],
+    from: ReplayFrom,
+) -> Result<Replay, ApiError> {
+    let (since, mut after) = match from {
+        ReplayFrom::Since(since) => (since, None),
+        ReplayFrom::After(after) => (after.0, Some(after)),
+    };
+    let split: Vec<Vec<&str>> = patterns.iter().map(|p| p.split(':').collect()).collect();
+    let kinds: Vec<(&'static str, Vec<Vec<&str>>)> = ["volume", "balance"]
+        .into_iter()
+        .map(|kind| {
+            (
+                kind,
+                split.iter().filter(|p| p[0] == kind).cloned().collect(),
+            )
+        })
+        .filter(|(_, patterns): &(_, Vec<Vec<&str>>)| !patterns.is_empty())
+        .collect();
+    let mut events: Vec<LiveEvent> = vec![];
+    loop {
+        let mut query_builder = QueryBuilder::new("");
+        for (i, (kind, patterns)) in kinds.iter().enumerate() {
+            if i > 0 {
+                query_builder.push(" UNION ALL ");
+            }
+            push_replay_kind(&mut query_builder, kind, patterns, since, after.as_ref());
+        }
+        query_builder.push(" ORDER BY timestamp, kind, id LIMIT ");
+        query_builder.push_bind(REPLAY_PAGE_ROWS);
+        let rows = query_builder
+            .build()
+            .fetch_all(&state.crypto_data_db)
+            .await?;
+        if let Some(row) = rows.last() {
+            let kind = match row.get::<&str, _>("kind") {
+                "volume" => "volume",
+                _ => "balance",
+            };
+            after = Some((row.get("timestamp"), kind, row.get("id")));
+        }
+        let caught_up = (rows.len() as i64) < REPLAY_PAGE_ROWS;
+        events.extend(rows.iter().map(replay_event));
+        if caught_up {
+            return Ok(Replay {
+                events,
+                truncated: None,
+            });
+        }
+        if events.len() >= REPLAY_LIMIT {
+            return Ok(Replay {
+                events,
+                truncated: after.map(ReplayFrom::After),
+            });
+        }
+    }
+}
+
+/// Same channels as the WebSocket feed over Server-Sent Events. A reconnecting
+/// client sends `Last-Event-ID` (or `last_event_id`) and first receives the
+/// rows it missed. If there are more than `REPLAY_LIMIT`, the stream ends with
+/// a `truncated` event whose id is the point to resume from.
+pub async fn get_events(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<GetEventsRequest>,
+) -> Result<Sse<BoxStream<'static, Result<Event, Infallible>>>, ApiError> {
+    let patterns: Vec<String> = query
+        .channels
+        .split(',')
+        .map(|channel| channel.trim().to_string())
+        .filter(|channel| !channel.is_empty())
+        .collect();
+    if patterns.is_empty() || patterns.len() > MAX_SUBSCRIPTIONS {
+        return Err(ApiError::InvalidRequest(format!(
+            "Between 1 and {} channels are required",
+            MAX_SUBSCRIPTIONS
+        )));
+    }
+    if let Some(channel) = patterns.iter().find(|c| !is_valid_channel(c)) {
+        return Err(ApiError::InvalidRequest(format!(
+            "Invalid channel '{}'",
+            channel
+        )));
+    }
+    let last_event_id = match headers.get(LAST_EVENT_ID_HEADER) {
+        Some(value) => Some(ReplayFrom::parse(value.to_str().map_err(|_| {
+            ApiError::InvalidRequest("Invalid Last-Event-ID".to_string())
+        })?)?),
+        None => query
+            .last_event_id
+            .as_deref()
+            .map(ReplayFrom::parse)
+            .transpose()?,
+    };
+
+    // Subscribe before replaying so nothing lands in between.
+    let receiver = state.live.subscribe();
+    let replay = match last_event_id {
+        Some(from) => replay_events(&state, &patterns, from).await?,
+        None => Replay {
+            events: vec![],
+            truncated: None,
+        },
+    };
+    // Live rows that were also replayed, e.g. because they committed while the
+    // replay ran. Any other live row is new, whatever its timestamp.
+    let replayed_ids: HashSet<(&'static str, i32)> = replay
+        .events
+        .iter()
+        .filter_map(|event| Some((event.kind(), event.id()?)))
+        .collect();
+
+    let replay_state = state.clone();
+    let replayed = stream::iter(
+        replay
+            .events
+            .into_iter()
+            .map(move |event| sse_event(&replay_state, event)),
+    );
+    if let Some(resume_from) = replay.truncated {
+        // Ending the stream makes EventSource reconnect with this id as
+        // Last-Event-ID, which replays the next part.
+        let resume_from = resume_from.event_id();
+        let truncated = Event::default()
+            .id(resume_from.clone())
+            .event("truncated")
+            .json_data(StreamMessage::Truncated { resume_from })
+            .unwrap_or_default();
+        let events = replayed.chain(stream::once(future::ready(truncated)));
+        return Ok(Sse::new(events.map(Ok).boxed()).keep_alive(heartbeat()));
+    }
+    let live = BroadcastStream::new(receiver).filter_map(move |event| {
+        let event = match event {
+            Ok(event) => {
+                let channel = event.channel();
+                let replayed = event
+                    .id()
+                    .is_some_and(|id| replayed_ids.contains(&(event.kind(), id)));
+                (!replayed
+                    && patterns
+                        .iter()
+                        .any(|pattern| channel_matches(pattern, &channel)))
+                .then(|| sse_event(&state, event))
+            }
+            Err(BroadcastStreamRecvError::Lagged(skipped)) => Some(
+                Event::default()
+                    .event("lagged")
+                    .json_data(StreamMessage::Lagged { skipped })
+                    .unwrap_or_default(),
+            ),
+        };
+        future::ready(event)
+    });
+    Ok(Sse::new(replayed.chain(live).map(Ok).boxed()).keep_alive(heartbeat()))
+}
+
+fn heartbeat() -> KeepAlive {
+    KeepAlive::new()
+        .interval(HEARTBEAT_INTERVAL)
+        .text("heartbeat")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replay_from_event_ids() {
+        let since = DateTime::from_timestamp(1730764800, 0).unwrap();
+        assert_eq!(
+            ReplayFrom::parse("1730764800").unwrap(),
+            ReplayFrom::Since(since)
+        );
+        let after = ReplayFrom::After((
+            DateTime::from_timestamp_micros(1730764800123456).unwrap(),
+            "balance",
+            42,
+        ));
+        assert_eq!(after.event_id(), "1730764800123456:balance:42");
+        assert_eq!(ReplayFrom::parse(&after.event_id()).unwrap(), after);
+        for value in [
+            "",
+            "abc",
+            "1:trade:1",
+            "1:volume",
+            "1:volume:x",
+            "1:volume:1:1",
+        ] {
+            assert!(
+                ReplayFrom::parse(value).is_err(),
+                "{} should be rejected",
+                value
+            );
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use crate::{
+    dto::{
+        request::{GetSummaryRequest, OutputFormatRequest},
+        response::{MarketSummary, MarketSummaryResponse},
+    },
+    utils::{
+        errors::ApiError,
+        exchanges::{QuoteConvention, VolumeSemantics},
+        export::{envelope, export_rows, OutputFormat},
+        symbol::{parse_symbols, split_symbol},
+        time_window::parse_duration,
+    },
+    AppState,
+};
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::Response,
+};
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{postgres::PgRow, Postgres, QueryBuilder, Row};
+use std::sync::Arc;
+
+const DEFAULT_WINDOW: &str = "24h";
+const MAX_WINDOW_DAYS: i64 = 31;
+const MAX_SYMBOLS: usize = 100;
+
+/// Per exchange and pair aggregates of the rows in `[$2, $3)`. Every enabled
+/// exchange gets at least one row: one per requested symbol, or a single
+/// all-NULL row when it has no data and no symbols were requested.
+fn build_summary_query(
+    exchange_ids: Vec<i32>,
+    symbols: Option<&[String]>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> QueryBuilder<'static, Postgres> {
+    let mut query_builder = QueryBuilder::new(
+        "WITH stats AS (SELECT exchange_id, token_symbol, COUNT(*) AS samples, \
+         SUM(total_volume) AS volume, SUM(total_volume * price) AS quote_volume, \
+         (array_agg(price ORDER BY timestamp ASC))[1] AS open_price, \
+         (array_agg(price ORDER BY timestamp DESC))[1] AS last_price, \
+         (array_agg(day_total_volume ORDER BY timestamp DESC) FILTER (WHERE day_total_volume IS NOT NULL))[1] AS day_volume, \
+         MAX(price) AS high, MIN(price) AS low, MAX(timestamp) AS last_timestamp \
+         FROM volume_data WHERE exchange_id = ANY(",
+    );
+    query_builder.push_bind(exchange_ids.clone());
+    query_builder.push(") AND timestamp>=");
+    query_builder.push_bind(from);
+    query_builder.push(" AND timestamp<");
+    query_builder.push_bind(to);
+    if let Some(symbols) = symbols {
+        query_builder.push(" AND token_symbol = ANY(");
+        query_builder.push_bind(symbols.to_vec());
+        query_builder.push(")");
+    }
+    query_builder.push(" GROUP BY exchange_id, token_symbol) SELECT e.id AS exchange_id, ");
+    query_builder.push(match symbols {
+        Some(_) => "requested.token_symbol::VARCHAR AS token_symbol",
+        None => "s.token_symbol",
+    });
+    query_builder.push(
+        ", COALESCE(s.samples, 0) AS samples, s.volume, s.quote_volume, s.open_price, s.last_price, \
+         s.day_volume, s.high, s.low, s.last_timestamp FROM unnest(",
+    );
+    query_builder.push_bind(exchange_ids);
+    query_builder.push("::INT[]) e(id)");
+    match symbols {
+        Some(symbols) => {
+            query_builder.push(" CROSS JOIN unnest(");
+            query_builder.push_bind(symbols.to_vec());
+            query_builder.push(
+                "::TEXT[]) requested(token_symbol) LEFT JOIN stats s \
+                 ON s.exchange_id = e.id AND s.token_symbol = requested.token_symbol",
+            );
+        }
+        None => {
+            query_builder.push(" LEFT JOIN stats s ON s.exchange_id = e.id");
+        }
+    }
+    query_builder.push(" ORDER BY e.id, token_symbol");
+    query_builder
+}
+
+/// Volume of a row in the pair's quote currency. Rolling 24h exchanges report
+/// their latest 24h total, pro-rated to the window length.
+fn summary_volume(
+    state: &AppState,
+    item: &PgRow,
+    exchange_id: i32,
+    window: Duration,
+) -> Option<f64> {
+    let exchange = state.exchanges.get(exchange_id)?;
+    match exchange.volume_semantics {
+        VolumeSemantics::PerRow => match exchange.quote_convention {
+            QuoteConvention::Base => item.get::<Option<f64>, _>("quote_volume"),
+            QuoteConvention::Quote => item.get::<Option<f64>, _>("volume"),
+        },
+        VolumeSemantics::Rolling24h => {
+            let day_volume = item.get::<Option<f64>, _>("day_volume")?;
+            let day_volume = match exchange.quote_convention {
+                QuoteConvention::Base => day_volume * item.get::<Option<f64>, _>("last_price")?,
+                QuoteConvention::Quote => day_volume,
+            };
+            Some(day_volume * window.num_seconds() as f64 / 86400.0)
+        }
+    }
+}
+
+fn summary_row(state: &AppState, item: &PgRow, window: Duration) -> MarketSummary {
+    let exchange_id = item.get::<i32, _>("exchange_id");
+    let symbol = item.get::<Option<String>, _>("token_symbol");
+    let quote = symbol
+        .as_deref()
+        .and_then(split_symbol)
+        .map(|(_, quote)| quote.to_string());
+    let samples = item.get::<i64, _>("samples");
+    let open_price = item.get::<Option<f64>, _>("open_price");
+    let last_price = item.get::<Option<f64>, _>("last_price");
+    let price_change = open_price.zip(last_price).map(|(open, last)| last - open);
+    let price_change_pct = open_price
+        .zip(price_change)
+        .filter(|(open, _)| *open != 0.0)
+        .map(|(open, change)| change / open * 100.0);
+    MarketSummary {
+        exchange_id,
+        exchange: state.exchanges.name(exchange_id),
+        volume: if samples > 0 {
+            summary_volume(state, item, exchange_id, window)
+        } else {
+            None
+        },
+        volume_unit: quote.clone(),
+        open_price,
+        last_price,
+        price_change,
+        price_change_pct,
+        high: item.get::<Option<f64>, _>("high"),
+        low: item.get::<Option<f64>, _>("low"),
+        price_unit: quote,
+        samples,
+        last_timestamp: item
+            .get::<Option<DateTime<Utc>>, _>("last_timestamp")
+            .map(|timestamp| timestamp.timestamp()),
+        symbol,
+    }
+}
+
+/// Volume, price change and range of every enabled exchange and pair over the
+/// trailing `window`, in a single query.
+pub async fn query_summary(
+    state: &AppState,
+    query: &GetSummaryRequest,
+) -> Result<MarketSummaryResponse, ApiError> {
+    let window_name = query.window.as_deref().unwrap_or(DEFAULT_WINDOW);
+    let window = parse_duration(window_name)?;
+    if window > Duration::days(MAX_WINDOW_DAYS) {
+        return Err(ApiError::InvalidRequest(format!(
+            "window must be at most {} days",
+            MAX_WINDOW_DAYS
+        )));
+    }
+    let symbols = query
+        .symbols
+        .as_deref()
+        .map(|symbols| parse_symbols(symbols, MAX_SYMBOLS))
+        .transpose()?;
+    let to = Utc::now();
+    let from = to - window;
+    let exchange_ids = state
+        .exchanges
+        .enabled()
+        .map(|exchange| exchange.id)
+        .collect();
+    let data = build_summary_query(exchange_ids, symbols.as_deref(), from, to)
+        .build()
+        .fetch_all(&state.crypto_data_db)
+        .await?
+        .iter()
+        .map(|item| summary_row(state, item, window))
+        .collect();
+    Ok(MarketSummaryResponse {
+        window: window_name.to_string(),
+        from: from.timestamp(),
+        to: to.timestamp(),
+        symbols,
+        data,
+    })
+}
+
+pub async fn get_summary(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(output): Query<OutputFormatRequest>,
+    Query(query): Query<GetSummaryRequest>,
+) -> Result<Response, ApiError> {
+    let format = OutputFormat::negotiate(output.format.as_deref(), &headers)?;
+    let mut summary = query_summary(&state, &query).await?;
+    let data = std::mem::take(&mut summary.data);
+    Ok(export_rows(format, envelope(&summary), data))
+}
@@ -0,0 +1,20 @@
+use crate::{
+    utils::{errors::ApiError, symbol::query_symbols},
+    AppState,
+};
+use axum::{
+    extract::State,
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::sync::Arc;
+
+pub async fn get_symbols(State(state): State<Arc<AppState>>) -> Result<Response, ApiError> {
+    if let Some(symbols) = state.symbols.get() {
+        return Ok(Json(symbols.as_ref()).into_response());
+    }
+    // Requests before the first background refresh load the list themselves.
+    let symbols = query_symbols(&state.crypto_data_db, &state.exchanges).await?;
+    state.symbols.replace(symbols.clone());
+    Ok(Json(symbols).into_response())
+}
@@ -45,7 +45,7 @@ pub async fn login(
         let (access_token, refresh_token) = generate_token_pair(state, user[0].id)?;
         let response = Json(JWTTokenResponse {
             api_token: access_token,
-            refreshToken: refresh_token,
+            refresh_token,
             access_token: "".to_string(),
         })
         .into_response();
@@ -61,7 +61,7 @@ pub async fn signup(
         .bind(req.email.clone())
         .fetch_all(&state.db)
         .await?;
-    if user.len() != 0 {
+    if !user.is_empty() {
         return Err(ApiError::AlreadySignUp);
     }
     let confirmation_code: String = thread_rng()
@@ -94,7 +94,7 @@ pub async fn forgot_password(
         .bind(req.email.clone())
         .fetch_all(&state.db)
         .await?;
-    if user.len() == 0 {
+    if user.is_empty() {
         return Err(ApiError::NoEmailFound);
     }
     let id = uuid::Uuid::new_v4().to_string();
@@ -138,15 +138,13 @@ pub async fn confirm(
                 )
                 .await;
 
-                let password_hash = bcrypt::hash(data.password, 12);
-                if password_hash.is_err() {
-                    error!(
-                        "Failed to get the hash of password: {}",
-                        password_hash.unwrap_err()
-                    );
-                    return Err(ApiError::SignupError);
-                }
-                let password_hash = password_hash.unwrap();
+                let password_hash = match bcrypt::hash(data.password, 12) {
+                    Ok(password_hash) => password_hash,
+                    Err(e) => {
+                        error!("Failed to get the hash of password: {}", e);
+                        return Err(ApiError::SignupError);
+                    }
+                };
                 sqlx::query(
                     "INSERT INTO users (email, full_name, password_hash) VALUES ($1, $2, $3)",
                 )
@@ -155,7 +153,7 @@ pub async fn confirm(
                 .bind(password_hash)
                 .execute(&state.db)
                 .await?;
-                return Ok(());
+                Ok(())
             }
             _ => Err(ApiError::InvalidConfirmationEmail),
         },
@@ -166,14 +164,16 @@ pub async fn user_info(
     user: UserClaims,
 ) -> Result<impl IntoResponse, ApiError> {
     let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE (id = $1)")
-        .bind(user.uid.clone())
+        .bind(user.uid)
         .fetch_all(&state.db)
         .await?;
     if user.len() != 1 {
         return Err(ApiError::Unauthorized);
     }
-    let mut info = UserInfoResponse::default();
-    info.pic = user[0].profile_picture_url.clone();
+    let info = UserInfoResponse {
+        pic: user[0].profile_picture_url.clone(),
+        ..Default::default()
+    };
     let response = Json(info).into_response();
-    return Ok(response);
+    Ok(response)
 }
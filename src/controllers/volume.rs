@@ -1,158 +1,558 @@
 use crate::{
-    dto::request::*,
-    utils::{errors::ApiError, jwt::UserClaims},
+    controllers::summary::query_summary,
+    dto::{
+        request::*,
+        response::{
+            ExchangeVolume24hResponse, MarketSummary, VolumePoint, VolumeSeriesResponse,
+            VolumeSharePoint, VolumeShareSeriesResponse,
+        },
+    },
+    utils::{
+        conversion::Converter,
+        downsample::Downsample,
+        errors::ApiError,
+        exchanges::VolumeSemantics,
+        export::{envelope, export_rows, stream_rows, vary_accept, OutputFormat},
+        pagination::{with_next_cursor, Page},
+        rollup::{RollupSource, Rollups},
+        symbol::split_symbol,
+        time_window::{Bucket, TimeWindow},
+    },
     AppState,
 };
 use axum::{
     extract::{Query, State},
-    response::IntoResponse,
+    http::HeaderMap,
+    response::{IntoResponse, Response},
     Json,
 };
-use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
 use serde_json::json;
-use sqlx::{QueryBuilder, Row};
-use std::sync::Arc;
-use tracing::info;
-pub async fn get_volume_data(
-    State(state): State<Arc<AppState>>,
-    Query(query): Query<GetVolumeDataRequest>,
-) -> Result<impl IntoResponse, ApiError> {
-    let mut query_builder = QueryBuilder::new("SELECT SUM(total_volume) AS total_quantity, AVG(price) AS average_price, token_symbol, MAX(day_total_volume) as total_volume_day, exchange_id, date_trunc('hour', timestamp)");
-
-    let mut start_time = Utc::now();
-    let mut now_time = start_time.clone();
-    match query.interval.as_str() {
-        "1D" => {
-            start_time = start_time - Duration::days(1);
-            query_builder.push(" + INTERVAL '1 minute' * (floor(EXTRACT(MINUTE FROM timestamp) / 5) * 5) AS time_interval");
-        }
-        "7D" => {
-            start_time = start_time - Duration::days(7);
-            query_builder.push(" + INTERVAL '1 minute' * (floor(EXTRACT(MINUTE FROM timestamp) / 5) * 5) AS time_interval");
-        }
-        "1M" => {
-            let naive_date = start_time.naive_utc().date();
-            let year = naive_date.year();
-            let month = naive_date.month();
-            let new_month = if month == 1 { 12 } else { month - 1 };
-            let new_year = if month == 1 { year - 1 } else { year };
-            let month_days = (Utc.ymd(year, month, 1) - Duration::hours(24)).day();
-            let mut new_day = naive_date.day();
-            if new_day > month_days {
-                new_day = month_days;
+use sqlx::{postgres::PgRow, Postgres, QueryBuilder, Row};
+use std::{collections::HashMap, sync::Arc};
+
+fn volume_window(query: &GetVolumeDataRequest) -> Result<TimeWindow, ApiError> {
+    TimeWindow::resolve(
+        query.interval.as_deref(),
+        query.from.as_deref(),
+        query.to.as_deref(),
+        query.bucket.as_deref(),
+    )
+}
+
+fn volume_downsample(query: &GetVolumeDataRequest) -> Result<Option<Downsample>, ApiError> {
+    Downsample::from_request(query.max_points, query.downsample.as_deref())
+}
+
+fn volume_page(query: &GetVolumeDataRequest) -> Result<Option<Page>, ApiError> {
+    let page = Page::from_request(query.cursor.as_deref(), query.limit)?;
+    if page.is_some() && query.max_points.is_some() {
+        return Err(ApiError::InvalidRequest(
+            "cursor and limit cannot be combined with max_points".to_string(),
+        ));
+    }
+    Ok(page)
+}
+
+fn build_volume_query(
+    rollups: &Rollups,
+    query: &GetVolumeDataRequest,
+    window: &TimeWindow,
+    page: Option<&Page>,
+) -> QueryBuilder<'static, Postgres> {
+    let mut query_builder = match page {
+        Some(_) => Page::begin(),
+        None => QueryBuilder::new(""),
+    };
+    query_builder.push("SELECT SUM(total_volume) AS total_quantity, SUM(price_sum) / SUM(price_count)::DOUBLE PRECISION AS average_price, token_symbol, MAX(day_volume_max) as total_volume_day, SUM(day_volume_sum) / NULLIF(SUM(day_volume_count), 0)::DOUBLE PRECISION AS average_volume_day, exchange_id, ");
+    query_builder.push(window.bucket.sql_expr("ts"));
+    query_builder.push(" AS time_interval FROM ");
+    rollups.push_source(
+        &mut query_builder,
+        RollupSource::Volume,
+        window,
+        |query_builder, column| {
+            query_builder.push(" WHERE token_symbol=");
+            query_builder.push_bind(query.symbol.clone());
+            if let Some(exchange_id) = query.exchange_id {
+                query_builder.push(" AND exchange_id=");
+                query_builder.push_bind(exchange_id);
             }
-            start_time = Utc.ymd(new_year, new_month, new_day).and_hms(0, 0, 0);
-            query_builder.push(" AS time_interval");
-        }
-        "1Y" => {
-            let naive_date = start_time.naive_utc().date();
-
-            let month = if naive_date.month() + 1 == 13 {
-                1
-            } else {
-                naive_date.month()
-            };
-            let year = if month == 1 {
-                naive_date.year()
-            } else {
-                naive_date.year() - 1
-            };
-            let month_days = (Utc.ymd(year, month, 1) - Duration::hours(24)).day();
-            let mut day = naive_date.day();
-            if day > month_days {
-                day = month_days;
+            window.push_filter(query_builder, column);
+            if let Some(page) = page {
+                page.push_filter(query_builder, column);
             }
-            start_time = Utc
-                .ymd(naive_date.year() - 1, naive_date.month(), day)
-                .and_hms(0, 0, 0);
-            query_builder.push(" AS time_interval");
+        },
+    );
+    query_builder.push(" GROUP BY time_interval, exchange_id, token_symbol");
+    match page {
+        Some(page) => page.finish(&mut query_builder),
+        None => {
+            query_builder.push(" ORDER BY time_interval ASC");
         }
-        "All" => {
-            query_builder.push(" AS time_interval");
+    }
+    query_builder
+}
+
+/// Converts one aggregated row into `unit`, or `None` if the row's symbol
+/// cannot be expressed in that unit. Rolling 24h exchanges report their latest
+/// 24h total unless `per_bucket` is set, in which case the average 24h total
+/// is pro-rated to the bucket length so it can be added to per-row exchanges.
+fn volume_point(
+    state: &AppState,
+    item: &PgRow,
+    unit_symbol: &str,
+    per_bucket: Option<Bucket>,
+    converter: Option<&Converter>,
+) -> Option<VolumePoint> {
+    let timestamp = item.get::<DateTime<Utc>, _>("time_interval");
+    let exchange_id = item.get::<i32, _>("exchange_id");
+    let total_volume = match (state.exchanges.volume_semantics(exchange_id), per_bucket) {
+        (VolumeSemantics::PerRow, _) => item.get::<f64, _>("total_quantity"),
+        (VolumeSemantics::Rolling24h, None) => item.get::<f64, _>("total_volume_day"),
+        (VolumeSemantics::Rolling24h, Some(bucket)) => {
+            item.get::<f64, _>("average_volume_day") * bucket.duration().num_seconds() as f64
+                / 86400.0
         }
-        _ => {
-            return Ok(Json(json!([])));
+    };
+    let token_symbol: String = item.get::<String, _>("token_symbol");
+    let (base, quote) = split_symbol(&token_symbol)?;
+    let convention = state.exchanges.quote_convention(exchange_id);
+    let price = item.get::<f64, _>("average_price");
+    let volume = match converter {
+        None => convention.convert(total_volume, price, base, quote, unit_symbol)?,
+        Some(converter) => {
+            convention.convert(total_volume, price, base, quote, converter.source_unit())?
+                * converter.rate_at(timestamp.timestamp())?
         }
+    };
+    Some(VolumePoint {
+        timestamp: timestamp.timestamp(),
+        exchange_id,
+        exchange: state.exchanges.name(exchange_id),
+        volume,
+    })
+}
+
+/// `None` when `query.unit` is the base or quote of the symbol, which the rows
+/// can be converted into by themselves. Other units go through the quote, or
+/// the base if the quote has no conversion path.
+async fn volume_converter(
+    state: &AppState,
+    query: &GetVolumeDataRequest,
+    window: &TimeWindow,
+) -> Result<Option<Converter>, ApiError> {
+    let Some((base, quote)) = split_symbol(&query.symbol) else {
+        return Ok(None);
+    };
+    if query.unit == base || query.unit == quote {
+        return Ok(None);
     }
-    query_builder.push(" FROM volume_data WHERE token_symbol=");
-    query_builder.push_bind(query.symbol);
-    if let Some(exchange_id) = query.exchange_id {
-        query_builder.push(" AND exchange_id=");
-        query_builder.push_bind(exchange_id);
+    match Converter::load(state, quote, &query.unit, window).await {
+        Ok(converter) => Ok(Some(converter)),
+        Err(ApiError::InvalidRequest(_)) => {
+            match Converter::load(state, base, &query.unit, window).await {
+                Ok(converter) => Ok(Some(converter)),
+                Err(ApiError::InvalidRequest(_)) => Err(ApiError::InvalidRequest(format!(
+                    "No conversion path from {} to {}",
+                    query.symbol, query.unit
+                ))),
+                Err(e) => Err(e),
+            }
+        }
+        Err(e) => Err(e),
     }
-    if start_time != now_time {
-        query_builder.push(" AND timestamp>=");
-        query_builder.push_bind(start_time);
+}
+
+fn volume_series_meta(
+    query: &GetVolumeDataRequest,
+    window: &TimeWindow,
+    converter: Option<&Converter>,
+) -> VolumeSeriesResponse {
+    VolumeSeriesResponse {
+        symbol: query.symbol.clone(),
+        unit: query.unit.clone(),
+        bucket: window.bucket.as_str().to_string(),
+        from: window.from.map(|t| t.timestamp()),
+        to: window.to.map(|t| t.timestamp()),
+        next_cursor: None,
+        conversion: converter.map(Converter::info),
+        data: vec![],
     }
-    query_builder
-        .push(" GROUP BY time_interval, exchange_id, token_symbol ORDER BY time_interval ASC");
-    let sql_query = query_builder.build();
-    let query_result = sql_query.fetch_all(&state.crypto_data_db).await?;
-    let mut res: Vec<Vec<String>> = vec![];
-    for item in query_result {
-        let timestamp = item.get::<DateTime<Utc>, _>("time_interval");
-        let exchange_id = item.get::<i32, _>("exchange_id");
-        let mut total_volume = item.get::<f64, _>("total_quantity");
-        if exchange_id == 1 {
-            total_volume = item.get::<f64, _>("total_volume_day");
-        }
-        let token_symbol: String = item.get::<String, _>("token_symbol");
-        let token_parts: Vec<&str> = token_symbol.splitn(2, '-').collect();
-        if token_parts.len() != 2 {
-            continue;
+}
+
+pub async fn query_volume_series(
+    state: &AppState,
+    query: &GetVolumeDataRequest,
+) -> Result<VolumeSeriesResponse, ApiError> {
+    let window = volume_window(query)?;
+    let page = volume_page(query)?;
+    let converter = volume_converter(state, query, &window).await?;
+    let mut query_builder = build_volume_query(&state.rollups, query, &window, page.as_ref());
+    let query_result = query_builder
+        .build()
+        .fetch_all(&state.crypto_data_db)
+        .await?;
+    let mut series = volume_series_meta(query, &window, converter.as_ref());
+    let query_result = match page {
+        Some(page) => {
+            let (rows, next_cursor) = page.split(query_result);
+            series.next_cursor = next_cursor;
+            rows
         }
-        let mut unit = 1.0;
+        None => query_result,
+    };
+    series.data = query_result
+        .iter()
+        .filter_map(|item| volume_point(state, item, &query.unit, None, converter.as_ref()))
+        .collect();
+    if let Some(downsample) = volume_downsample(query)? {
+        series.data = downsample.apply(
+            series.data,
+            |point| point.exchange_id,
+            |point| point.timestamp as f64,
+            |point| point.volume,
+        );
+    }
+    Ok(series)
+}
 
-        if token_parts[1] == query.unit {
-            if exchange_id != 1 {
-                unit = item.get::<f64, _>("average_price");
+/// Adds every exchange's total and percentage share to the points of its bucket.
+fn market_share(points: Vec<VolumePoint>) -> Vec<VolumeSharePoint> {
+    let mut totals: HashMap<i64, f64> = HashMap::new();
+    for point in &points {
+        *totals.entry(point.timestamp).or_default() += point.volume;
+    }
+    points
+        .into_iter()
+        .map(|point| {
+            let total_volume = totals[&point.timestamp];
+            VolumeSharePoint {
+                timestamp: point.timestamp,
+                exchange_id: point.exchange_id,
+                exchange: point.exchange,
+                volume: point.volume,
+                total_volume,
+                share: if total_volume == 0.0 {
+                    0.0
+                } else {
+                    point.volume / total_volume * 100.0
+                },
             }
-        } else if token_parts[0] == query.unit {
-            if exchange_id == 1 {
-                unit = 1.0 / item.get::<f64, _>("average_price");
+        })
+        .collect()
+}
+
+/// Cross-exchange volume per bucket in `query.unit`, with every exchange's share.
+pub async fn query_volume_shares(
+    state: &AppState,
+    query: &GetVolumeDataRequest,
+) -> Result<VolumeShareSeriesResponse, ApiError> {
+    if query.cursor.is_some() || query.limit.is_some() || query.max_points.is_some() {
+        return Err(ApiError::InvalidRequest(
+            "aggregate cannot be combined with cursor, limit or max_points".to_string(),
+        ));
+    }
+    let window = volume_window(query)?;
+    let converter = volume_converter(state, query, &window).await?;
+    let mut query_builder = build_volume_query(&state.rollups, query, &window, None);
+    let query_result = query_builder
+        .build()
+        .fetch_all(&state.crypto_data_db)
+        .await?;
+    let points = query_result
+        .iter()
+        .filter_map(|item| {
+            volume_point(
+                state,
+                item,
+                &query.unit,
+                Some(window.bucket),
+                converter.as_ref(),
+            )
+        })
+        .collect();
+    Ok(VolumeShareSeriesResponse {
+        symbol: query.symbol.clone(),
+        unit: query.unit.clone(),
+        bucket: window.bucket.as_str().to_string(),
+        from: window.from.map(|t| t.timestamp()),
+        to: window.to.map(|t| t.timestamp()),
+        conversion: converter.map(|converter| converter.info()),
+        data: market_share(points),
+    })
+}
+
+async fn respond_volume_shares(
+    state: Arc<AppState>,
+    query: GetVolumeDataRequest,
+    format: OutputFormat,
+    legacy_json: bool,
+) -> Result<Response, ApiError> {
+    let mut series = query_volume_shares(&state, &query).await?;
+    let data = std::mem::take(&mut series.data);
+    if legacy_json && format == OutputFormat::Json {
+        let res: Vec<Vec<String>> = data
+            .into_iter()
+            .map(|point| {
+                vec![
+                    point.timestamp.to_string(),
+                    point.exchange_id.to_string(),
+                    point.volume.to_string(),
+                    point.total_volume.to_string(),
+                    point.share.to_string(),
+                ]
+            })
+            .collect();
+        return Ok(vary_accept(Json(json!(res)).into_response()));
+    }
+    Ok(export_rows(format, envelope(&series), data))
+}
+
+async fn stream_volume_series(
+    state: Arc<AppState>,
+    query: GetVolumeDataRequest,
+    format: OutputFormat,
+) -> Result<Response, ApiError> {
+    let window = volume_window(&query)?;
+    let converter = volume_converter(&state, &query, &window).await?;
+    let meta = envelope(&volume_series_meta(&query, &window, converter.as_ref()));
+    Ok(stream_rows(format, meta, move |mut sink| async move {
+        let mut query_builder = build_volume_query(&state.rollups, &query, &window, None);
+        let mut rows = query_builder.build().fetch(&state.crypto_data_db);
+        while let Some(item) = rows.try_next().await? {
+            if let Some(point) = volume_point(&state, &item, &query.unit, None, converter.as_ref())
+            {
+                if !sink.send(point).await {
+                    break;
+                }
             }
-        } else {
-            continue;
         }
-        let mut res_item: Vec<String> = vec![];
-        res_item.push(timestamp.timestamp().to_string());
-        res_item.push(exchange_id.to_string());
-        res_item.push((total_volume * unit).to_string());
-        res.push(res_item);
+        Ok(sink)
+    }))
+}
+
+/// Streams the series unless it has to be collected first for downsampling or
+/// paging. `legacy_json` keeps the v1 nested-array JSON shape.
+async fn respond_volume_series(
+    state: Arc<AppState>,
+    query: GetVolumeDataRequest,
+    format: OutputFormat,
+    legacy_json: bool,
+) -> Result<Response, ApiError> {
+    if query.aggregate.unwrap_or(false) {
+        return respond_volume_shares(state, query, format, legacy_json).await;
     }
-    return Ok(Json(json!(res)));
+    let legacy_json = legacy_json && format == OutputFormat::Json;
+    let collect = volume_downsample(&query)?.is_some() || volume_page(&query)?.is_some();
+    if !legacy_json && !collect {
+        return stream_volume_series(state, query, format).await;
+    }
+    let mut series = query_volume_series(&state, &query).await?;
+    let next_cursor = series.next_cursor.clone();
+    let data = std::mem::take(&mut series.data);
+    let response = if legacy_json {
+        let res: Vec<Vec<String>> = data
+            .into_iter()
+            .map(|point| {
+                vec![
+                    point.timestamp.to_string(),
+                    point.exchange_id.to_string(),
+                    point.volume.to_string(),
+                ]
+            })
+            .collect();
+        vary_accept(Json(json!(res)).into_response())
+    } else {
+        export_rows(format, envelope(&series), data)
+    };
+    Ok(with_next_cursor(response, next_cursor))
+}
+
+pub async fn get_volume_data(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(output): Query<OutputFormatRequest>,
+    Query(query): Query<GetVolumeDataRequest>,
+) -> Result<Response, ApiError> {
+    let format = OutputFormat::negotiate(output.format.as_deref(), &headers)?;
+    respond_volume_series(state, query, format, true).await
+}
+
+pub async fn get_volume_series(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(output): Query<OutputFormatRequest>,
+    Query(query): Query<GetVolumeDataRequest>,
+) -> Result<Response, ApiError> {
+    let format = OutputFormat::negotiate(output.format.as_deref(), &headers)?;
+    respond_volume_series(state, query, format, false).await
+}
+
+/// Folds per-pair summary rows into one row per exchange in `exchanges`, in
+/// that order. Volumes are summed over every pair; the price is the latest
+/// one of the pair reported most recently. Exchanges without rows get a zero
+/// volume and no price.
+fn fold_24hr_volume(
+    exchanges: Vec<(i32, Option<String>)>,
+    rows: Vec<MarketSummary>,
+) -> Vec<ExchangeVolume24hResponse> {
+    exchanges
+        .into_iter()
+        .map(|(exchange_id, exchange)| {
+            let rows: Vec<&MarketSummary> = rows
+                .iter()
+                .filter(|row| row.exchange_id == exchange_id && row.samples > 0)
+                .collect();
+            let latest = rows
+                .iter()
+                .copied()
+                .filter(|row| row.last_price.is_some())
+                .max_by_key(|row| row.last_timestamp);
+            let mut units = rows
+                .iter()
+                .filter(|row| row.volume.is_some())
+                .map(|row| row.volume_unit.as_deref());
+            let first_unit = units.next().flatten();
+            let volume_unit = units
+                .all(|unit| unit == first_unit)
+                .then(|| first_unit.map(str::to_string))
+                .flatten();
+            ExchangeVolume24hResponse {
+                exchange_id,
+                exchange,
+                symbol: latest.and_then(|row| row.symbol.clone()),
+                // `sum` of no floats is -0.
+                volume: rows
+                    .iter()
+                    .filter_map(|row| row.volume)
+                    .fold(0.0, |a, b| a + b),
+                volume_unit,
+                price: latest.and_then(|row| row.last_price),
+                price_unit: latest.and_then(|row| row.price_unit.clone()),
+            }
+        })
+        .collect()
 }
+
+/// Last-24h volume of every enabled exchange in its quote currency, summed
+/// over its pairs, with the latest price of the pair it most recently
+/// reported.
+pub async fn query_24hr_volume(
+    state: &AppState,
+) -> Result<Vec<ExchangeVolume24hResponse>, ApiError> {
+    let summary = query_summary(
+        state,
+        &GetSummaryRequest {
+            window: Some("24h".to_string()),
+            symbols: None,
+        },
+    )
+    .await?;
+    let exchanges = state
+        .exchanges
+        .enabled()
+        .map(|exchange| (exchange.id, Some(exchange.name.clone())))
+        .collect();
+    Ok(fold_24hr_volume(exchanges, summary.data))
+}
+
 pub async fn get_24hr_volume_data(
     State(state): State<Arc<AppState>>,
-) -> Result<impl IntoResponse, ApiError> {
-    let mut res = vec![];
-    for exch_id in 0..=4 {
-        let mut volume_quantity = 0.0;
-        if exch_id != 1 {
-            let total_volume_query = format!("SELECT SUM(total_volume * price) AS volume FROM (SELECT * FROM volume_data WHERE exchange_id = {} ORDER BY timestamp DESC LIMIT 1440) a", exch_id);
-            let mut query = QueryBuilder::new(total_volume_query);
-            let query = query.build();
-            let data = query.fetch_one(&state.crypto_data_db).await?;
-            volume_quantity = data.get::<f64, _>("volume");
-        } else {
-            let total_volume_query = format!("SELECT day_total_volume FROM volume_data WHERE exchange_id = 1 ORDER BY timestamp DESC LIMIT 1");
-            let mut query = QueryBuilder::new(total_volume_query);
-            let query = query.build();
-            let data = query.fetch_one(&state.crypto_data_db).await?;
-            volume_quantity = data.get::<f64, _>("day_total_volume");
+    headers: HeaderMap,
+    Query(output): Query<OutputFormatRequest>,
+) -> Result<Response, ApiError> {
+    let format = OutputFormat::negotiate(output.format.as_deref(), &headers)?;
+    if format != OutputFormat::Json {
+        return Ok(export_rows(format, None, query_24hr_volume(&state).await?));
+    }
+    let res: Vec<Vec<String>> = query_24hr_volume(&state)
+        .await?
+        .into_iter()
+        .map(|item| {
+            vec![
+                item.exchange_id.to_string(),
+                item.volume.to_string(),
+                item.price.unwrap_or_default().to_string(),
+            ]
+        })
+        .collect();
+    Ok(vary_accept(Json(json!(res)).into_response()))
+}
+
+pub async fn get_24hr_volume_summary(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(output): Query<OutputFormatRequest>,
+) -> Result<Response, ApiError> {
+    let format = OutputFormat::negotiate(output.format.as_deref(), &headers)?;
+    Ok(export_rows(format, None, query_24hr_volume(&state).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(
+        exchange_id: i32,
+        symbol: &str,
+        volume: Option<f64>,
+        last: Option<(f64, i64)>,
+    ) -> MarketSummary {
+        let quote = symbol.split('-').nth(1).map(str::to_string);
+        MarketSummary {
+            exchange_id,
+            exchange: None,
+            symbol: Some(symbol.to_string()),
+            volume,
+            volume_unit: quote.clone(),
+            open_price: None,
+            last_price: last.map(|(price, _)| price),
+            price_change: None,
+            price_change_pct: None,
+            high: None,
+            low: None,
+            price_unit: quote,
+            samples: if last.is_some() { 10 } else { 0 },
+            last_timestamp: last.map(|(_, timestamp)| timestamp),
         }
-        let mut query = QueryBuilder::new(format!(
-            "SELECT price FROM volume_data WHERE exchange_id = {} ORDER BY timestamp DESC LIMIT 1",
-            exch_id
-        ));
-        let query = query.build();
-        let data = query.fetch_one(&state.crypto_data_db).await?;
-        let price = data.get::<f64, _>("price");
-        let mut res_item = vec![];
-        res_item.push(exch_id.to_string());
-        res_item.push(volume_quantity.to_string());
-        res_item.push(price.to_string());
-        res.push(res_item);
     }
-    return Ok(Json(json!(res)));
+
+    #[test]
+    fn folds_24hr_volume_per_exchange() {
+        let exchanges = vec![
+            (0, Some("A".to_string())),
+            (1, Some("B".to_string())),
+            (2, Some("C".to_string())),
+        ];
+        let rows = vec![
+            row(0, "BTC-USDT", Some(100.0), Some((50.0, 20))),
+            row(0, "ETH-USDT", Some(30.0), Some((3.0, 30))),
+            row(0, "SOL-USDT", None, None),
+            row(2, "BTC-USDT", Some(5.0), Some((51.0, 10))),
+            row(2, "SOL-BTC", Some(1.0), Some((0.002, 5))),
+        ];
+        let folded = fold_24hr_volume(exchanges, rows);
+        assert_eq!(
+            folded.iter().map(|row| row.exchange_id).collect::<Vec<_>>(),
+            vec![0, 1, 2]
+        );
+
+        assert_eq!(folded[0].volume, 130.0);
+        assert_eq!(folded[0].volume_unit.as_deref(), Some("USDT"));
+        assert_eq!(folded[0].symbol.as_deref(), Some("ETH-USDT"));
+        assert_eq!(folded[0].price, Some(3.0));
+
+        // No rows in the window.
+        assert_eq!(folded[1].exchange.as_deref(), Some("B"));
+        assert!(folded[1].volume == 0.0 && folded[1].volume.is_sign_positive());
+        assert_eq!(folded[1].symbol, None);
+        assert_eq!(folded[1].price, None);
+
+        // Pairs quoted in different currencies have no common unit.
+        assert_eq!(folded[2].volume, 6.0);
+        assert_eq!(folded[2].volume_unit, None);
+        assert_eq!(folded[2].symbol.as_deref(), Some("BTC-USDT"));
+    }
 }
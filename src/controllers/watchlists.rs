@@ -0,0 +1,632 @@
+use crate::{
+    dto::{
+        request::WatchlistRequest,
+        response::{
+            WatchlistEntryResponse, WatchlistEntrySummary, WatchlistResponse,
+            WatchlistSummaryResponse,
+        },
+    },
+    utils::{
+        conversion::Converter,
+        errors::ApiError,
+        exchanges::{QuoteConvention, VolumeSemantics},
+        jwt::UserClaims,
+        symbol::split_symbol,
+        time_window::{Bucket, TimeWindow},
+    },
+    AppState,
+};
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{Postgres, QueryBuilder, Row, Transaction};
+use std::{collections::HashMap, sync::Arc};
+
+const MAX_ENTRIES: usize = 100;
+/// Column sizes from `migrations/*_watchlists.sql`.
+const MAX_NAME_LENGTH: usize = 255;
+const MAX_SYMBOL_LENGTH: usize = 32;
+const MAX_UNIT_LENGTH: usize = 16;
+
+fn check_length(field: &str, value: &str, max: usize) -> Result<(), ApiError> {
+    // VARCHAR limits count characters, not bytes.
+    if value.chars().count() > max {
+        return Err(ApiError::InvalidRequest(format!(
+            "{} must be at most {} characters",
+            field, max
+        )));
+    }
+    Ok(())
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct Watchlist {
+    id: i64,
+    name: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+fn watchlist_response(
+    watchlist: Watchlist,
+    entries: Vec<WatchlistEntryResponse>,
+) -> WatchlistResponse {
+    WatchlistResponse {
+        id: watchlist.id,
+        name: watchlist.name,
+        entries,
+        created_at: watchlist.created_at.timestamp(),
+        updated_at: watchlist.updated_at.timestamp(),
+    }
+}
+
+fn not_found(id: i64) -> ApiError {
+    ApiError::NotFound(format!("Watchlist {} not found", id))
+}
+
+fn validate(req: &WatchlistRequest) -> Result<(), ApiError> {
+    if req.name.trim().is_empty() {
+        return Err(ApiError::InvalidRequest("name is required".to_string()));
+    }
+    check_length("name", req.name.trim(), MAX_NAME_LENGTH)?;
+    if req.entries.len() > MAX_ENTRIES {
+        return Err(ApiError::InvalidRequest(format!(
+            "A watchlist holds at most {} entries",
+            MAX_ENTRIES
+        )));
+    }
+    for entry in &req.entries {
+        if entry.symbol.trim().is_empty() {
+            return Err(ApiError::InvalidRequest(
+                "Every entry needs a symbol".to_string(),
+            ));
+        }
+        check_length("symbol", entry.symbol.trim(), MAX_SYMBOL_LENGTH)?;
+        if let Some(unit) = &entry.unit {
+            check_length("unit", unit, MAX_UNIT_LENGTH)?;
+        }
+        if entry.exchange_id.is_some_and(|exchange_id| exchange_id < 0) {
+            return Err(ApiError::InvalidRequest(format!(
+                "Invalid exchange_id for {}",
+                entry.symbol
+            )));
+        }
+    }
+    Ok(())
+}
+
+async fn find_watchlist(
+    state: &AppState,
+    user: &UserClaims,
+    id: i64,
+) -> Result<Watchlist, ApiError> {
+    sqlx::query_as::<_, Watchlist>("SELECT * FROM watchlists WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user.uid)
+        .fetch_optional(&state.db)
+        .await?
+        .ok_or_else(|| not_found(id))
+}
+
+async fn load_entries(
+    state: &AppState,
+    watchlist_ids: Vec<i64>,
+) -> Result<Vec<WatchlistEntryResponse>, ApiError> {
+    Ok(sqlx::query_as::<_, WatchlistEntryResponse>(
+        "SELECT watchlist_id, position, symbol, exchange_id, unit FROM watchlist_entries \
+         WHERE watchlist_id = ANY($1) ORDER BY watchlist_id, position",
+    )
+    .bind(watchlist_ids)
+    .fetch_all(&state.db)
+    .await?)
+}
+
+async fn insert_entries(
+    tx: &mut Transaction<'_, Postgres>,
+    watchlist_id: i64,
+    req: &WatchlistRequest,
+) -> Result<(), ApiError> {
+    if req.entries.is_empty() {
+        return Ok(());
+    }
+    let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
+        "INSERT INTO watchlist_entries (watchlist_id, position, symbol, exchange_id, unit) ",
+    );
+    query_builder.push_values(
+        req.entries.iter().enumerate(),
+        |mut row, (position, entry)| {
+            row.push_bind(watchlist_id)
+                .push_bind(position as i32)
+                .push_bind(entry.symbol.trim().to_string())
+                .push_bind(entry.exchange_id)
+                .push_bind(entry.unit.clone());
+        },
+    );
+    query_builder.build().execute(&mut **tx).await?;
+    Ok(())
+}
+
+/// Pairs every watchlist with its entries, which are ordered by watchlist.
+fn group_entries(
+    watchlists: Vec<Watchlist>,
+    mut entries: Vec<WatchlistEntryResponse>,
+) -> Vec<WatchlistResponse> {
+    watchlists
+        .into_iter()
+        .map(|watchlist| {
+            let (own, rest) = entries
+                .drain(..)
+                .partition(|entry| entry.watchlist_id == watchlist.id);
+            entries = rest;
+            watchlist_response(watchlist, own)
+        })
+        .collect()
+}
+
+pub async fn list_watchlists(
+    State(state): State<Arc<AppState>>,
+    user: UserClaims,
+) -> Result<Response, ApiError> {
+    let watchlists =
+        sqlx::query_as::<_, Watchlist>("SELECT * FROM watchlists WHERE user_id = $1 ORDER BY id")
+            .bind(user.uid)
+            .fetch_all(&state.db)
+            .await?;
+    let entries = load_entries(&state, watchlists.iter().map(|w| w.id).collect()).await?;
+    Ok(Json(group_entries(watchlists, entries)).into_response())
+}
+
+pub async fn create_watchlist(
+    State(state): State<Arc<AppState>>,
+    user: UserClaims,
+    Json(req): Json<WatchlistRequest>,
+) -> Result<Response, ApiError> {
+    validate(&req)?;
+    let mut tx = state.db.begin().await?;
+    let watchlist = sqlx::query_as::<_, Watchlist>(
+        "INSERT INTO watchlists (user_id, name) VALUES ($1, $2) RETURNING *",
+    )
+    .bind(user.uid)
+    .bind(req.name.trim())
+    .fetch_one(&mut *tx)
+    .await?;
+    insert_entries(&mut tx, watchlist.id, &req).await?;
+    tx.commit().await?;
+    let entries = load_entries(&state, vec![watchlist.id]).await?;
+    Ok((
+        StatusCode::CREATED,
+        Json(watchlist_response(watchlist, entries)),
+    )
+        .into_response())
+}
+
+pub async fn get_watchlist(
+    State(state): State<Arc<AppState>>,
+    user: UserClaims,
+    Path(id): Path<i64>,
+) -> Result<Response, ApiError> {
+    let watchlist = find_watchlist(&state, &user, id).await?;
+    let entries = load_entries(&state, vec![watchlist.id]).await?;
+    Ok(Json(watchlist_response(watchlist, entries)).into_response())
+}
+
+/// Renames the watchlist and replaces its entries.
+pub async fn update_watchlist(
+    State(state): State<Arc<AppState>>,
+    user: UserClaims,
+    Path(id): Path<i64>,
+    Json(req): Json<WatchlistRequest>,
+) -> Result<Response, ApiError> {
+    validate(&req)?;
+    let mut tx = state.db.begin().await?;
+    let watchlist = sqlx::query_as::<_, Watchlist>(
+        "UPDATE watchlists SET name = $3, updated_at = NOW() WHERE id = $1 AND user_id = $2 RETURNING *",
+    )
+    .bind(id)
+    .bind(user.uid)
+    .bind(req.name.trim())
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| not_found(id))?;
+    sqlx::query("DELETE FROM watchlist_entries WHERE watchlist_id = $1")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    insert_entries(&mut tx, id, &req).await?;
+    tx.commit().await?;
+    let entries = load_entries(&state, vec![id]).await?;
+    Ok(Json(watchlist_response(watchlist, entries)).into_response())
+}
+
+pub async fn delete_watchlist(
+    State(state): State<Arc<AppState>>,
+    user: UserClaims,
+    Path(id): Path<i64>,
+) -> Result<Response, ApiError> {
+    let result = sqlx::query("DELETE FROM watchlists WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user.uid)
+        .execute(&state.db)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(not_found(id));
+    }
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// Latest figures of one pair on one exchange.
+struct MarketRow {
+    exchange_id: i32,
+    symbol: String,
+    price: f64,
+    price_24h_ago: Option<f64>,
+    /// Last-24h volume in the quote currency.
+    volume: Option<f64>,
+    timestamp: i64,
+}
+
+struct BalanceRow {
+    exchange_id: i32,
+    token: String,
+    wallet_balance: f64,
+    transfer_balance: f64,
+    timestamp: i64,
+}
+
+/// One query for every pair of the watchlist: the latest row, the last row at
+/// least 24h old and the last-24h volume of each exchange.
+async fn query_market(state: &AppState, pairs: Vec<String>) -> Result<Vec<MarketRow>, ApiError> {
+    let rows = sqlx::query(
+        "WITH latest AS ( \
+            SELECT DISTINCT ON (exchange_id, token_symbol) exchange_id, token_symbol, price, day_total_volume, timestamp \
+            FROM volume_data WHERE token_symbol = ANY($1) ORDER BY exchange_id, token_symbol, timestamp DESC \
+         ), previous AS ( \
+            SELECT DISTINCT ON (exchange_id, token_symbol) exchange_id, token_symbol, price \
+            FROM volume_data WHERE token_symbol = ANY($1) AND timestamp <= NOW() - INTERVAL '24 hours' \
+            ORDER BY exchange_id, token_symbol, timestamp DESC \
+         ), day AS ( \
+            SELECT exchange_id, token_symbol, SUM(total_volume) AS volume, SUM(total_volume * price) AS quote_volume \
+            FROM volume_data WHERE token_symbol = ANY($1) AND timestamp > NOW() - INTERVAL '24 hours' \
+            GROUP BY exchange_id, token_symbol \
+         ) \
+         SELECT l.exchange_id, l.token_symbol, l.price, l.day_total_volume, l.timestamp, \
+            p.price AS price_24h_ago, d.volume, d.quote_volume \
+         FROM latest l \
+         LEFT JOIN previous p USING (exchange_id, token_symbol) \
+         LEFT JOIN day d USING (exchange_id, token_symbol)",
+    )
+    .bind(pairs)
+    .fetch_all(&state.crypto_data_db)
+    .await?;
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let exchange_id = row.get::<i32, _>("exchange_id");
+            let price = row.get::<f64, _>("price");
+            let convention = state.exchanges.quote_convention(exchange_id);
+            let volume = match state.exchanges.volume_semantics(exchange_id) {
+                VolumeSemantics::Rolling24h => {
+                    row.get::<Option<f64>, _>("day_total_volume")
+                        .map(|volume| match convention {
+                            QuoteConvention::Base => volume * price,
+                            QuoteConvention::Quote => volume,
+                        })
+                }
+                VolumeSemantics::PerRow => match convention {
+                    QuoteConvention::Base => row.get::<Option<f64>, _>("quote_volume"),
+                    QuoteConvention::Quote => row.get::<Option<f64>, _>("volume"),
+                },
+            };
+            MarketRow {
+                exchange_id,
+                symbol: row.get::<String, _>("token_symbol"),
+                price,
+                price_24h_ago: row.get::<Option<f64>, _>("price_24h_ago"),
+                volume,
+                timestamp: row.get::<DateTime<Utc>, _>("timestamp").timestamp(),
+            }
+        })
+        .collect())
+}
+
+async fn query_balances(
+    state: &AppState,
+    tokens: Vec<String>,
+) -> Result<Vec<BalanceRow>, ApiError> {
+    let rows = sqlx::query(
+        "SELECT DISTINCT ON (exchange_id, token_symbol) exchange_id, token_symbol, wallet_balance, transfer_balance, timestamp \
+         FROM balance_data WHERE token_symbol = ANY($1) ORDER BY exchange_id, token_symbol, timestamp DESC",
+    )
+    .bind(tokens)
+    .fetch_all(&state.crypto_data_db)
+    .await?;
+    Ok(rows
+        .iter()
+        .map(|row| BalanceRow {
+            exchange_id: row.get::<i32, _>("exchange_id"),
+            token: row.get::<String, _>("token_symbol"),
+            wallet_balance: row.get::<f64, _>("wallet_balance"),
+            transfer_balance: row.get::<f64, _>("transfer_balance"),
+            timestamp: row.get::<DateTime<Utc>, _>("timestamp").timestamp(),
+        })
+        .collect())
+}
+
+/// Current rate from one unit to another, `None` when nothing connects them.
+struct Rates<'a> {
+    state: &'a AppState,
+    window: TimeWindow,
+    now: i64,
+    cache: HashMap<(String, String), Option<f64>>,
+}
+
+impl Rates<'_> {
+    async fn get(&mut self, from: &str, to: &str) -> Result<Option<f64>, ApiError> {
+        if from == to {
+            return Ok(Some(1.0));
+        }
+        let key = (from.to_string(), to.to_string());
+        if let Some(rate) = self.cache.get(&key) {
+            return Ok(*rate);
+        }
+        let rate = match Converter::load(self.state, from, to, &self.window).await {
+            Ok(converter) => converter.rate_at(self.now),
+            Err(ApiError::InvalidRequest(_)) => None,
+            Err(e) => return Err(e),
+        };
+        self.cache.insert(key, rate);
+        Ok(rate)
+    }
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), value| (sum + value, count + 1));
+    (count > 0).then(|| sum / count as f64)
+}
+
+/// Balances are kept per token, so a pair entry shows the balance of its base.
+fn balance_token(symbol: &str) -> String {
+    split_symbol(symbol)
+        .map(|(base, _)| base.to_string())
+        .unwrap_or_else(|| symbol.to_string())
+}
+
+/// Volumes and balances are shown in the entry's unit, the pair's quote
+/// currency or the token itself, whichever is given first.
+fn entry_unit(entry: &WatchlistEntryResponse) -> String {
+    entry
+        .unit
+        .clone()
+        .or_else(|| split_symbol(&entry.symbol).map(|(_, quote)| quote.to_string()))
+        .unwrap_or_else(|| balance_token(&entry.symbol))
+}
+
+/// Mean price over the exchanges, and its 24h change over the exchanges that
+/// also have a price from 24h ago.
+fn price_change(market: &[&MarketRow]) -> (Option<f64>, Option<f64>) {
+    let price = mean(market.iter().map(|row| row.price));
+    let with_history: Vec<&&MarketRow> = market
+        .iter()
+        .filter(|row| row.price_24h_ago.is_some())
+        .collect();
+    let price_change_24h_pct = match (
+        mean(with_history.iter().map(|row| row.price)),
+        mean(with_history.iter().filter_map(|row| row.price_24h_ago)),
+    ) {
+        (Some(current), Some(previous)) if previous != 0.0 => {
+            Some((current - previous) / previous * 100.0)
+        }
+        _ => None,
+    };
+    (price, price_change_24h_pct)
+}
+
+pub async fn get_watchlist_summary(
+    State(state): State<Arc<AppState>>,
+    user: UserClaims,
+    Path(id): Path<i64>,
+) -> Result<Response, ApiError> {
+    let watchlist = find_watchlist(&state, &user, id).await?;
+    let entries = load_entries(&state, vec![watchlist.id]).await?;
+    let mut pairs: Vec<String> = entries.iter().map(|entry| entry.symbol.clone()).collect();
+    let mut tokens: Vec<String> = entries
+        .iter()
+        .map(|entry| balance_token(&entry.symbol))
+        .collect();
+    pairs.sort();
+    pairs.dedup();
+    tokens.sort();
+    tokens.dedup();
+    let market = query_market(&state, pairs).await?;
+    let balances = query_balances(&state, tokens).await?;
+
+    let now = Utc::now();
+    let mut rates = Rates {
+        state: &state,
+        window: TimeWindow {
+            from: Some(now - Duration::hours(24)),
+            to: None,
+            bucket: Bucket::OneHour,
+        },
+        now: now.timestamp(),
+        cache: HashMap::new(),
+    };
+    let mut data = vec![];
+    for entry in &entries {
+        // Without an exchange, every enabled exchange counts.
+        let included = |exchange_id: i32| match entry.exchange_id {
+            Some(id) => id == exchange_id,
+            None => state
+                .exchanges
+                .get(exchange_id)
+                .is_none_or(|exchange| exchange.enabled),
+        };
+        let market: Vec<&MarketRow> = market
+            .iter()
+            .filter(|row| row.symbol == entry.symbol && included(row.exchange_id))
+            .collect();
+        let token = balance_token(&entry.symbol);
+        let balances: Vec<&BalanceRow> = balances
+            .iter()
+            .filter(|row| row.token == token && included(row.exchange_id))
+            .collect();
+        let quote = split_symbol(&entry.symbol).map(|(_, quote)| quote.to_string());
+        let unit = entry_unit(entry);
+        let (price, price_change_24h_pct) = price_change(&market);
+        let volumes: Vec<f64> = market.iter().filter_map(|row| row.volume).collect();
+        let volume_24h = match (&quote, volumes.is_empty()) {
+            (Some(quote), false) => rates
+                .get(quote, &unit)
+                .await?
+                .map(|rate| volumes.iter().sum::<f64>() * rate),
+            _ => None,
+        };
+        let (wallet_balance, transfer_balance) = if balances.is_empty() {
+            (None, None)
+        } else {
+            let rate = rates.get(&token, &unit).await?;
+            let sum = |value: fn(&BalanceRow) -> f64| {
+                rate.map(|rate| balances.iter().map(|row| value(row)).sum::<f64>() * rate)
+            };
+            (
+                sum(|row| row.wallet_balance),
+                sum(|row| row.transfer_balance),
+            )
+        };
+        data.push(WatchlistEntrySummary {
+            position: entry.position,
+            symbol: entry.symbol.clone(),
+            exchange_id: entry.exchange_id,
+            exchange: entry.exchange_id.and_then(|id| state.exchanges.name(id)),
+            unit,
+            price,
+            price_unit: price.and(quote),
+            price_change_24h_pct,
+            volume_24h,
+            wallet_balance,
+            transfer_balance,
+            price_timestamp: market.iter().map(|row| row.timestamp).max(),
+            balance_timestamp: balances.iter().map(|row| row.timestamp).max(),
+        });
+    }
+    Ok(Json(WatchlistSummaryResponse {
+        id: watchlist.id,
+        name: watchlist.name,
+        timestamp: now.timestamp(),
+        data,
+    })
+    .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dto::request::WatchlistEntryRequest;
+
+    fn request(name: &str, symbols: &[&str]) -> WatchlistRequest {
+        WatchlistRequest {
+            name: name.to_string(),
+            entries: symbols
+                .iter()
+                .map(|symbol| WatchlistEntryRequest {
+                    symbol: symbol.to_string(),
+                    exchange_id: None,
+                    unit: None,
+                })
+                .collect(),
+        }
+    }
+
+    fn entry(watchlist_id: i64, position: i32, symbol: &str) -> WatchlistEntryResponse {
+        WatchlistEntryResponse {
+            watchlist_id,
+            position,
+            symbol: symbol.to_string(),
+            exchange_id: None,
+            unit: None,
+        }
+    }
+
+    fn market(price: f64, price_24h_ago: Option<f64>) -> MarketRow {
+        MarketRow {
+            exchange_id: 1,
+            symbol: "BTC-USDT".to_string(),
+            price,
+            price_24h_ago,
+            volume: None,
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn validation() {
+        assert!(validate(&request("Majors", &["BTC-USDT", "ETH"])).is_ok());
+        assert!(validate(&request("  ", &[])).is_err());
+        assert!(validate(&request(&"n".repeat(MAX_NAME_LENGTH + 1), &[])).is_err());
+        // Limits count characters, not bytes.
+        assert!(validate(&request(&"é".repeat(MAX_NAME_LENGTH), &[])).is_ok());
+        assert!(validate(&request("Majors", &[" "])).is_err());
+        assert!(validate(&request("Majors", &[&"S".repeat(MAX_SYMBOL_LENGTH + 1)])).is_err());
+        assert!(validate(&request("Majors", &vec!["BTC"; MAX_ENTRIES + 1])).is_err());
+
+        let mut req = request("Majors", &["BTC"]);
+        req.entries[0].unit = Some("U".repeat(MAX_UNIT_LENGTH + 1));
+        assert!(validate(&req).is_err());
+        req.entries[0].unit = None;
+        req.entries[0].exchange_id = Some(-1);
+        assert!(validate(&req).is_err());
+    }
+
+    #[test]
+    fn entries_are_grouped_by_watchlist() {
+        let watchlist = |id| Watchlist {
+            id,
+            name: format!("List {}", id),
+            created_at: DateTime::from_timestamp(0, 0).unwrap(),
+            updated_at: DateTime::from_timestamp(0, 0).unwrap(),
+        };
+        let grouped = group_entries(
+            vec![watchlist(1), watchlist(2), watchlist(3)],
+            vec![entry(1, 0, "BTC"), entry(1, 1, "ETH"), entry(3, 0, "SOL")],
+        );
+        let symbols: Vec<Vec<&str>> = grouped
+            .iter()
+            .map(|w| w.entries.iter().map(|e| e.symbol.as_str()).collect())
+            .collect();
+        assert_eq!(symbols, vec![vec!["BTC", "ETH"], vec![], vec!["SOL"]]);
+        assert_eq!(grouped[2].id, 3);
+    }
+
+    #[test]
+    fn units_fall_back_to_the_quote_then_the_token() {
+        let mut pair = entry(1, 0, "BTC-USDT");
+        assert_eq!(entry_unit(&pair), "USDT");
+        pair.unit = Some("EUR".to_string());
+        assert_eq!(entry_unit(&pair), "EUR");
+        assert_eq!(entry_unit(&entry(1, 0, "BTC")), "BTC");
+        assert_eq!(balance_token("BTC-USDT"), "BTC");
+        assert_eq!(balance_token("BTC"), "BTC");
+    }
+
+    #[test]
+    fn price_change_only_uses_exchanges_with_history() {
+        let rows = [
+            market(110.0, Some(100.0)),
+            market(90.0, Some(100.0)),
+            market(130.0, None),
+        ];
+        let (price, change) = price_change(&rows.iter().collect::<Vec<_>>());
+        assert_eq!(price, Some(110.0));
+        assert_eq!(change, Some(0.0));
+
+        let (price, change) = price_change(&[&market(105.0, Some(100.0))]);
+        assert_eq!(price, Some(105.0));
+        assert_eq!(change, Some(5.0));
+        assert_eq!(price_change(&[&market(1.0, Some(0.0))]).1, None);
+        assert_eq!(price_change(&[&market(1.0, None)]), (Some(1.0), None));
+        assert_eq!(price_change(&[]), (None, None));
+    }
+}
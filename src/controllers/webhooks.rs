@@ -0,0 +1,168 @@
+use crate::{
+    dto::{request::WebhookRequest, response::WebhookSecretResponse},
+    utils::{
+        errors::ApiError,
+        jwt::UserClaims,
+        webhooks::{self, Webhook, WebhookDelivery, EVENT_TYPES},
+    },
+    AppState,
+};
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use std::sync::Arc;
+
+const SECRET_LENGTH: usize = 32;
+
+fn validate(req: &WebhookRequest) -> Result<Vec<String>, ApiError> {
+    webhooks::check_url(&req.url).map_err(ApiError::InvalidRequest)?;
+    if req.secret.as_deref().is_some_and(str::is_empty) {
+        return Err(ApiError::InvalidRequest(
+            "secret must not be empty".to_string(),
+        ));
+    }
+    let event_types = req.event_types.clone().unwrap_or_default();
+    if let Some(unknown) = event_types
+        .iter()
+        .find(|event_type| !EVENT_TYPES.contains(&event_type.as_str()))
+    {
+        return Err(ApiError::InvalidRequest(format!(
+            "Unknown event type: {}, expected one of {}",
+            unknown,
+            EVENT_TYPES.join(", ")
+        )));
+    }
+    Ok(event_types)
+}
+
+fn not_found(id: i64) -> ApiError {
+    ApiError::NotFound(format!("Webhook {} not found", id))
+}
+
+async fn find_webhook(state: &AppState, user: &UserClaims, id: i64) -> Result<Webhook, ApiError> {
+    sqlx::query_as::<_, Webhook>("SELECT * FROM webhooks WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user.uid)
+        .fetch_optional(&state.db)
+        .await?
+        .ok_or_else(|| not_found(id))
+}
+
+pub async fn list_webhooks(
+    State(state): State<Arc<AppState>>,
+    user: UserClaims,
+) -> Result<Response, ApiError> {
+    let webhooks =
+        sqlx::query_as::<_, Webhook>("SELECT * FROM webhooks WHERE user_id = $1 ORDER BY id")
+            .bind(user.uid)
+            .fetch_all(&state.db)
+            .await?;
+    Ok(Json(webhooks).into_response())
+}
+
+pub async fn create_webhook(
+    State(state): State<Arc<AppState>>,
+    user: UserClaims,
+    Json(req): Json<WebhookRequest>,
+) -> Result<Response, ApiError> {
+    let event_types = validate(&req)?;
+    let secret = req.secret.unwrap_or_else(|| {
+        thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(SECRET_LENGTH)
+            .map(char::from)
+            .collect()
+    });
+    let webhook = sqlx::query_as::<_, Webhook>(
+        "INSERT INTO webhooks (user_id, url, secret, event_types, enabled) VALUES ($1, $2, $3, $4, $5) RETURNING *",
+    )
+    .bind(user.uid)
+    .bind(req.url)
+    .bind(&secret)
+    .bind(event_types)
+    .bind(req.enabled.unwrap_or(true))
+    .fetch_one(&state.db)
+    .await?;
+    Ok((
+        StatusCode::CREATED,
+        Json(WebhookSecretResponse { webhook, secret }),
+    )
+        .into_response())
+}
+
+pub async fn get_webhook(
+    State(state): State<Arc<AppState>>,
+    user: UserClaims,
+    Path(id): Path<i64>,
+) -> Result<Response, ApiError> {
+    Ok(Json(find_webhook(&state, &user, id).await?).into_response())
+}
+
+pub async fn update_webhook(
+    State(state): State<Arc<AppState>>,
+    user: UserClaims,
+    Path(id): Path<i64>,
+    Json(req): Json<WebhookRequest>,
+) -> Result<Response, ApiError> {
+    let event_types = validate(&req)?;
+    let webhook = sqlx::query_as::<_, Webhook>(
+        "UPDATE webhooks SET url = $3, secret = COALESCE($4, secret), event_types = $5, enabled = $6, updated_at = NOW() \
+         WHERE id = $1 AND user_id = $2 RETURNING *",
+    )
+    .bind(id)
+    .bind(user.uid)
+    .bind(req.url)
+    .bind(req.secret)
+    .bind(event_types)
+    .bind(req.enabled.unwrap_or(true))
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or_else(|| not_found(id))?;
+    Ok(Json(webhook).into_response())
+}
+
+pub async fn delete_webhook(
+    State(state): State<Arc<AppState>>,
+    user: UserClaims,
+    Path(id): Path<i64>,
+) -> Result<Response, ApiError> {
+    let result = sqlx::query("DELETE FROM webhooks WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user.uid)
+        .execute(&state.db)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(not_found(id));
+    }
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+pub async fn get_webhook_deliveries(
+    State(state): State<Arc<AppState>>,
+    user: UserClaims,
+    Path(id): Path<i64>,
+) -> Result<Response, ApiError> {
+    let webhook = find_webhook(&state, &user, id).await?;
+    let deliveries = sqlx::query_as::<_, WebhookDelivery>(
+        "SELECT * FROM webhook_deliveries WHERE webhook_id = $1 ORDER BY created_at DESC, id DESC LIMIT 100",
+    )
+    .bind(webhook.id)
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(deliveries).into_response())
+}
+
+pub async fn redeliver_webhook(
+    State(state): State<Arc<AppState>>,
+    user: UserClaims,
+    Path((id, delivery_id)): Path<(i64, i64)>,
+) -> Result<Response, ApiError> {
+    let webhook = find_webhook(&state, &user, id).await?;
+    let delivery = webhooks::redeliver(&state.db, webhook.id, delivery_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Delivery {} not found", delivery_id)))?;
+    Ok((StatusCode::ACCEPTED, Json(delivery)).into_response())
+}
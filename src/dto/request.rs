@@ -1,3 +1,4 @@
+use crate::utils::alerts::{Comparator, Metric};
 use serde::Deserialize;
 #[derive(Debug, Deserialize)]
 pub struct AuthRequest {
@@ -10,36 +11,135 @@ pub struct LoginRequest {
     pub password: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct OutputFormatRequest {
+    pub format: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct GetVolumeDataRequest {
     pub symbol: String,
-    pub interval: String,
+    pub interval: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub bucket: Option<String>,
+    pub max_points: Option<usize>,
+    pub downsample: Option<String>,
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
     pub exchange_id: Option<i64>,
     pub unit: String,
+    /// Sum all exchanges per bucket and report each exchange's share.
+    pub aggregate: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetSummaryRequest {
+    /// Trailing window such as `24h` or `7d`, 24 hours by default.
+    pub window: Option<String>,
+    /// Comma-separated pairs; every pair with data in the window when omitted.
+    pub symbols: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct GetBalanceDataRequest {
     pub symbol: String,
-    pub interval: String,
+    pub interval: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub bucket: Option<String>,
+    pub max_points: Option<usize>,
+    pub downsample: Option<String>,
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
     pub exchange_id: Option<i64>,
+    /// Converts balances into this unit, defaults to the token itself.
+    pub unit: Option<String>,
+    /// How snapshots in a bucket are combined: last (default), first, avg, min or max.
+    pub agg: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
-pub struct GetLatestBalanceDataRequest {
-    pub exchange_id: i64,
+pub struct GetCandlesRequest {
+    pub symbol: String,
+    pub interval: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub bucket: Option<String>,
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+    pub exchange_id: Option<i64>,
 }
 
 #[derive(Debug, Deserialize)]
-pub struct Get24HVolumeDataRequest {
+pub struct GetIndicatorsRequest {
     pub symbol: String,
-    pub interval: String,
+    pub interval: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub bucket: Option<String>,
+    pub exchange_id: Option<i64>,
+    /// Comma-separated specs such as `sma:20,ema:50,vwap,rsi:14,bb:20:2`.
+    pub indicators: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetBalanceSnapshotRequest {
+    /// Unix seconds or RFC 3339, now when omitted.
+    pub at: Option<String>,
+    /// Earlier timestamp to diff the `at` snapshot against.
+    pub compare_to: Option<String>,
+    pub exchange_id: Option<i64>,
+    /// Comma-separated tokens, every token when omitted.
+    pub symbols: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetAnomaliesRequest {
+    /// Every symbol when omitted.
+    pub symbol: Option<String>,
+    pub exchange_id: Option<i64>,
+    /// `volume` (default), `wallet_balance` or `transfer_balance`.
+    pub metric: Option<String>,
+    pub interval: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub bucket: Option<String>,
+    /// Number of preceding buckets each point is compared against.
+    pub window: Option<usize>,
+    /// Score from which a point is flagged.
+    pub sensitivity: Option<f64>,
+    /// `mad` (default) or `zscore`.
+    pub method: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchQueryRequest {
+    /// Client-chosen key of the result.
+    pub id: String,
+    /// volume, balance, candles or summary.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The query string fields of the matching endpoint.
+    #[serde(default)]
+    pub params: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchRequest {
+    pub queries: Vec<BatchQueryRequest>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetLatestBalanceDataRequest {
+    pub exchange_id: i64,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct SignupRequest {
     pub email: String,
     pub password: String,
+    #[allow(dead_code)]
     pub password_confirmation: String,
 }
 
@@ -53,3 +153,82 @@ pub struct ConfirmRequest {
 pub struct ForgotPasswordRequest {
     pub email: String,
 }
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamAction {
+    Subscribe,
+    Unsubscribe,
+}
+
+/// Client message on the `/api/v1/stream` WebSocket.
+#[derive(Debug, Deserialize)]
+pub struct StreamCommand {
+    pub action: StreamAction,
+    pub channels: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetEventsRequest {
+    /// Comma separated, e.g. `volume:1:BTC-USDT,balance:1`.
+    pub channels: String,
+    pub last_event_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetBalanceFlowsRequest {
+    pub symbol: String,
+    pub interval: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub bucket: Option<String>,
+    pub exchange_id: Option<i64>,
+    /// Which balance the flows are computed on: wallet, transfer or total (default).
+    pub balance: Option<String>,
+    /// Adds the flows of all exchanges together per bucket.
+    pub sum_exchanges: Option<bool>,
+    /// Values flows in this unit, defaults to the token itself.
+    pub unit: Option<String>,
+}
+
+/// Body of `POST /api/v1/alerts` and `PUT /api/v1/alerts/:id`.
+#[derive(Debug, Deserialize)]
+pub struct AlertRuleRequest {
+    pub name: Option<String>,
+    pub metric: Metric,
+    pub symbol: String,
+    /// Evaluates across all exchanges when omitted.
+    pub exchange_id: Option<i32>,
+    pub comparator: Comparator,
+    pub threshold: f64,
+    pub window_secs: Option<i32>,
+    pub cooldown_secs: Option<i32>,
+    pub enabled: Option<bool>,
+}
+
+/// Body of `POST /api/v1/webhooks` and `PUT /api/v1/webhooks/:id`.
+#[derive(Debug, Deserialize)]
+pub struct WebhookRequest {
+    pub url: String,
+    /// Generated on creation and kept on update when omitted.
+    pub secret: Option<String>,
+    /// Event types to deliver, all of them when empty or omitted.
+    pub event_types: Option<Vec<String>>,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WatchlistEntryRequest {
+    pub symbol: String,
+    /// Covers all exchanges when omitted.
+    pub exchange_id: Option<i32>,
+    pub unit: Option<String>,
+}
+
+/// Body of `POST /api/v1/watchlists` and `PUT /api/v1/watchlists/:id`.
+/// Entries are kept in the order given.
+#[derive(Debug, Deserialize)]
+pub struct WatchlistRequest {
+    pub name: String,
+    pub entries: Vec<WatchlistEntryRequest>,
+}
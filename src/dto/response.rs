@@ -1,4 +1,11 @@
+use crate::utils::{
+    conversion::ConversionInfo,
+    export::{Cell, ColumnType, ExportRow},
+    live::LiveEvent,
+    webhooks::Webhook,
+};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 #[derive(Debug, Deserialize)]
 pub struct GoogleUserProfileResponse {
     pub email: String,
@@ -8,7 +15,8 @@ pub struct GoogleUserProfileResponse {
 #[derive(Debug, Clone, Default, Serialize)]
 pub struct JWTTokenResponse {
     pub api_token: String,
-    pub refreshToken: String,
+    #[serde(rename = "refreshToken")]
+    pub refresh_token: String,
     pub access_token: String,
 }
 
@@ -22,3 +30,705 @@ pub struct UserInfoResponse {
     pub last_name: String,
     pub pic: String,
 }
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VolumePoint {
+    pub timestamp: i64,
+    pub exchange_id: i32,
+    pub exchange: Option<String>,
+    pub volume: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VolumeSeriesResponse {
+    pub symbol: String,
+    pub unit: String,
+    pub bucket: String,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conversion: Option<ConversionInfo>,
+    pub data: Vec<VolumePoint>,
+}
+
+/// One exchange's volume in a bucket next to the all-exchange total.
+#[derive(Debug, Clone, Serialize)]
+pub struct VolumeSharePoint {
+    pub timestamp: i64,
+    pub exchange_id: i32,
+    pub exchange: Option<String>,
+    pub volume: f64,
+    pub total_volume: f64,
+    /// Percentage of `total_volume`, 0 to 100.
+    pub share: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VolumeShareSeriesResponse {
+    pub symbol: String,
+    pub unit: String,
+    pub bucket: String,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conversion: Option<ConversionInfo>,
+    pub data: Vec<VolumeSharePoint>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BalancePoint {
+    pub timestamp: i64,
+    pub exchange_id: i32,
+    pub exchange: Option<String>,
+    pub token: String,
+    pub wallet_balance: f64,
+    pub transfer_balance: f64,
+}
+
+/// Last known balance of a token on an exchange at the snapshot time, and how
+/// many seconds before that time it was reported.
+#[derive(Debug, Clone, Serialize)]
+pub struct BalanceSnapshotPoint {
+    pub exchange_id: i32,
+    pub exchange: Option<String>,
+    pub token: String,
+    pub wallet_balance: f64,
+    pub transfer_balance: f64,
+    pub timestamp: i64,
+    pub staleness_secs: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BalanceSnapshotResponse {
+    pub at: i64,
+    pub exchange_id: Option<i64>,
+    pub symbols: Option<Vec<String>>,
+    pub data: Vec<BalanceSnapshotPoint>,
+}
+
+/// Balances of a token at two times. The `from_` side is `None` when the token
+/// had not been reported yet at `from`.
+#[derive(Debug, Clone, Serialize)]
+pub struct BalanceDiffPoint {
+    pub exchange_id: i32,
+    pub exchange: Option<String>,
+    pub token: String,
+    pub from_wallet_balance: Option<f64>,
+    pub to_wallet_balance: f64,
+    pub wallet_change: Option<f64>,
+    pub from_transfer_balance: Option<f64>,
+    pub to_transfer_balance: f64,
+    pub transfer_change: Option<f64>,
+    pub from_timestamp: Option<i64>,
+    pub to_timestamp: i64,
+    pub from_staleness_secs: Option<i64>,
+    pub to_staleness_secs: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BalanceDiffResponse {
+    pub from: i64,
+    pub to: i64,
+    pub exchange_id: Option<i64>,
+    pub symbols: Option<Vec<String>>,
+    pub data: Vec<BalanceDiffPoint>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BalanceSeriesResponse {
+    pub symbol: String,
+    pub unit: String,
+    pub bucket: String,
+    pub agg: String,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conversion: Option<ConversionInfo>,
+    pub data: Vec<BalancePoint>,
+}
+
+/// Balance changes between consecutive snapshots within one bucket.
+#[derive(Debug, Clone, Serialize)]
+pub struct BalanceFlowPoint {
+    pub timestamp: i64,
+    /// `None` when flows are summed across exchanges.
+    pub exchange_id: Option<i32>,
+    pub exchange: Option<String>,
+    pub token: String,
+    pub inflow: f64,
+    pub outflow: f64,
+    pub net_flow: f64,
+    /// Running total of `net_flow` since the start of the window.
+    pub cumulative_net_flow: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BalanceFlowSeriesResponse {
+    pub symbol: String,
+    pub unit: String,
+    pub balance: String,
+    pub bucket: String,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conversion: Option<ConversionInfo>,
+    pub data: Vec<BalanceFlowPoint>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LatestBalanceResponse {
+    pub exchange_id: i32,
+    pub exchange: Option<String>,
+    pub timestamp: Option<i64>,
+    pub data: Vec<BalancePoint>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExchangeVolume24hResponse {
+    pub exchange_id: i32,
+    pub exchange: Option<String>,
+    /// Pair the exchange most recently reported, whose latest price is `price`.
+    pub symbol: Option<String>,
+    /// Summed over every pair, `0` without rows in the last 24 hours.
+    pub volume: f64,
+    /// `None` when the pairs are quoted in different currencies.
+    pub volume_unit: Option<String>,
+    pub price: Option<f64>,
+    pub price_unit: Option<String>,
+}
+
+/// Market figures of one pair on one exchange over a summary window. Every
+/// figure is `None` when the exchange has no rows for the pair in the window.
+/// Volumes and prices are in the pair's quote currency.
+#[derive(Debug, Clone, Serialize)]
+pub struct MarketSummary {
+    pub exchange_id: i32,
+    pub exchange: Option<String>,
+    pub symbol: Option<String>,
+    pub volume: Option<f64>,
+    pub volume_unit: Option<String>,
+    pub open_price: Option<f64>,
+    pub last_price: Option<f64>,
+    pub price_change: Option<f64>,
+    pub price_change_pct: Option<f64>,
+    pub high: Option<f64>,
+    pub low: Option<f64>,
+    pub price_unit: Option<String>,
+    pub samples: i64,
+    pub last_timestamp: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MarketSummaryResponse {
+    pub window: String,
+    pub from: i64,
+    pub to: i64,
+    pub symbols: Option<Vec<String>>,
+    pub data: Vec<MarketSummary>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CandlePoint {
+    pub timestamp: i64,
+    pub exchange_id: i32,
+    pub exchange: Option<String>,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    /// In the base currency, `None` for exchanges that only report rolling
+    /// 24h totals.
+    pub volume: Option<f64>,
+    pub trade_rows: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IndicatorSeries {
+    pub name: String,
+    pub values: Vec<Option<f64>>,
+}
+
+/// Indicator series of one exchange, aligned index by index with `timestamps`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExchangeIndicators {
+    pub exchange_id: i32,
+    pub exchange: Option<String>,
+    pub timestamps: Vec<i64>,
+    pub close: Vec<f64>,
+    /// In the base currency, `null` for exchanges that only report rolling
+    /// 24h totals.
+    pub volume: Vec<Option<f64>>,
+    pub series: Vec<IndicatorSeries>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IndicatorsResponse {
+    pub symbol: String,
+    pub price_unit: String,
+    pub volume_unit: String,
+    pub bucket: String,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub indicators: Vec<String>,
+    pub data: Vec<ExchangeIndicators>,
+}
+
+/// A flagged bucket with its scores and the statistics of the buckets it was
+/// compared against. `score` is `None` when the baseline was constant.
+#[derive(Debug, Clone, Serialize)]
+pub struct AnomalyPoint {
+    pub timestamp: i64,
+    pub exchange_id: i32,
+    pub exchange: Option<String>,
+    pub symbol: String,
+    pub value: f64,
+    pub score: Option<f64>,
+    pub zscore: Option<f64>,
+    pub mad_score: Option<f64>,
+    pub mean: f64,
+    pub std_dev: f64,
+    pub median: f64,
+    pub mad: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AnomaliesResponse {
+    pub symbol: Option<String>,
+    pub metric: String,
+    pub bucket: String,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub window: usize,
+    pub sensitivity: f64,
+    pub method: String,
+    pub data: Vec<AnomalyPoint>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CandleSeriesResponse {
+    pub symbol: String,
+    pub price_unit: String,
+    pub volume_unit: String,
+    pub bucket: String,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+    pub data: Vec<CandlePoint>,
+}
+
+impl ExportRow for VolumePoint {
+    const COLUMNS: &'static [(&'static str, ColumnType)] = &[
+        ("timestamp", ColumnType::Int),
+        ("exchange_id", ColumnType::Int),
+        ("exchange", ColumnType::Text),
+        ("volume", ColumnType::Float),
+    ];
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            Cell::Int(self.timestamp),
+            Cell::Int(self.exchange_id as i64),
+            Cell::Text(self.exchange.clone()),
+            Cell::Float(self.volume),
+        ]
+    }
+}
+
+impl ExportRow for VolumeSharePoint {
+    const COLUMNS: &'static [(&'static str, ColumnType)] = &[
+        ("timestamp", ColumnType::Int),
+        ("exchange_id", ColumnType::Int),
+        ("exchange", ColumnType::Text),
+        ("volume", ColumnType::Float),
+        ("total_volume", ColumnType::Float),
+        ("share", ColumnType::Float),
+    ];
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            Cell::Int(self.timestamp),
+            Cell::Int(self.exchange_id as i64),
+            Cell::Text(self.exchange.clone()),
+            Cell::Float(self.volume),
+            Cell::Float(self.total_volume),
+            Cell::Float(self.share),
+        ]
+    }
+}
+
+impl ExportRow for BalanceSnapshotPoint {
+    const COLUMNS: &'static [(&'static str, ColumnType)] = &[
+        ("exchange_id", ColumnType::Int),
+        ("exchange", ColumnType::Text),
+        ("token", ColumnType::Text),
+        ("wallet_balance", ColumnType::Float),
+        ("transfer_balance", ColumnType::Float),
+        ("timestamp", ColumnType::Int),
+        ("staleness_secs", ColumnType::Int),
+    ];
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            Cell::Int(self.exchange_id as i64),
+            Cell::Text(self.exchange.clone()),
+            Cell::Text(Some(self.token.clone())),
+            Cell::Float(self.wallet_balance),
+            Cell::Float(self.transfer_balance),
+            Cell::Int(self.timestamp),
+            Cell::Int(self.staleness_secs),
+        ]
+    }
+}
+
+impl ExportRow for BalanceDiffPoint {
+    const COLUMNS: &'static [(&'static str, ColumnType)] = &[
+        ("exchange_id", ColumnType::Int),
+        ("exchange", ColumnType::Text),
+        ("token", ColumnType::Text),
+        ("from_wallet_balance", ColumnType::Float),
+        ("to_wallet_balance", ColumnType::Float),
+        ("wallet_change", ColumnType::Float),
+        ("from_transfer_balance", ColumnType::Float),
+        ("to_transfer_balance", ColumnType::Float),
+        ("transfer_change", ColumnType::Float),
+        ("from_timestamp", ColumnType::Int),
+        ("to_timestamp", ColumnType::Int),
+        ("from_staleness_secs", ColumnType::Int),
+        ("to_staleness_secs", ColumnType::Int),
+    ];
+    fn cells(&self) -> Vec<Cell> {
+        let float = |value: Option<f64>| value.map_or(Cell::Null, Cell::Float);
+        let int = |value: Option<i64>| value.map_or(Cell::Null, Cell::Int);
+        vec![
+            Cell::Int(self.exchange_id as i64),
+            Cell::Text(self.exchange.clone()),
+            Cell::Text(Some(self.token.clone())),
+            float(self.from_wallet_balance),
+            Cell::Float(self.to_wallet_balance),
+            float(self.wallet_change),
+            float(self.from_transfer_balance),
+            Cell::Float(self.to_transfer_balance),
+            float(self.transfer_change),
+            int(self.from_timestamp),
+            Cell::Int(self.to_timestamp),
+            int(self.from_staleness_secs),
+            Cell::Int(self.to_staleness_secs),
+        ]
+    }
+}
+
+impl ExportRow for BalancePoint {
+    const COLUMNS: &'static [(&'static str, ColumnType)] = &[
+        ("timestamp", ColumnType::Int),
+        ("exchange_id", ColumnType::Int),
+        ("exchange", ColumnType::Text),
+        ("token", ColumnType::Text),
+        ("wallet_balance", ColumnType::Float),
+        ("transfer_balance", ColumnType::Float),
+    ];
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            Cell::Int(self.timestamp),
+            Cell::Int(self.exchange_id as i64),
+            Cell::Text(self.exchange.clone()),
+            Cell::Text(Some(self.token.clone())),
+            Cell::Float(self.wallet_balance),
+            Cell::Float(self.transfer_balance),
+        ]
+    }
+}
+
+impl ExportRow for BalanceFlowPoint {
+    const COLUMNS: &'static [(&'static str, ColumnType)] = &[
+        ("timestamp", ColumnType::Int),
+        ("exchange_id", ColumnType::Int),
+        ("exchange", ColumnType::Text),
+        ("token", ColumnType::Text),
+        ("inflow", ColumnType::Float),
+        ("outflow", ColumnType::Float),
+        ("net_flow", ColumnType::Float),
+        ("cumulative_net_flow", ColumnType::Float),
+    ];
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            Cell::Int(self.timestamp),
+            self.exchange_id
+                .map_or(Cell::Null, |exchange_id| Cell::Int(exchange_id as i64)),
+            Cell::Text(self.exchange.clone()),
+            Cell::Text(Some(self.token.clone())),
+            Cell::Float(self.inflow),
+            Cell::Float(self.outflow),
+            Cell::Float(self.net_flow),
+            Cell::Float(self.cumulative_net_flow),
+        ]
+    }
+}
+
+impl ExportRow for ExchangeVolume24hResponse {
+    const COLUMNS: &'static [(&'static str, ColumnType)] = &[
+        ("exchange_id", ColumnType::Int),
+        ("exchange", ColumnType::Text),
+        ("symbol", ColumnType::Text),
+        ("volume", ColumnType::Float),
+        ("volume_unit", ColumnType::Text),
+        ("price", ColumnType::Float),
+        ("price_unit", ColumnType::Text),
+    ];
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            Cell::Int(self.exchange_id as i64),
+            Cell::Text(self.exchange.clone()),
+            Cell::Text(self.symbol.clone()),
+            Cell::Float(self.volume),
+            Cell::Text(self.volume_unit.clone()),
+            self.price.map_or(Cell::Null, Cell::Float),
+            Cell::Text(self.price_unit.clone()),
+        ]
+    }
+}
+
+impl ExportRow for MarketSummary {
+    const COLUMNS: &'static [(&'static str, ColumnType)] = &[
+        ("exchange_id", ColumnType::Int),
+        ("exchange", ColumnType::Text),
+        ("symbol", ColumnType::Text),
+        ("volume", ColumnType::Float),
+        ("volume_unit", ColumnType::Text),
+        ("open_price", ColumnType::Float),
+        ("last_price", ColumnType::Float),
+        ("price_change", ColumnType::Float),
+        ("price_change_pct", ColumnType::Float),
+        ("high", ColumnType::Float),
+        ("low", ColumnType::Float),
+        ("price_unit", ColumnType::Text),
+        ("samples", ColumnType::Int),
+        ("last_timestamp", ColumnType::Int),
+    ];
+    fn cells(&self) -> Vec<Cell> {
+        let float = |value: Option<f64>| value.map_or(Cell::Null, Cell::Float);
+        vec![
+            Cell::Int(self.exchange_id as i64),
+            Cell::Text(self.exchange.clone()),
+            Cell::Text(self.symbol.clone()),
+            float(self.volume),
+            Cell::Text(self.volume_unit.clone()),
+            float(self.open_price),
+            float(self.last_price),
+            float(self.price_change),
+            float(self.price_change_pct),
+            float(self.high),
+            float(self.low),
+            Cell::Text(self.price_unit.clone()),
+            Cell::Int(self.samples),
+            self.last_timestamp.map_or(Cell::Null, Cell::Int),
+        ]
+    }
+}
+
+impl ExportRow for AnomalyPoint {
+    const COLUMNS: &'static [(&'static str, ColumnType)] = &[
+        ("timestamp", ColumnType::Int),
+        ("exchange_id", ColumnType::Int),
+        ("exchange", ColumnType::Text),
+        ("symbol", ColumnType::Text),
+        ("value", ColumnType::Float),
+        ("score", ColumnType::Float),
+        ("zscore", ColumnType::Float),
+        ("mad_score", ColumnType::Float),
+        ("mean", ColumnType::Float),
+        ("std_dev", ColumnType::Float),
+        ("median", ColumnType::Float),
+        ("mad", ColumnType::Float),
+    ];
+    fn cells(&self) -> Vec<Cell> {
+        let float = |value: Option<f64>| value.map_or(Cell::Null, Cell::Float);
+        vec![
+            Cell::Int(self.timestamp),
+            Cell::Int(self.exchange_id as i64),
+            Cell::Text(self.exchange.clone()),
+            Cell::Text(Some(self.symbol.clone())),
+            Cell::Float(self.value),
+            float(self.score),
+            float(self.zscore),
+            float(self.mad_score),
+            Cell::Float(self.mean),
+            Cell::Float(self.std_dev),
+            Cell::Float(self.median),
+            Cell::Float(self.mad),
+        ]
+    }
+}
+
+impl ExportRow for CandlePoint {
+    const COLUMNS: &'static [(&'static str, ColumnType)] = &[
+        ("timestamp", ColumnType::Int),
+        ("exchange_id", ColumnType::Int),
+        ("exchange", ColumnType::Text),
+        ("open", ColumnType::Float),
+        ("high", ColumnType::Float),
+        ("low", ColumnType::Float),
+        ("close", ColumnType::Float),
+        ("volume", ColumnType::Float),
+        ("trade_rows", ColumnType::Int),
+    ];
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            Cell::Int(self.timestamp),
+            Cell::Int(self.exchange_id as i64),
+            Cell::Text(self.exchange.clone()),
+            Cell::Float(self.open),
+            Cell::Float(self.high),
+            Cell::Float(self.low),
+            Cell::Float(self.close),
+            self.volume.map_or(Cell::Null, Cell::Float),
+            Cell::Int(self.trade_rows),
+        ]
+    }
+}
+
+/// Server message on the `/api/v1/stream` WebSocket.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum StreamMessage {
+    Subscribed {
+        channels: Vec<String>,
+    },
+    Unsubscribed {
+        channels: Vec<String>,
+    },
+    Update {
+        channel: String,
+        exchange: Option<String>,
+        data: LiveEvent,
+    },
+    /// The client fell behind and `skipped` updates were dropped.
+    Lagged {
+        skipped: u64,
+    },
+    /// Replay stopped before catching up; reconnect with `resume_from` as
+    /// Last-Event-ID for the rest.
+    Truncated {
+        resume_from: String,
+    },
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExchangeCoverage {
+    pub exchange_id: i32,
+    pub exchange: Option<String>,
+    pub first_timestamp: i64,
+    pub last_timestamp: i64,
+    pub rows: i64,
+}
+
+/// Where a symbol appears in one table.
+#[derive(Debug, Clone, Serialize)]
+pub struct SymbolCoverage {
+    pub first_timestamp: i64,
+    pub last_timestamp: i64,
+    pub rows: i64,
+    pub exchanges: Vec<ExchangeCoverage>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SymbolInfo {
+    pub symbol: String,
+    pub base: Option<String>,
+    pub quote: Option<String>,
+    pub volume: Option<SymbolCoverage>,
+    pub balance: Option<SymbolCoverage>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SymbolListResponse {
+    pub refreshed_at: Option<i64>,
+    pub data: Vec<SymbolInfo>,
+}
+
+/// The only response that includes the webhook's signing secret.
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookSecretResponse {
+    #[serde(flatten)]
+    pub webhook: Webhook,
+    pub secret: String,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct WatchlistEntryResponse {
+    #[serde(skip_serializing)]
+    pub watchlist_id: i64,
+    pub position: i32,
+    pub symbol: String,
+    pub exchange_id: Option<i32>,
+    pub unit: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchlistResponse {
+    pub id: i64,
+    pub name: String,
+    pub entries: Vec<WatchlistEntryResponse>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// Latest market and balance figures of one watchlist entry. Volumes and
+/// balances are in `unit`, prices stay in the pair's quote currency.
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchlistEntrySummary {
+    pub position: i32,
+    pub symbol: String,
+    pub exchange_id: Option<i32>,
+    pub exchange: Option<String>,
+    pub unit: String,
+    pub price: Option<f64>,
+    pub price_unit: Option<String>,
+    pub price_change_24h_pct: Option<f64>,
+    pub volume_24h: Option<f64>,
+    pub wallet_balance: Option<f64>,
+    pub transfer_balance: Option<f64>,
+    pub price_timestamp: Option<i64>,
+    pub balance_timestamp: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchlistSummaryResponse {
+    pub id: i64,
+    pub name: String,
+    pub timestamp: i64,
+    pub data: Vec<WatchlistEntrySummary>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheMetricsResponse {
+    pub enabled: bool,
+    pub ttl: u64,
+    /// Includes coalesced requests.
+    pub hits: u64,
+    pub misses: u64,
+    pub coalesced: u64,
+    pub errors: u64,
+    pub stored: u64,
+    pub hit_ratio: Option<f64>,
+}
+
+/// Outcome of one batch query. `data` is the JSON body the matching endpoint
+/// would return, `error` its error message.
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchResult {
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchResponse {
+    pub results: BTreeMap<String, BatchResult>,
+}
@@ -7,10 +7,19 @@ use crate::utils::{config::*, oauth::build_oauth_client};
 use axum::Router;
 use oauth2::basic::BasicClient;
 use sqlx::PgPool;
-use std::sync::Arc;
-use tokio::net::TcpListener;
+use std::{sync::Arc, time::Duration};
+use tokio::{net::TcpListener, sync::Semaphore};
 use tracing::{error, info};
-use utils::redis::{RedisClient, RedisClientBuilder};
+use utils::{
+    alerts::start_alert_evaluator,
+    cache::{IngestWatermarks, QueryCache},
+    exchanges::ExchangeRegistry,
+    live::LiveFeed,
+    redis::{RedisClient, RedisClientBuilder},
+    rollup::Rollups,
+    symbol::SymbolCatalog,
+    webhooks::start_webhook_dispatcher,
+};
 
 #[derive(Clone)]
 pub struct AppState {
@@ -19,6 +28,13 @@ pub struct AppState {
     pub db: PgPool,
     pub redis: RedisClient,
     pub crypto_data_db: PgPool,
+    pub exchanges: ExchangeRegistry,
+    pub live: LiveFeed,
+    pub symbols: SymbolCatalog,
+    pub cache: QueryCache,
+    pub rollups: Rollups,
+    /// Shared by every `/api/v1/batch` request, see `BATCH_CONCURRENCY`.
+    pub batch_permits: Arc<Semaphore>,
 }
 
 #[tokio::main]
@@ -39,19 +55,57 @@ async fn main() {
         })
         .unwrap();
     info!("✔ Connected to the Database!");
+    let exchanges = ExchangeRegistry::load(&app_database)
+        .await
+        .map_err(|e| {
+            error!("💥 Error to load the exchanges: {}", e);
+        })
+        .unwrap();
+    info!("✔ Loaded {} exchanges!", exchanges.all().len());
     let app_redis = RedisClient::build_from_config(&env.redis_url)
         .map_err(|e| {
             error!("💥 Error in redis connection: {}", e);
         })
         .unwrap();
     info!("✔ Connected to the Redis!");
+    let live_feed = LiveFeed::start(crypto_data_database.clone());
+    let symbols = SymbolCatalog::start(
+        crypto_data_database.clone(),
+        exchanges.clone(),
+        Duration::from_secs(env.symbols_refresh_interval),
+    );
+    let cache = QueryCache::new(
+        env.cache_enabled,
+        Duration::from_secs(env.cache_ttl),
+        app_redis.clone(),
+        IngestWatermarks::start(crypto_data_database.clone(), &live_feed),
+    );
+    let rollups = Rollups::start(
+        crypto_data_database.clone(),
+        env.rollups_enabled,
+        Duration::from_secs(env.rollup_refresh_interval),
+    );
     let app_state = Arc::new(AppState {
         env: env.clone(),
         oauth_client: build_oauth_client(env.client_id, env.client_secret, env.redirect_url),
         db: app_database,
         redis: app_redis,
         crypto_data_db: crypto_data_database,
+        exchanges,
+        live: live_feed,
+        symbols,
+        cache,
+        rollups,
+        batch_permits: Arc::new(Semaphore::new(env.batch_concurrency.max(1))),
     });
+    start_alert_evaluator(
+        app_state.clone(),
+        Duration::from_secs(app_state.env.alerts_evaluation_interval),
+    );
+    start_webhook_dispatcher(
+        app_state.clone(),
+        Duration::from_secs(app_state.env.webhooks_dispatch_interval),
+    );
 
     let app_router: Router = routes::create_router(app_state);
     let app_listener = TcpListener::bind("0.0.0.0:9000")
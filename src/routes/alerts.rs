@@ -0,0 +1,24 @@
+use std::sync::Arc;
+
+use crate::controllers::alerts;
+use crate::AppState;
+use axum::routing::get;
+
+pub fn add_routers(
+    router: axum::Router<Arc<AppState>>,
+    state: Arc<AppState>,
+) -> axum::Router<Arc<AppState>> {
+    router
+        .route(
+            "/api/v1/alerts",
+            get(alerts::list_alerts).post(alerts::create_alert),
+        )
+        .route(
+            "/api/v1/alerts/:id",
+            get(alerts::get_alert)
+                .put(alerts::update_alert)
+                .delete(alerts::delete_alert),
+        )
+        .route("/api/v1/alerts/:id/events", get(alerts::get_alert_events))
+        .with_state(state)
+}
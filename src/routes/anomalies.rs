@@ -0,0 +1,19 @@
+use std::sync::Arc;
+
+use crate::controllers::anomalies;
+use crate::utils::cache::cache_response;
+use crate::AppState;
+use axum::{middleware::from_fn_with_state, routing::get};
+
+pub fn add_routers(
+    router: axum::Router<Arc<AppState>>,
+    state: Arc<AppState>,
+) -> axum::Router<Arc<AppState>> {
+    let cached = || from_fn_with_state(state.clone(), cache_response);
+    router
+        .route(
+            "/api/v1/anomalies",
+            get(anomalies::get_anomalies).layer(cached()),
+        )
+        .with_state(state)
+}
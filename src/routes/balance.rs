@@ -1,18 +1,39 @@
 use std::sync::Arc;
 
 use crate::controllers::balance;
+use crate::utils::cache::cache_response;
 use crate::AppState;
-use axum::routing::get;
+use axum::{middleware::from_fn_with_state, routing::get};
 
 pub fn add_routers(
     router: axum::Router<Arc<AppState>>,
     state: Arc<AppState>,
 ) -> axum::Router<Arc<AppState>> {
+    let cached = || from_fn_with_state(state.clone(), cache_response);
     router
-        .route("/api/v1/balance", get(balance::get_balance_data))
+        .route(
+            "/api/v1/balance",
+            get(balance::get_balance_data).layer(cached()),
+        )
         .route(
             "/api/v1/balance/latest",
-            get(balance::get_latest_balance_data),
+            get(balance::get_latest_balance_data).layer(cached()),
+        )
+        .route(
+            "/api/v1/balance/flows",
+            get(balance::get_balance_flows).layer(cached()),
+        )
+        .route(
+            "/api/v1/balance/snapshot",
+            get(balance::get_balance_snapshot).layer(cached()),
+        )
+        .route(
+            "/api/v2/balance",
+            get(balance::get_balance_series).layer(cached()),
+        )
+        .route(
+            "/api/v2/balance/latest",
+            get(balance::get_latest_balance_summary).layer(cached()),
         )
         .with_state(state)
 }
@@ -0,0 +1,14 @@
+use std::sync::Arc;
+
+use crate::controllers::batch;
+use crate::AppState;
+use axum::routing::post;
+
+pub fn add_routers(
+    router: axum::Router<Arc<AppState>>,
+    state: Arc<AppState>,
+) -> axum::Router<Arc<AppState>> {
+    router
+        .route("/api/v1/batch", post(batch::run_batch))
+        .with_state(state)
+}
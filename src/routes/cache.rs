@@ -0,0 +1,14 @@
+use std::sync::Arc;
+
+use crate::controllers::cache;
+use crate::AppState;
+use axum::routing::get;
+
+pub fn add_routers(
+    router: axum::Router<Arc<AppState>>,
+    state: Arc<AppState>,
+) -> axum::Router<Arc<AppState>> {
+    router
+        .route("/api/v1/cache/metrics", get(cache::get_cache_metrics))
+        .with_state(state)
+}
@@ -0,0 +1,20 @@
+use std::sync::Arc;
+
+use crate::controllers::candles;
+use crate::utils::cache::cache_response;
+use crate::AppState;
+use axum::{middleware::from_fn_with_state, routing::get};
+
+pub fn add_routers(
+    router: axum::Router<Arc<AppState>>,
+    state: Arc<AppState>,
+) -> axum::Router<Arc<AppState>> {
+    let cached = || from_fn_with_state(state.clone(), cache_response);
+    router
+        .route("/api/v1/candles", get(candles::get_candles).layer(cached()))
+        .route(
+            "/api/v2/candles",
+            get(candles::get_candle_series).layer(cached()),
+        )
+        .with_state(state)
+}
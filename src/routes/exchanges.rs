@@ -0,0 +1,14 @@
+use std::sync::Arc;
+
+use crate::controllers::exchanges;
+use crate::AppState;
+use axum::routing::get;
+
+pub fn add_routers(
+    router: axum::Router<Arc<AppState>>,
+    state: Arc<AppState>,
+) -> axum::Router<Arc<AppState>> {
+    router
+        .route("/api/v1/exchanges", get(exchanges::get_exchanges))
+        .with_state(state)
+}
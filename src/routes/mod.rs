@@ -1,11 +1,26 @@
+pub mod alerts;
+pub mod anomalies;
 pub mod balance;
+pub mod batch;
+pub mod cache;
+pub mod candles;
+pub mod exchanges;
+pub mod indicators;
 pub mod oauth;
+pub mod stream;
+pub mod summary;
+pub mod symbols;
 pub mod user;
 pub mod volume;
+pub mod watchlists;
+pub mod webhooks;
 use std::sync::Arc;
 
-use crate::AppState;
-use axum::Router;
+use crate::{
+    utils::{cache::CACHE_HEADER, pagination::NEXT_CURSOR_HEADER},
+    AppState,
+};
+use axum::{http::HeaderName, Router};
 use tower_http::cors::{Any, CorsLayer};
 pub fn create_router(state: Arc<AppState>) -> Router {
     let router = Router::new();
@@ -13,10 +28,26 @@ pub fn create_router(state: Arc<AppState>) -> Router {
     let router = user::add_routers(router, state.clone());
     let router = volume::add_routers(router, state.clone());
     let router = balance::add_routers(router, state.clone());
+    let router = candles::add_routers(router, state.clone());
+    let router = indicators::add_routers(router, state.clone());
+    let router = anomalies::add_routers(router, state.clone());
+    let router = batch::add_routers(router, state.clone());
+    let router = stream::add_routers(router, state.clone());
+    let router = exchanges::add_routers(router, state.clone());
+    let router = symbols::add_routers(router, state.clone());
+    let router = summary::add_routers(router, state.clone());
+    let router = alerts::add_routers(router, state.clone());
+    let router = webhooks::add_routers(router, state.clone());
+    let router = watchlists::add_routers(router, state.clone());
+    let router = cache::add_routers(router, state.clone());
     let cors = CorsLayer::new()
         .allow_methods(Any)
         .allow_origin(Any)
-        .allow_headers(Any);
+        .allow_headers(Any)
+        .expose_headers([
+            HeaderName::from_static(NEXT_CURSOR_HEADER),
+            HeaderName::from_static(CACHE_HEADER),
+        ]);
     let router = router.layer(cors);
     router.with_state(state)
 }
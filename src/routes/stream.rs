@@ -0,0 +1,15 @@
+use std::sync::Arc;
+
+use crate::controllers::stream;
+use crate::AppState;
+use axum::routing::get;
+
+pub fn add_routers(
+    router: axum::Router<Arc<AppState>>,
+    state: Arc<AppState>,
+) -> axum::Router<Arc<AppState>> {
+    router
+        .route("/api/v1/stream", get(stream::get_stream))
+        .route("/api/v1/events", get(stream::get_events))
+        .with_state(state)
+}
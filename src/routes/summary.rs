@@ -0,0 +1,16 @@
+use std::sync::Arc;
+
+use crate::controllers::summary;
+use crate::utils::cache::cache_response;
+use crate::AppState;
+use axum::{middleware::from_fn_with_state, routing::get};
+
+pub fn add_routers(
+    router: axum::Router<Arc<AppState>>,
+    state: Arc<AppState>,
+) -> axum::Router<Arc<AppState>> {
+    let cached = || from_fn_with_state(state.clone(), cache_response);
+    router
+        .route("/api/v1/summary", get(summary::get_summary).layer(cached()))
+        .with_state(state)
+}
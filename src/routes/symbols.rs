@@ -0,0 +1,14 @@
+use std::sync::Arc;
+
+use crate::controllers::symbols;
+use crate::AppState;
+use axum::routing::get;
+
+pub fn add_routers(
+    router: axum::Router<Arc<AppState>>,
+    state: Arc<AppState>,
+) -> axum::Router<Arc<AppState>> {
+    router
+        .route("/api/v1/symbols", get(symbols::get_symbols))
+        .with_state(state)
+}
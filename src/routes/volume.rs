@@ -1,15 +1,31 @@
 use std::sync::Arc;
 
 use crate::controllers::volume;
+use crate::utils::cache::cache_response;
 use crate::AppState;
-use axum::routing::{get, post};
+use axum::{middleware::from_fn_with_state, routing::get};
 
 pub fn add_routers(
     router: axum::Router<Arc<AppState>>,
     state: Arc<AppState>,
 ) -> axum::Router<Arc<AppState>> {
+    let cached = || from_fn_with_state(state.clone(), cache_response);
     router
-        .route("/api/v1/volume", get(volume::get_volume_data))
-        .route("/api/v1/24hr", get(volume::get_24hr_volume_data))
+        .route(
+            "/api/v1/volume",
+            get(volume::get_volume_data).layer(cached()),
+        )
+        .route(
+            "/api/v1/24hr",
+            get(volume::get_24hr_volume_data).layer(cached()),
+        )
+        .route(
+            "/api/v2/volume",
+            get(volume::get_volume_series).layer(cached()),
+        )
+        .route(
+            "/api/v2/24hr",
+            get(volume::get_24hr_volume_summary).layer(cached()),
+        )
         .with_state(state)
 }
@@ -0,0 +1,27 @@
+use std::sync::Arc;
+
+use crate::controllers::watchlists;
+use crate::AppState;
+use axum::routing::get;
+
+pub fn add_routers(
+    router: axum::Router<Arc<AppState>>,
+    state: Arc<AppState>,
+) -> axum::Router<Arc<AppState>> {
+    router
+        .route(
+            "/api/v1/watchlists",
+            get(watchlists::list_watchlists).post(watchlists::create_watchlist),
+        )
+        .route(
+            "/api/v1/watchlists/:id",
+            get(watchlists::get_watchlist)
+                .put(watchlists::update_watchlist)
+                .delete(watchlists::delete_watchlist),
+        )
+        .route(
+            "/api/v1/watchlists/:id/summary",
+            get(watchlists::get_watchlist_summary),
+        )
+        .with_state(state)
+}
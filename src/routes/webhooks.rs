@@ -0,0 +1,31 @@
+use std::sync::Arc;
+
+use crate::controllers::webhooks;
+use crate::AppState;
+use axum::routing::{get, post};
+
+pub fn add_routers(
+    router: axum::Router<Arc<AppState>>,
+    state: Arc<AppState>,
+) -> axum::Router<Arc<AppState>> {
+    router
+        .route(
+            "/api/v1/webhooks",
+            get(webhooks::list_webhooks).post(webhooks::create_webhook),
+        )
+        .route(
+            "/api/v1/webhooks/:id",
+            get(webhooks::get_webhook)
+                .put(webhooks::update_webhook)
+                .delete(webhooks::delete_webhook),
+        )
+        .route(
+            "/api/v1/webhooks/:id/deliveries",
+            get(webhooks::get_webhook_deliveries),
+        )
+        .route(
+            "/api/v1/webhooks/:id/deliveries/:delivery_id/redeliver",
+            post(webhooks::redeliver_webhook),
+        )
+        .with_state(state)
+}
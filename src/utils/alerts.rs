@@ -0,0 +1,568 @@
+use crate::{
+    utils::{
+        errors::ApiError,
+        exchanges::{ExchangeRegistry, QuoteConvention, VolumeSemantics},
+        live::{BalanceTick, VolumeTick},
+        smtp::send_alert_email,
+        webhooks::{self, ALERT_TRIGGERED},
+    },
+    AppState,
+};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{PgPool, Postgres, QueryBuilder, Row};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use tracing::{error, info};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    /// Latest wallet plus transfer balance.
+    Balance,
+    /// Percentage change of the balance over the rule's window.
+    BalanceChangePct,
+    /// Volume traded over the rule's window, in the symbol's quote currency.
+    Volume,
+    /// Latest price, averaged across exchanges.
+    Price,
+    /// Percentage change of the price over the rule's window.
+    PriceChangePct,
+}
+
+impl Metric {
+    pub fn uses_balances(self) -> bool {
+        matches!(self, Metric::Balance | Metric::BalanceChangePct)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum Comparator {
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+}
+
+impl Comparator {
+    pub fn matches(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Comparator::Gt => value > threshold,
+            Comparator::Gte => value >= threshold,
+            Comparator::Lt => value < threshold,
+            Comparator::Lte => value <= threshold,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct AlertRule {
+    pub id: i64,
+    pub user_id: i64,
+    pub name: Option<String>,
+    pub metric: Metric,
+    pub symbol: String,
+    pub exchange_id: Option<i32>,
+    pub comparator: Comparator,
+    pub threshold: f64,
+    pub window_secs: i32,
+    pub cooldown_secs: i32,
+    pub enabled: bool,
+    #[serde(with = "chrono::serde::ts_seconds_option")]
+    pub last_triggered_at: Option<DateTime<Utc>>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl AlertRule {
+    fn window(&self) -> ChronoDuration {
+        ChronoDuration::seconds(self.window_secs as i64)
+    }
+
+    pub fn cooldown_elapsed(&self, now: DateTime<Utc>) -> bool {
+        match self.last_triggered_at {
+            Some(last) => now - last >= ChronoDuration::seconds(self.cooldown_secs as i64),
+            None => true,
+        }
+    }
+
+    pub fn should_trigger(&self, value: f64, now: DateTime<Utc>) -> bool {
+        self.enabled && self.cooldown_elapsed(now) && self.comparator.matches(value, self.threshold)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct AlertEvent {
+    pub id: i64,
+    pub rule_id: i64,
+    pub value: f64,
+    pub threshold: f64,
+    pub notified: bool,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub triggered_at: DateTime<Utc>,
+}
+
+/// Rows a rule is evaluated against, at most two windows old.
+#[derive(Debug, Clone)]
+pub enum MetricData {
+    Balance(Vec<BalanceTick>),
+    Volume(Vec<VolumeTick>),
+}
+
+/// Ticks of each exchange, oldest first, up to `now`.
+fn by_exchange<T: Clone>(
+    ticks: &[T],
+    exchange_id: impl Fn(&T) -> i32,
+    timestamp: impl Fn(&T) -> i64,
+    rule: &AlertRule,
+    now: i64,
+) -> BTreeMap<i32, Vec<T>> {
+    let mut series: BTreeMap<i32, Vec<T>> = BTreeMap::new();
+    for tick in ticks {
+        let id = exchange_id(tick);
+        if timestamp(tick) <= now && rule.exchange_id.is_none_or(|rule_id| rule_id == id) {
+            series.entry(id).or_default().push(tick.clone());
+        }
+    }
+    for ticks in series.values_mut() {
+        ticks.sort_by_key(|tick| timestamp(tick));
+    }
+    series
+}
+
+/// Current and window-start values of each exchange that has both.
+fn current_and_baseline<T>(
+    series: &BTreeMap<i32, Vec<T>>,
+    timestamp: impl Fn(&T) -> i64,
+    value: impl Fn(&T) -> f64,
+    cutoff: i64,
+) -> Vec<(f64, f64)> {
+    series
+        .values()
+        .filter_map(|ticks| {
+            let current = value(ticks.last()?);
+            let baseline = value(ticks.iter().rev().find(|tick| timestamp(tick) <= cutoff)?);
+            Some((current, baseline))
+        })
+        .collect()
+}
+
+fn pct_change(current: f64, baseline: f64) -> Option<f64> {
+    (baseline != 0.0).then(|| (current - baseline) / baseline * 100.0)
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), value| (sum + value, count + 1));
+    (count > 0).then(|| sum / count as f64)
+}
+
+/// Computes the rule's metric from fixture-friendly rows. `None` when there
+/// is not enough data, e.g. no snapshot before the start of the window.
+pub fn metric_value(
+    rule: &AlertRule,
+    data: &MetricData,
+    exchanges: &ExchangeRegistry,
+    now: DateTime<Utc>,
+) -> Option<f64> {
+    let cutoff = (now - rule.window()).timestamp();
+    let now = now.timestamp();
+    match (rule.metric, data) {
+        (Metric::Balance | Metric::BalanceChangePct, MetricData::Balance(ticks)) => {
+            let series = by_exchange(ticks, |t| t.exchange_id, |t| t.timestamp, rule, now);
+            let total = |tick: &BalanceTick| tick.wallet_balance + tick.transfer_balance;
+            if rule.metric == Metric::Balance {
+                let latest: Vec<f64> = series
+                    .values()
+                    .filter_map(|ticks| ticks.last().map(total))
+                    .collect();
+                return (!latest.is_empty()).then(|| latest.iter().sum());
+            }
+            let pairs = current_and_baseline(&series, |t| t.timestamp, total, cutoff);
+            if pairs.is_empty() {
+                return None;
+            }
+            pct_change(
+                pairs.iter().map(|(current, _)| current).sum(),
+                pairs.iter().map(|(_, baseline)| baseline).sum(),
+            )
+        }
+        (Metric::Price | Metric::PriceChangePct, MetricData::Volume(ticks)) => {
+            let series = by_exchange(ticks, |t| t.exchange_id, |t| t.timestamp, rule, now);
+            if rule.metric == Metric::Price {
+                return mean(
+                    series
+                        .values()
+                        .filter_map(|ticks| ticks.last().map(|tick| tick.price)),
+                );
+            }
+            let pairs = current_and_baseline(&series, |t| t.timestamp, |t| t.price, cutoff);
+            pct_change(
+                mean(pairs.iter().map(|(current, _)| *current))?,
+                mean(pairs.iter().map(|(_, baseline)| *baseline))?,
+            )
+        }
+        (Metric::Volume, MetricData::Volume(ticks)) => {
+            let series = by_exchange(ticks, |t| t.exchange_id, |t| t.timestamp, rule, now);
+            let window_days = rule.window_secs as f64 / 86400.0;
+            let volumes: Vec<f64> = series
+                .iter()
+                .filter_map(|(&exchange_id, ticks)| {
+                    let in_quote = |tick: &VolumeTick, volume: f64| match exchanges
+                        .quote_convention(exchange_id)
+                    {
+                        QuoteConvention::Base => volume * tick.price,
+                        QuoteConvention::Quote => volume,
+                    };
+                    match exchanges.volume_semantics(exchange_id) {
+                        VolumeSemantics::PerRow => Some(
+                            ticks
+                                .iter()
+                                .filter(|tick| tick.timestamp > cutoff)
+                                .map(|tick| in_quote(tick, tick.total_volume))
+                                .sum(),
+                        ),
+                        // Pro-rated like the aggregate volume series.
+                        VolumeSemantics::Rolling24h => {
+                            let tick = ticks.last()?;
+                            Some(in_quote(tick, tick.day_total_volume?) * window_days)
+                        }
+                    }
+                })
+                .collect();
+            (!volumes.is_empty()).then(|| volumes.iter().sum())
+        }
+        _ => None,
+    }
+}
+
+async fn load_metric_data(
+    db: &PgPool,
+    rule: &AlertRule,
+    now: DateTime<Utc>,
+) -> Result<MetricData, sqlx::Error> {
+    let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
+        if rule.metric.uses_balances() {
+            "SELECT id, exchange_id, token_symbol, wallet_balance, transfer_balance, timestamp FROM balance_data"
+        } else {
+            "SELECT id, exchange_id, token_symbol, price, total_volume, day_total_volume, timestamp FROM volume_data"
+        },
+    );
+    query_builder.push(" WHERE token_symbol=");
+    query_builder.push_bind(rule.symbol.clone());
+    if let Some(exchange_id) = rule.exchange_id {
+        query_builder.push(" AND exchange_id=");
+        query_builder.push_bind(exchange_id);
+    }
+    query_builder.push(" AND timestamp>=");
+    query_builder.push_bind(now - rule.window() * 2);
+    query_builder.push(" AND timestamp<=");
+    query_builder.push_bind(now);
+    let rows = query_builder.build().fetch_all(db).await?;
+    let timestamp =
+        |row: &sqlx::postgres::PgRow| row.get::<DateTime<Utc>, _>("timestamp").timestamp();
+    Ok(if rule.metric.uses_balances() {
+        MetricData::Balance(
+            rows.iter()
+                .map(|row| BalanceTick {
+                    id: Some(row.get::<i32, _>("id")),
+                    exchange_id: row.get::<i32, _>("exchange_id"),
+                    token_symbol: row.get::<String, _>("token_symbol"),
+                    wallet_balance: row.get::<f64, _>("wallet_balance"),
+                    transfer_balance: row.get::<f64, _>("transfer_balance"),
+                    timestamp: timestamp(row),
+                })
+                .collect(),
+        )
+    } else {
+        MetricData::Volume(
+            rows.iter()
+                .map(|row| VolumeTick {
+                    id: Some(row.get::<i32, _>("id")),
+                    exchange_id: row.get::<i32, _>("exchange_id"),
+                    token_symbol: row.get::<String, _>("token_symbol"),
+                    price: row.get::<f64, _>("price"),
+                    total_volume: row.get::<f64, _>("total_volume"),
+                    day_total_volume: row.get::<Option<f64>, _>("day_total_volume"),
+                    timestamp: timestamp(row),
+                })
+                .collect(),
+        )
+    })
+}
+
+fn describe(rule: &AlertRule, value: f64) -> String {
+    let exchange = match rule.exchange_id {
+        Some(exchange_id) => format!("exchange {}", exchange_id),
+        None => "all exchanges".to_string(),
+    };
+    format!(
+        "{}: {:?} of {} on {} is {} ({:?} {} over {}s)",
+        rule.name.as_deref().unwrap_or("Alert"),
+        rule.metric,
+        rule.symbol,
+        exchange,
+        value,
+        rule.comparator,
+        rule.threshold,
+        rule.window_secs
+    )
+}
+
+async fn trigger(
+    state: &Arc<AppState>,
+    rule: &AlertRule,
+    value: f64,
+    now: DateTime<Utc>,
+) -> Result<(), ApiError> {
+    sqlx::query("UPDATE alert_rules SET last_triggered_at = $1 WHERE id = $2")
+        .bind(now)
+        .bind(rule.id)
+        .execute(&state.db)
+        .await?;
+    let (event_id,): (i64,) = sqlx::query_as(
+        "INSERT INTO alert_events (rule_id, value, threshold, triggered_at) VALUES ($1, $2, $3, $4) RETURNING id",
+    )
+    .bind(rule.id)
+    .bind(value)
+    .bind(rule.threshold)
+    .bind(now)
+    .fetch_one(&state.db)
+    .await?;
+    info!("Alert rule {} triggered with value {}", rule.id, value);
+    let payload = json!({
+        "rule_id": rule.id,
+        "event_id": event_id,
+        "name": rule.name,
+        "metric": rule.metric,
+        "symbol": rule.symbol,
+        "exchange_id": rule.exchange_id,
+        "comparator": rule.comparator,
+        "threshold": rule.threshold,
+        "value": value,
+        "triggered_at": now.timestamp(),
+    });
+    if let Err(e) =
+        webhooks::enqueue(&state.db, Some(rule.user_id), ALERT_TRIGGERED, &payload).await
+    {
+        error!(
+            "💥 Error to queue webhooks for alert rule {}: {}",
+            rule.id, e
+        );
+    }
+
+    let (email,): (String,) = sqlx::query_as("SELECT email FROM users WHERE id = $1")
+        .bind(rule.user_id)
+        .fetch_one(&state.db)
+        .await?;
+    let message = describe(rule, value);
+    let mail_state = state.clone();
+    // lettre's SMTP transport is blocking.
+    let sent = tokio::task::spawn_blocking(move || send_alert_email(email, message, mail_state))
+        .await
+        .map_err(|e| ApiError::CustomError(std::io::Error::other(e)))?;
+    match sent {
+        Ok(()) => {
+            sqlx::query("UPDATE alert_events SET notified = TRUE WHERE id = $1")
+                .bind(event_id)
+                .execute(&state.db)
+                .await?;
+        }
+        Err(e) => error!(
+            "💥 Error to send the alert email for rule {}: {}",
+            rule.id, e
+        ),
+    }
+    Ok(())
+}
+
+pub async fn evaluate_alerts(state: &Arc<AppState>) -> Result<(), ApiError> {
+    let rules = sqlx::query_as::<_, AlertRule>("SELECT * FROM alert_rules WHERE enabled")
+        .fetch_all(&state.db)
+        .await?;
+    let now = Utc::now();
+    for rule in rules.iter().filter(|rule| rule.cooldown_elapsed(now)) {
+        let data = match load_metric_data(&state.crypto_data_db, rule, now).await {
+            Ok(data) => data,
+            Err(e) => {
+                error!("💥 Error to load data for alert rule {}: {}", rule.id, e);
+                continue;
+            }
+        };
+        let Some(value) = metric_value(rule, &data, &state.exchanges, now) else {
+            continue;
+        };
+        if rule.should_trigger(value, now) {
+            if let Err(e) = trigger(state, rule, value, now).await {
+                error!("💥 Error to record alert rule {}: {}", rule.id, e);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn start_alert_evaluator(state: Arc<AppState>, interval: Duration) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            if let Err(e) = evaluate_alerts(&state).await {
+                error!("💥 Error to evaluate alert rules: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::exchanges::Exchange;
+
+    const NOW: i64 = 1_730_764_800;
+
+    fn rule(
+        metric: Metric,
+        exchange_id: Option<i32>,
+        comparator: Comparator,
+        threshold: f64,
+    ) -> AlertRule {
+        let created_at = DateTime::from_timestamp(0, 0).unwrap();
+        AlertRule {
+            id: 1,
+            user_id: 1,
+            name: None,
+            metric,
+            symbol: "BTC".to_string(),
+            exchange_id,
+            comparator,
+            threshold,
+            window_secs: 3600,
+            cooldown_secs: 3600,
+            enabled: true,
+            last_triggered_at: None,
+            created_at,
+            updated_at: created_at,
+        }
+    }
+
+    fn balance(exchange_id: i32, ago: i64, wallet_balance: f64) -> BalanceTick {
+        BalanceTick {
+            id: None,
+            exchange_id,
+            token_symbol: "BTC".to_string(),
+            wallet_balance,
+            transfer_balance: 0.0,
+            timestamp: NOW - ago,
+        }
+    }
+
+    fn volume(
+        exchange_id: i32,
+        ago: i64,
+        price: f64,
+        total_volume: f64,
+        day: Option<f64>,
+    ) -> VolumeTick {
+        VolumeTick {
+            id: None,
+            exchange_id,
+            token_symbol: "BTC-USDT".to_string(),
+            price,
+            total_volume,
+            day_total_volume: day,
+            timestamp: NOW - ago,
+        }
+    }
+
+    fn now() -> DateTime<Utc> {
+        DateTime::from_timestamp(NOW, 0).unwrap()
+    }
+
+    #[test]
+    fn balance_drop_over_window() {
+        let data = MetricData::Balance(vec![
+            balance(2, 5400, 100.0),
+            balance(2, 3600, 100.0),
+            balance(2, 600, 97.0),
+            balance(2, 0, 94.0),
+            balance(3, 3600, 1000.0),
+            balance(3, 0, 1000.0),
+        ]);
+        let exchanges = ExchangeRegistry::default();
+        let rule = rule(Metric::BalanceChangePct, Some(2), Comparator::Lte, -5.0);
+        let value = metric_value(&rule, &data, &exchanges, now()).unwrap();
+        assert!((value + 6.0).abs() < 1e-9);
+        assert!(rule.should_trigger(value, now()));
+
+        // Across all exchanges the same drop is well under 5%.
+        let mut all = rule.clone();
+        all.exchange_id = None;
+        let value = metric_value(&all, &data, &exchanges, now()).unwrap();
+        assert!(!all.should_trigger(value, now()));
+    }
+
+    #[test]
+    fn missing_baseline_is_not_evaluated() {
+        let data = MetricData::Balance(vec![balance(2, 600, 97.0), balance(2, 0, 94.0)]);
+        let rule = rule(Metric::BalanceChangePct, Some(2), Comparator::Lte, -5.0);
+        assert_eq!(
+            metric_value(&rule, &data, &ExchangeRegistry::default(), now()),
+            None
+        );
+    }
+
+    #[test]
+    fn volume_respects_exchange_semantics() {
+        let exchanges = ExchangeRegistry::new(vec![Exchange {
+            id: 1,
+            name: "Rolling".to_string(),
+            slug: "rolling".to_string(),
+            volume_semantics: VolumeSemantics::Rolling24h,
+            quote_convention: QuoteConvention::Quote,
+            enabled: true,
+        }]);
+        let data = MetricData::Volume(vec![
+            // Per-row base volume: only the rows inside the window count.
+            volume(0, 7200, 10.0, 100.0, None),
+            volume(0, 1800, 10.0, 2.0, None),
+            volume(0, 0, 20.0, 1.0, None),
+            // Rolling 24h quote volume, pro-rated to one hour.
+            volume(1, 0, 20.0, 0.0, Some(2400.0)),
+        ]);
+        let mut rule = rule(Metric::Volume, None, Comparator::Gt, 100.0);
+        rule.symbol = "BTC-USDT".to_string();
+        let value = metric_value(&rule, &data, &exchanges, now()).unwrap();
+        assert!((value - (20.0 + 20.0 + 100.0)).abs() < 1e-9);
+        assert!(rule.should_trigger(value, now()));
+    }
+
+    #[test]
+    fn cooldown_suppresses_repeats() {
+        let mut rule = rule(Metric::Price, None, Comparator::Gt, 10.0);
+        let data = MetricData::Volume(vec![
+            volume(0, 0, 20.0, 1.0, None),
+            volume(2, 0, 30.0, 1.0, None),
+        ]);
+        let value = metric_value(&rule, &data, &ExchangeRegistry::default(), now()).unwrap();
+        assert_eq!(value, 25.0);
+        rule.last_triggered_at = Some(now() - ChronoDuration::minutes(30));
+        assert!(!rule.should_trigger(value, now()));
+        rule.last_triggered_at = Some(now() - ChronoDuration::hours(2));
+        assert!(rule.should_trigger(value, now()));
+        rule.enabled = false;
+        assert!(!rule.should_trigger(value, now()));
+    }
+
+    #[test]
+    fn mismatched_data_has_no_value() {
+        let rule = rule(Metric::Balance, None, Comparator::Gt, 0.0);
+        let data = MetricData::Volume(vec![volume(0, 0, 20.0, 1.0, None)]);
+        assert_eq!(
+            metric_value(&rule, &data, &ExchangeRegistry::default(), now()),
+            None
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use crate::utils::errors::ApiError;
+use std::str::FromStr;
+
+/// Scales the median absolute deviation to the standard deviation of a
+/// normal distribution.
+const MAD_SCALE: f64 = 1.4826;
+/// Same for the mean absolute deviation, used when more than half of the
+/// baseline sits on the median and the MAD is zero.
+const MEAN_AD_SCALE: f64 = 1.2533;
+
+/// Which score decides whether a point is flagged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// Distance from the baseline mean in standard deviations.
+    ZScore,
+    /// Robust distance from the baseline median in scaled median absolute
+    /// deviations, which a few earlier outliers do not inflate.
+    Mad,
+}
+
+impl Method {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::ZScore => "zscore",
+            Method::Mad => "mad",
+        }
+    }
+}
+
+impl FromStr for Method {
+    type Err = ApiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zscore" => Ok(Method::ZScore),
+            "mad" => Ok(Method::Mad),
+            _ => Err(ApiError::InvalidRequest(format!(
+                "Unknown method '{}', expected zscore or mad",
+                s
+            ))),
+        }
+    }
+}
+
+/// Statistics of the points a value is compared against.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Baseline {
+    pub mean: f64,
+    pub std_dev: f64,
+    pub median: f64,
+    pub mad: f64,
+}
+
+impl Baseline {
+    fn of(values: &[f64]) -> Self {
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let std_dev = (values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n).sqrt();
+        let center = median(values.to_vec());
+        Baseline {
+            mean,
+            std_dev,
+            median: center,
+            mad: median(values.iter().map(|x| (x - center).abs()).collect()),
+        }
+    }
+
+    fn zscore(&self, value: f64) -> Option<f64> {
+        deviation(value, self.mean, self.std_dev)
+    }
+
+    fn mad_score(&self, value: f64, values: &[f64]) -> Option<f64> {
+        let spread = if self.mad > 0.0 {
+            MAD_SCALE * self.mad
+        } else {
+            MEAN_AD_SCALE * values.iter().map(|x| (x - self.median).abs()).sum::<f64>()
+                / values.len() as f64
+        };
+        deviation(value, self.median, spread)
+    }
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+/// `None` when the baseline is constant and `value` differs from it, which
+/// no finite score describes.
+fn deviation(value: f64, center: f64, spread: f64) -> Option<f64> {
+    if spread > 0.0 {
+        Some((value - center) / spread)
+    } else if value == center {
+        Some(0.0)
+    } else {
+        None
+    }
+}
+
+/// A flagged point. `score` is the one selected by the method, `None` for a
+/// departure from a constant baseline.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Anomaly {
+    pub index: usize,
+    pub score: Option<f64>,
+    pub zscore: Option<f64>,
+    pub mad_score: Option<f64>,
+    pub baseline: Baseline,
+}
+
+/// Scores every value against the `window` values before it and returns the
+/// ones whose score reaches `sensitivity` in either direction. The first
+/// `window` values only serve as baseline.
+pub fn detect(values: &[f64], window: usize, method: Method, sensitivity: f64) -> Vec<Anomaly> {
+    (window..values.len())
+        .filter_map(|index| {
+            let previous = &values[index - window..index];
+            let value = values[index];
+            let baseline = Baseline::of(previous);
+            let zscore = baseline.zscore(value);
+            let mad_score = baseline.mad_score(value, previous);
+            let score = match method {
+                Method::ZScore => zscore,
+                Method::Mad => mad_score,
+            };
+            score
+                .is_none_or(|score| score.abs() >= sensitivity)
+                .then_some(Anomaly {
+                    index,
+                    score,
+                    zscore,
+                    mad_score,
+                    baseline,
+                })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn baseline_statistics() {
+        let baseline = Baseline::of(&[1.0, 2.0, 3.0, 4.0, 100.0]);
+        assert_eq!(baseline.mean, 22.0);
+        assert_eq!(baseline.median, 3.0);
+        assert_eq!(baseline.mad, 1.0);
+        assert_eq!(Baseline::of(&[4.0, 1.0, 3.0, 2.0]).median, 2.5);
+    }
+
+    #[test]
+    fn flags_a_spike_against_a_noisy_baseline() {
+        let mut values = vec![10.0, 11.0, 9.0, 10.0, 12.0, 8.0, 10.0, 11.0];
+        values.push(40.0);
+        values.push(10.0);
+        let anomalies = detect(&values, 8, Method::ZScore, 3.0);
+        assert_eq!(anomalies.len(), 1);
+        assert_eq!(anomalies[0].index, 8);
+        assert_eq!(anomalies[0].baseline.mean, 10.125);
+        assert!(anomalies[0].score.unwrap() > 3.0);
+        assert_eq!(detect(&values, 8, Method::ZScore, 100.0), vec![]);
+    }
+
+    #[test]
+    fn mad_is_not_masked_by_earlier_outliers() {
+        // The first spike inflates the standard deviation of the baseline so
+        // the second one no longer stands out by z-score.
+        let values = [10.0, 11.0, 9.0, 10.0, 60.0, 10.0, 11.0, 9.0, 10.0, 60.0];
+        let zscore: Vec<usize> = detect(&values, 8, Method::ZScore, 3.0)
+            .iter()
+            .map(|a| a.index)
+            .collect();
+        let mad: Vec<usize> = detect(&values, 8, Method::Mad, 3.0)
+            .iter()
+            .map(|a| a.index)
+            .collect();
+        assert_eq!(zscore, Vec::<usize>::new());
+        assert_eq!(mad, vec![9]);
+    }
+
+    #[test]
+    fn departures_from_a_constant_baseline() {
+        // A drain after a flat balance: no finite score, but still flagged.
+        let anomalies = detect(&[0.0, 0.0, 0.0, 0.0, -50.0], 4, Method::ZScore, 3.0);
+        assert_eq!(anomalies.len(), 1);
+        assert_eq!(anomalies[0].score, None);
+        assert_eq!(
+            detect(&[0.0, 0.0, 0.0, 0.0, 0.0], 4, Method::Mad, 3.0),
+            vec![]
+        );
+        // Mostly flat with a few small moves: the MAD is zero, so the mean
+        // absolute deviation sets the scale instead.
+        let values = [0.0, 1.0, 0.0, -1.0, 0.0, 1.0, 0.0, 0.0, 1.0];
+        assert_eq!(detect(&values, 8, Method::Mad, 3.0), vec![]);
+        let values = [0.0, 1.0, 0.0, -1.0, 0.0, 1.0, 0.0, 0.0, -20.0];
+        assert_eq!(detect(&values, 8, Method::Mad, 3.0).len(), 1);
+    }
+}
@@ -0,0 +1,523 @@
+use crate::{
+    dto::response::CacheMetricsResponse,
+    utils::{
+        live::LiveFeed,
+        redis::{RedisClient, RedisClientExt},
+    },
+    AppState,
+};
+use axum::{
+    body::{Body, BodyDataStream, Bytes},
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Row};
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, RwLock, Weak,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::error;
+
+const KEY_PREFIX: &str = "QUERY_CACHE";
+/// Responses larger than this are streamed through without being cached.
+const MAX_CACHED_BYTES: usize = 8 * 1024 * 1024;
+/// Catches up on rows ingested while the live feed was disconnected.
+const WATERMARK_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+pub const CACHE_HEADER: &str = "x-cache";
+
+/// Latest ingestion time, in microseconds, of each exchange across
+/// `volume_data` and `balance_data`, kept current from the live feed. Rows
+/// count when they arrive, not by their own `timestamp`, so late rows still
+/// invalidate cached responses.
+#[derive(Debug, Clone, Default)]
+pub struct IngestWatermarks {
+    latest: Arc<RwLock<HashMap<i32, i64>>>,
+}
+
+impl IngestWatermarks {
+    pub fn start(db: PgPool, live: &LiveFeed) -> Self {
+        let watermarks = IngestWatermarks::default();
+        let polled = watermarks.clone();
+        tokio::spawn(async move {
+            let mut previous: HashMap<i32, i64> = HashMap::new();
+            loop {
+                match query_watermarks(&db).await {
+                    Ok(latest) => {
+                        for (exchange_id, ingested_at) in latest {
+                            if previous.insert(exchange_id, ingested_at) != Some(ingested_at) {
+                                polled.bump(exchange_id, ingested_at);
+                            }
+                        }
+                    }
+                    Err(e) => error!("💥 Error to refresh the ingest watermarks: {}", e),
+                }
+                tokio::time::sleep(WATERMARK_REFRESH_INTERVAL).await;
+            }
+        });
+        let followed = watermarks.clone();
+        let mut events = live.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => {
+                        followed.advance(event.exchange_id(), Utc::now().timestamp_micros())
+                    }
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+        watermarks
+    }
+
+    pub fn advance(&self, exchange_id: i32, timestamp: i64) {
+        if let Ok(mut latest) = self.latest.write() {
+            let current = latest.entry(exchange_id).or_default();
+            *current = (*current).max(timestamp);
+        }
+    }
+
+    /// Moves the watermark even when `ingested_at`, read from the database
+    /// clock, is behind a value taken from this server's clock.
+    pub fn bump(&self, exchange_id: i32, ingested_at: i64) {
+        if let Ok(mut latest) = self.latest.write() {
+            let current = latest.entry(exchange_id).or_default();
+            *current = (*current + 1).max(ingested_at);
+        }
+    }
+
+    /// Watermark of one exchange, or of all of them when `None`.
+    pub fn get(&self, exchange_id: Option<i32>) -> i64 {
+        let Ok(latest) = self.latest.read() else {
+            return 0;
+        };
+        match exchange_id {
+            Some(exchange_id) => latest.get(&exchange_id).copied().unwrap_or_default(),
+            None => latest.values().copied().max().unwrap_or_default(),
+        }
+    }
+}
+
+async fn query_watermarks(db: &PgPool) -> Result<Vec<(i32, i64)>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT exchange_id, (EXTRACT(EPOCH FROM MAX(ingested_at)) * 1000000)::BIGINT AS latest FROM ( \
+             SELECT exchange_id, MAX(ingested_at) AS ingested_at FROM volume_data GROUP BY exchange_id \
+             UNION ALL \
+             SELECT exchange_id, MAX(ingested_at) AS ingested_at FROM balance_data GROUP BY exchange_id \
+         ) latest GROUP BY exchange_id",
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(rows
+        .iter()
+        .map(|row| {
+            (
+                row.get::<i32, _>("exchange_id"),
+                row.get::<i64, _>("latest"),
+            )
+        })
+        .collect())
+}
+
+#[derive(Debug, Default)]
+struct CacheMetrics {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    /// Misses that waited for a concurrent identical request instead of querying.
+    coalesced: AtomicU64,
+    errors: AtomicU64,
+    stored: AtomicU64,
+}
+
+/// Read-through cache of market-data responses in Redis.
+///
+/// Keys embed the ingest watermark of the requested exchange (or of all
+/// exchanges), so new rows make older entries unreachable and the TTL only
+/// has to clean them up.
+#[derive(Debug, Clone)]
+pub struct QueryCache {
+    enabled: bool,
+    ttl: Duration,
+    redis: RedisClient,
+    watermarks: IngestWatermarks,
+    flights: Arc<Mutex<HashMap<String, Weak<tokio::sync::Mutex<()>>>>>,
+    metrics: Arc<CacheMetrics>,
+}
+
+/// Headers that describe the connection rather than the response, or that
+/// are set again for every response, and so are not cached.
+const UNCACHED_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "content-length",
+    "date",
+    "set-cookie",
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedResponse {
+    /// Response headers such as `Content-Type` and `x-next-cursor`.
+    headers: Vec<(String, String)>,
+    /// Base64, since exports can be binary.
+    body: String,
+}
+
+impl CachedResponse {
+    fn new(headers: &HeaderMap, body: &[u8]) -> Self {
+        CachedResponse {
+            headers: headers
+                .iter()
+                .filter(|(name, _)| {
+                    !UNCACHED_HEADERS.contains(&name.as_str())
+                        && !name.as_str().eq_ignore_ascii_case(CACHE_HEADER)
+                })
+                .filter_map(|(name, value)| {
+                    Some((name.to_string(), value.to_str().ok()?.to_string()))
+                })
+                .collect(),
+            body: STANDARD.encode(body),
+        }
+    }
+
+    fn into_response(self) -> Option<Response> {
+        let body = STANDARD.decode(self.body).ok()?;
+        let mut response = (StatusCode::OK, body).into_response();
+        let headers = response.headers_mut();
+        headers.remove(header::CONTENT_TYPE);
+        for (name, value) in self.headers {
+            if let (Ok(name), Ok(value)) = (
+                header::HeaderName::try_from(name),
+                HeaderValue::from_str(&value),
+            ) {
+                headers.append(name, value);
+            }
+        }
+        Some(response)
+    }
+}
+
+impl QueryCache {
+    pub fn new(
+        enabled: bool,
+        ttl: Duration,
+        redis: RedisClient,
+        watermarks: IngestWatermarks,
+    ) -> Self {
+        QueryCache {
+            enabled,
+            ttl,
+            redis,
+            watermarks,
+            flights: Arc::default(),
+            metrics: Arc::default(),
+        }
+    }
+
+    pub fn metrics(&self) -> CacheMetricsResponse {
+        let metrics = &self.metrics;
+        let hits = metrics.hits.load(Ordering::Relaxed) + metrics.coalesced.load(Ordering::Relaxed);
+        let misses = metrics.misses.load(Ordering::Relaxed);
+        CacheMetricsResponse {
+            enabled: self.enabled,
+            ttl: self.ttl.as_secs(),
+            hits,
+            misses,
+            coalesced: metrics.coalesced.load(Ordering::Relaxed),
+            errors: metrics.errors.load(Ordering::Relaxed),
+            stored: metrics.stored.load(Ordering::Relaxed),
+            hit_ratio: (hits + misses > 0).then(|| hits as f64 / (hits + misses) as f64),
+        }
+    }
+
+    fn key(&self, uri: &Uri, headers: &HeaderMap) -> String {
+        let exchange_id = query_pairs(uri.query().unwrap_or_default())
+            .into_iter()
+            .find(|(name, _)| *name == "exchange_id")
+            .and_then(|(_, value)| value.parse().ok());
+        let accept = headers
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok());
+        cache_key(uri, accept, self.watermarks.get(exchange_id))
+    }
+
+    /// `Err` when Redis is unreachable, in which case the cache is bypassed.
+    async fn lookup(&self, key: &str) -> Result<Option<Response>, ()> {
+        let value = self.redis.get(key).await.map_err(|e| {
+            self.metrics.errors.fetch_add(1, Ordering::Relaxed);
+            error!("💥 Error to read the query cache: {}", e);
+        })?;
+        Ok(value
+            .and_then(|value| serde_json::from_str::<CachedResponse>(&value).ok())
+            .and_then(CachedResponse::into_response))
+    }
+
+    async fn store(&self, key: &str, headers: &HeaderMap, body: &[u8]) {
+        let value = CachedResponse::new(headers, body);
+        let Ok(value) = serde_json::to_string(&value) else {
+            return;
+        };
+        match self.redis.set(key, &value, self.ttl).await {
+            Ok(()) => {
+                self.metrics.stored.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(e) => {
+                self.metrics.errors.fetch_add(1, Ordering::Relaxed);
+                error!("💥 Error to write the query cache: {}", e);
+            }
+        }
+    }
+
+    /// Lock shared by concurrent requests for the same key.
+    fn flight(&self, key: &str) -> Arc<tokio::sync::Mutex<()>> {
+        let mut flights = self.flights.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(flight) = flights.get(key).and_then(Weak::upgrade) {
+            return flight;
+        }
+        flights.retain(|_, flight| flight.strong_count() > 0);
+        let flight = Arc::new(tokio::sync::Mutex::new(()));
+        flights.insert(key.to_string(), Arc::downgrade(&flight));
+        flight
+    }
+}
+
+/// Non-empty `name=value` pairs of a query string, sorted.
+fn query_pairs(query: &str) -> Vec<(&str, &str)> {
+    let mut pairs: Vec<(&str, &str)> = query
+        .split('&')
+        .filter_map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (!name.is_empty() && !value.is_empty()).then_some((name, value))
+        })
+        .collect();
+    pairs.sort();
+    pairs
+}
+
+/// Key for a request, independent of parameter order. `Accept` only matters
+/// when no `format` parameter picks the output format.
+pub fn cache_key(uri: &Uri, accept: Option<&str>, watermark: i64) -> String {
+    let pairs = query_pairs(uri.query().unwrap_or_default());
+    let mut normalized = uri.path().to_string();
+    for (i, (name, value)) in pairs.iter().enumerate() {
+        normalized.push(if i == 0 { '?' } else { '&' });
+        normalized.push_str(&format!("{}={}", name, value));
+    }
+    if !pairs.iter().any(|(name, _)| *name == "format") {
+        normalized.push_str(&format!("|{}", accept.unwrap_or_default()));
+    }
+    format!(
+        "{}_{}_{}",
+        KEY_PREFIX,
+        watermark,
+        hex::encode(Sha256::digest(normalized.as_bytes()))
+    )
+}
+
+/// Passes a response body through while keeping a copy, which is stored once
+/// the body completes.
+struct Recording {
+    inner: BodyDataStream,
+    buffer: Option<Vec<u8>>,
+    finish: Option<(QueryCache, String, HeaderMap)>,
+}
+
+impl Stream for Recording {
+    type Item = Result<Bytes, axum::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let item = self.inner.poll_next_unpin(cx);
+        match &item {
+            Poll::Ready(Some(Ok(bytes))) => {
+                let too_large = self
+                    .buffer
+                    .as_ref()
+                    .is_some_and(|buffer| buffer.len() + bytes.len() > MAX_CACHED_BYTES);
+                if too_large {
+                    self.buffer = None;
+                } else if let Some(buffer) = self.buffer.as_mut() {
+                    buffer.extend_from_slice(bytes);
+                }
+            }
+            Poll::Ready(Some(Err(_))) => self.buffer = None,
+            Poll::Ready(None) => {
+                if let (Some(body), Some((cache, key, headers))) =
+                    (self.buffer.take(), self.finish.take())
+                {
+                    tokio::spawn(async move {
+                        cache.store(&key, &headers, &body).await;
+                    });
+                }
+            }
+            Poll::Pending => {}
+        }
+        item
+    }
+}
+
+/// Handlers opt single responses out of the cache with `Cache-Control: no-store`.
+fn is_no_store(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|directive| directive.trim().eq_ignore_ascii_case("no-store"))
+}
+
+fn with_cache_header(mut response: Response, value: &'static str) -> Response {
+    response
+        .headers_mut()
+        .insert(CACHE_HEADER, HeaderValue::from_static(value));
+    response
+}
+
+/// Middleware for GET routes whose responses only change with new data.
+pub async fn cache_response(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let cache = state.cache.clone();
+    if !cache.enabled {
+        return next.run(request).await;
+    }
+    let key = cache.key(request.uri(), request.headers());
+    match cache.lookup(&key).await {
+        Ok(Some(response)) => {
+            cache.metrics.hits.fetch_add(1, Ordering::Relaxed);
+            return with_cache_header(response, "HIT");
+        }
+        Ok(None) => {}
+        Err(()) => return next.run(request).await,
+    }
+    let guard = cache.flight(&key).lock_owned().await;
+    if let Ok(Some(response)) = cache.lookup(&key).await {
+        cache.metrics.coalesced.fetch_add(1, Ordering::Relaxed);
+        return with_cache_header(response, "HIT");
+    }
+    cache.metrics.misses.fetch_add(1, Ordering::Relaxed);
+    let response = next.run(request).await;
+    // Held only while the handler runs: waiting on the body would make every
+    // identical request wait for the slowest client, and bodies too large to
+    // store would then run one at a time.
+    drop(guard);
+    if response.status() != StatusCode::OK || is_no_store(response.headers()) {
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let recording = Recording {
+        inner: body.into_data_stream(),
+        buffer: Some(vec![]),
+        finish: Some((cache, key, parts.headers.clone())),
+    };
+    with_cache_header(
+        Response::from_parts(parts, Body::from_stream(recording)),
+        "MISS",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_ignore_parameter_order_and_empty_values() {
+        let a: Uri = "/api/v1/volume?symbol=BTC-USDT&interval=1Y&unit="
+            .parse()
+            .unwrap();
+        let b: Uri = "/api/v1/volume?interval=1Y&symbol=BTC-USDT"
+            .parse()
+            .unwrap();
+        assert_eq!(cache_key(&a, None, 10), cache_key(&b, None, 10));
+        assert_ne!(cache_key(&a, None, 10), cache_key(&a, None, 11));
+        let other: Uri = "/api/v2/volume?interval=1Y&symbol=BTC-USDT"
+            .parse()
+            .unwrap();
+        assert_ne!(cache_key(&b, None, 10), cache_key(&other, None, 10));
+    }
+
+    #[test]
+    fn accept_only_matters_without_format() {
+        let negotiated: Uri = "/api/v2/volume?symbol=BTC-USDT".parse().unwrap();
+        assert_ne!(
+            cache_key(&negotiated, Some("text/csv"), 0),
+            cache_key(&negotiated, None, 0)
+        );
+        let explicit: Uri = "/api/v2/volume?symbol=BTC-USDT&format=csv".parse().unwrap();
+        assert_eq!(
+            cache_key(&explicit, Some("text/csv"), 0),
+            cache_key(&explicit, None, 0)
+        );
+    }
+
+    #[test]
+    fn hits_keep_response_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/csv"));
+        headers.insert("x-next-cursor", HeaderValue::from_static("abc123"));
+        headers.insert(header::VARY, HeaderValue::from_static("accept"));
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from_static("3"));
+        headers.insert(CACHE_HEADER, HeaderValue::from_static("MISS"));
+        let stored = serde_json::to_string(&CachedResponse::new(&headers, b"a,b")).unwrap();
+        let response = serde_json::from_str::<CachedResponse>(&stored)
+            .unwrap()
+            .into_response()
+            .unwrap();
+        let response = with_cache_header(response, "HIT");
+        let headers = response.headers();
+        assert_eq!(headers["x-next-cursor"], "abc123");
+        assert_eq!(headers[header::CONTENT_TYPE], "text/csv");
+        assert_eq!(headers[header::VARY], "accept");
+        assert_eq!(headers[CACHE_HEADER], "HIT");
+        assert_eq!(headers.get_all(header::CONTENT_TYPE).iter().count(), 1);
+        assert!(!stored.contains("content-length"));
+    }
+
+    #[test]
+    fn no_store_responses_are_not_cached() {
+        let mut headers = HeaderMap::new();
+        assert!(!is_no_store(&headers));
+        headers.insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static("private, No-Store"),
+        );
+        assert!(is_no_store(&headers));
+    }
+
+    #[test]
+    fn watermarks_only_move_forward() {
+        let watermarks = IngestWatermarks::default();
+        assert_eq!(watermarks.get(None), 0);
+        watermarks.advance(1, 100);
+        watermarks.advance(1, 50);
+        watermarks.advance(2, 70);
+        assert_eq!(watermarks.get(Some(1)), 100);
+        assert_eq!(watermarks.get(Some(2)), 70);
+        assert_eq!(watermarks.get(Some(3)), 0);
+        assert_eq!(watermarks.get(None), 100);
+        watermarks.bump(1, 90);
+        assert_eq!(watermarks.get(Some(1)), 101);
+        watermarks.bump(2, 200);
+        assert_eq!(watermarks.get(Some(2)), 200);
+    }
+}
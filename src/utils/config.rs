@@ -1,7 +1,4 @@
-use chrono::{Duration, TimeDelta};
 use dotenv::dotenv;
-use lazy_static::lazy_static;
-use std::collections::HashMap;
 use std::env;
 use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 pub const AUTH_URL: &str = "https://accounts.google.com/o/oauth2/v2/auth";
@@ -22,6 +19,15 @@ pub struct Environment {
     pub smtp_username: String,
     pub smtp_password: String,
     pub crypto_data_database_url: String,
+    pub symbols_refresh_interval: u64,
+    pub conversion_pegs: Vec<(String, String)>,
+    pub alerts_evaluation_interval: u64,
+    pub webhooks_dispatch_interval: u64,
+    pub cache_enabled: bool,
+    pub cache_ttl: u64,
+    pub rollups_enabled: bool,
+    pub rollup_refresh_interval: u64,
+    pub batch_concurrency: usize,
 }
 impl Environment {
     pub fn default() -> Self {
@@ -45,6 +51,47 @@ impl Environment {
         let smtp_sender_email = env::var("SMTP_SENDER_EMAIL").unwrap_or("".into());
         let smtp_username = env::var("SMTP_USERNAME").unwrap_or("".into());
         let smtp_password = env::var("SMTP_PASSWORD").unwrap_or("".into());
+        let symbols_refresh_interval = env::var("SYMBOLS_REFRESH_INTERVAL")
+            .unwrap_or("".into())
+            .parse::<u64>()
+            .unwrap_or(300);
+        // Units treated as 1:1 when converting, e.g. CONVERSION_PEGS="USD:USDT,USD:USDC"
+        let conversion_pegs = env::var("CONVERSION_PEGS")
+            .unwrap_or("USD:USDT,USD:USDC".into())
+            .split(',')
+            .filter_map(|peg| {
+                let (a, b) = peg.split_once(':')?;
+                Some((a.trim().to_string(), b.trim().to_string()))
+            })
+            .collect();
+        let alerts_evaluation_interval = env::var("ALERTS_EVALUATION_INTERVAL")
+            .unwrap_or("".into())
+            .parse::<u64>()
+            .unwrap_or(60);
+        let webhooks_dispatch_interval = env::var("WEBHOOKS_DISPATCH_INTERVAL")
+            .unwrap_or("".into())
+            .parse::<u64>()
+            .unwrap_or(10);
+        let cache_enabled = env::var("CACHE_ENABLED")
+            .unwrap_or("".into())
+            .parse::<bool>()
+            .unwrap_or(true);
+        let cache_ttl = env::var("CACHE_TTL")
+            .unwrap_or("".into())
+            .parse::<u64>()
+            .unwrap_or(300);
+        let rollups_enabled = env::var("ROLLUPS_ENABLED")
+            .unwrap_or("".into())
+            .parse::<bool>()
+            .unwrap_or(true);
+        let rollup_refresh_interval = env::var("ROLLUP_REFRESH_INTERVAL")
+            .unwrap_or("".into())
+            .parse::<u64>()
+            .unwrap_or(60);
+        let batch_concurrency = env::var("BATCH_CONCURRENCY")
+            .unwrap_or("".into())
+            .parse::<usize>()
+            .unwrap_or(4);
         Environment {
             client_id,
             client_secret,
@@ -59,6 +106,15 @@ impl Environment {
             smtp_username,
             smtp_password,
             crypto_data_database_url,
+            symbols_refresh_interval,
+            conversion_pegs,
+            alerts_evaluation_interval,
+            webhooks_dispatch_interval,
+            cache_enabled,
+            cache_ttl,
+            rollups_enabled,
+            rollup_refresh_interval,
+            batch_concurrency,
         }
     }
 }
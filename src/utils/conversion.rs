@@ -0,0 +1,302 @@
+use crate::{
+    utils::{errors::ApiError, symbol::split_symbol, time_window::TimeWindow},
+    AppState,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{Postgres, QueryBuilder, Row};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+/// One hop of a conversion path.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConversionStep {
+    pub from: String,
+    pub to: String,
+    /// The `volume_data` pair whose price is used, `None` for a peg.
+    pub pair: Option<String>,
+    /// `true` when converting from the quote to the base, i.e. dividing by the price.
+    pub inverse: bool,
+}
+
+/// Finds the conversion with the fewest hops from `from` to `to` over the
+/// given `BASE-QUOTE` pairs and 1:1 pegs. Returns an empty path when both
+/// units are the same.
+pub fn find_path(
+    pairs: &[String],
+    pegs: &[(String, String)],
+    from: &str,
+    to: &str,
+) -> Option<Vec<ConversionStep>> {
+    let mut edges: HashMap<String, Vec<ConversionStep>> = HashMap::new();
+    let mut add = |from: &str, to: &str, pair: Option<&String>, inverse: bool| {
+        edges
+            .entry(from.to_string())
+            .or_default()
+            .push(ConversionStep {
+                from: from.to_string(),
+                to: to.to_string(),
+                pair: pair.cloned(),
+                inverse,
+            });
+    };
+    let split: Vec<(&str, &str, &String)> = pairs
+        .iter()
+        .filter_map(|pair| split_symbol(pair).map(|(base, quote)| (base, quote, pair)))
+        .collect();
+    for (base, quote, pair) in &split {
+        add(base, quote, Some(pair), false);
+        add(quote, base, Some(pair), true);
+    }
+    for (a, b) in pegs {
+        add(a, b, None, false);
+        add(b, a, None, false);
+    }
+    for steps in edges.values_mut() {
+        steps.sort_by(|a, b| (&a.to, &a.pair).cmp(&(&b.to, &b.pair)));
+    }
+
+    let mut previous: HashMap<String, ConversionStep> = HashMap::new();
+    let mut queue = VecDeque::from([from.to_string()]);
+    while let Some(unit) = queue.pop_front() {
+        if unit == to {
+            let mut path = vec![];
+            let mut current = unit;
+            while current != from {
+                let step = previous[&current].clone();
+                current = step.from.clone();
+                path.push(step);
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for step in edges.get(&unit).into_iter().flatten() {
+            if step.to != from && !previous.contains_key(&step.to) {
+                previous.insert(step.to.clone(), step.clone());
+                queue.push_back(step.to.clone());
+            }
+        }
+    }
+    None
+}
+
+/// Average price of each pair per bucket start, in Unix seconds.
+#[derive(Debug, Clone, Default)]
+pub struct PriceTable {
+    prices: HashMap<String, BTreeMap<i64, f64>>,
+}
+
+impl PriceTable {
+    pub fn insert(&mut self, pair: &str, timestamp: i64, price: f64) {
+        self.prices
+            .entry(pair.to_string())
+            .or_default()
+            .insert(timestamp, price);
+    }
+
+    /// Latest price at or before `timestamp`, or the earliest one known when
+    /// the pair has no earlier price.
+    pub fn price_at(&self, pair: &str, timestamp: i64) -> Option<f64> {
+        let prices = self.prices.get(pair)?;
+        prices
+            .range(..=timestamp)
+            .next_back()
+            .or_else(|| prices.iter().next())
+            .map(|(_, price)| *price)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConversionInfo {
+    pub from: String,
+    pub to: String,
+    pub path: Vec<ConversionStep>,
+    /// How step prices are picked for each point.
+    pub price_source: &'static str,
+}
+
+/// Converts amounts from one unit to another at the price of each bucket.
+#[derive(Debug, Clone)]
+pub struct Converter {
+    from: String,
+    to: String,
+    path: Vec<ConversionStep>,
+    prices: PriceTable,
+}
+
+impl Converter {
+    pub fn new(from: &str, to: &str, path: Vec<ConversionStep>, prices: PriceTable) -> Self {
+        Converter {
+            from: from.to_string(),
+            to: to.to_string(),
+            path,
+            prices,
+        }
+    }
+
+    /// The unit amounts are converted from.
+    pub fn source_unit(&self) -> &str {
+        &self.from
+    }
+
+    /// How many `to` one `from` was worth at `timestamp`.
+    pub fn rate_at(&self, timestamp: i64) -> Option<f64> {
+        self.path
+            .iter()
+            .try_fold(1.0, |rate, step| match &step.pair {
+                None => Some(rate),
+                Some(pair) => {
+                    let price = self.prices.price_at(pair, timestamp)?;
+                    Some(if step.inverse {
+                        rate / price
+                    } else {
+                        rate * price
+                    })
+                }
+            })
+    }
+
+    pub fn info(&self) -> ConversionInfo {
+        ConversionInfo {
+            from: self.from.clone(),
+            to: self.to.clone(),
+            path: self.path.clone(),
+            price_source: "average volume_data price per bucket across exchanges, carried forward",
+        }
+    }
+
+    /// Loads a converter for `from` into `to` covering `window`. Fails with a
+    /// client error when no chain of pairs connects the two units.
+    pub async fn load(
+        state: &AppState,
+        from: &str,
+        to: &str,
+        window: &TimeWindow,
+    ) -> Result<Self, ApiError> {
+        let pairs = available_pairs(state).await?;
+        let path = find_path(&pairs, &state.env.conversion_pegs, from, to).ok_or_else(|| {
+            ApiError::InvalidRequest(format!("No conversion path from {} to {}", from, to))
+        })?;
+        let path_pairs: Vec<String> = path.iter().filter_map(|step| step.pair.clone()).collect();
+        let mut prices = PriceTable::default();
+        if !path_pairs.is_empty() {
+            let mut query_builder: QueryBuilder<Postgres> =
+                QueryBuilder::new("SELECT token_symbol, AVG(price) AS price, ");
+            query_builder.push(window.bucket.sql_expr("timestamp"));
+            query_builder.push(" AS time_interval FROM volume_data WHERE token_symbol = ANY(");
+            query_builder.push_bind(path_pairs.clone());
+            query_builder.push(")");
+            window.push_filter(&mut query_builder, "timestamp");
+            query_builder.push(" GROUP BY token_symbol, time_interval");
+            let rows = query_builder
+                .build()
+                .fetch_all(&state.crypto_data_db)
+                .await?;
+            for row in &rows {
+                prices.insert(
+                    &row.get::<String, _>("token_symbol"),
+                    row.get::<DateTime<Utc>, _>("time_interval").timestamp(),
+                    row.get::<f64, _>("price"),
+                );
+            }
+            // The last price before the window is carried into its first buckets.
+            if let Some(from) = window.from {
+                let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
+                    "SELECT DISTINCT ON (token_symbol) token_symbol, price, timestamp FROM volume_data WHERE token_symbol = ANY(",
+                );
+                query_builder.push_bind(path_pairs);
+                query_builder.push(") AND timestamp<");
+                query_builder.push_bind(from);
+                query_builder.push(" ORDER BY token_symbol, timestamp DESC");
+                let rows = query_builder
+                    .build()
+                    .fetch_all(&state.crypto_data_db)
+                    .await?;
+                for row in &rows {
+                    prices.insert(
+                        &row.get::<String, _>("token_symbol"),
+                        row.get::<DateTime<Utc>, _>("timestamp").timestamp(),
+                        row.get::<f64, _>("price"),
+                    );
+                }
+            }
+        }
+        Ok(Converter::new(from, to, path, prices))
+    }
+}
+
+async fn available_pairs(state: &AppState) -> Result<Vec<String>, ApiError> {
+    if let Some(symbols) = state.symbols.get() {
+        return Ok(symbols
+            .data
+            .iter()
+            .filter(|symbol| symbol.volume.is_some())
+            .map(|symbol| symbol.symbol.clone())
+            .collect());
+    }
+    let rows = sqlx::query("SELECT DISTINCT token_symbol FROM volume_data")
+        .fetch_all(&state.crypto_data_db)
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| row.get::<String, _>("token_symbol"))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(pairs: &[&str]) -> Vec<String> {
+        pairs.iter().map(|pair| pair.to_string()).collect()
+    }
+
+    fn hops(path: &[ConversionStep]) -> Vec<(&str, bool)> {
+        path.iter()
+            .map(|step| (step.pair.as_deref().unwrap_or("peg"), step.inverse))
+            .collect()
+    }
+
+    #[test]
+    fn direct_and_inverse_paths() {
+        let pairs = pairs(&["BTC-USDT"]);
+        let path = find_path(&pairs, &[], "BTC", "USDT").unwrap();
+        assert_eq!(hops(&path), vec![("BTC-USDT", false)]);
+        let path = find_path(&pairs, &[], "USDT", "BTC").unwrap();
+        assert_eq!(hops(&path), vec![("BTC-USDT", true)]);
+        assert!(find_path(&pairs, &[], "BTC", "BTC").unwrap().is_empty());
+    }
+
+    #[test]
+    fn triangulates_through_intermediate_pairs() {
+        let pairs = pairs(&["SOL-BTC", "BTC-USDT", "ETH-USDT"]);
+        let path = find_path(&pairs, &[], "SOL", "ETH").unwrap();
+        assert_eq!(
+            hops(&path),
+            vec![("SOL-BTC", false), ("BTC-USDT", false), ("ETH-USDT", true)]
+        );
+        let pegs = vec![("USD".to_string(), "USDT".to_string())];
+        let path = find_path(&pairs, &pegs, "SOL", "USD").unwrap();
+        assert_eq!(
+            hops(&path),
+            vec![("SOL-BTC", false), ("BTC-USDT", false), ("peg", false)]
+        );
+        assert!(find_path(&pairs, &[], "SOL", "DOGE").is_none());
+    }
+
+    #[test]
+    fn rates_use_the_price_of_each_bucket() {
+        let pairs = pairs(&["SOL-BTC", "BTC-USDT"]);
+        let path = find_path(&pairs, &[], "USDT", "SOL").unwrap();
+        let mut prices = PriceTable::default();
+        prices.insert("SOL-BTC", 0, 0.002);
+        prices.insert("BTC-USDT", 0, 50000.0);
+        prices.insert("BTC-USDT", 60, 100000.0);
+        let converter = Converter::new("USDT", "SOL", path, prices);
+        let close = |a: Option<f64>, b: f64| (a.unwrap() - b).abs() < 1e-12;
+        assert!(close(converter.rate_at(0), 0.01));
+        // SOL-BTC has no price at 120, so the one from 0 is carried forward.
+        assert!(close(converter.rate_at(120), 0.005));
+        // Before the first price, the earliest one is used.
+        assert!(close(converter.rate_at(-60), 0.01));
+    }
+}
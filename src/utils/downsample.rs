@@ -0,0 +1,233 @@
+use crate::utils::errors::ApiError;
+use std::{collections::HashMap, hash::Hash, str::FromStr};
+
+pub const MAX_POINTS: usize = 10000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// Largest-Triangle-Three-Buckets: keeps the visually significant shape.
+    Lttb,
+    /// Keeps the minimum and maximum of each bucket, so spikes always survive.
+    MinMax,
+}
+
+impl FromStr for Method {
+    type Err = ApiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lttb" => Ok(Method::Lttb),
+            "minmax" => Ok(Method::MinMax),
+            _ => Err(ApiError::InvalidRequest(format!(
+                "Unknown downsample method '{}', expected lttb or minmax",
+                s
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Downsample {
+    pub max_points: usize,
+    pub method: Method,
+}
+
+impl Downsample {
+    pub fn from_request(
+        max_points: Option<usize>,
+        method: Option<&str>,
+    ) -> Result<Option<Self>, ApiError> {
+        let Some(max_points) = max_points else {
+            return Ok(None);
+        };
+        if !(3..=MAX_POINTS).contains(&max_points) {
+            return Err(ApiError::InvalidRequest(format!(
+                "max_points must be between 3 and {}",
+                MAX_POINTS
+            )));
+        }
+        let method = match method {
+            Some(method) => method.parse()?,
+            None => Method::Lttb,
+        };
+        Ok(Some(Downsample { max_points, method }))
+    }
+
+    /// Downsamples every series (grouped by `key`) to at most `max_points`
+    /// points, keeping the original order of `items`.
+    pub fn apply<T, K, FK, FX, FY>(&self, items: Vec<T>, key: FK, x: FX, y: FY) -> Vec<T>
+    where
+        K: Eq + Hash,
+        FK: Fn(&T) -> K,
+        FX: Fn(&T) -> f64,
+        FY: Fn(&T) -> f64,
+    {
+        let mut series: HashMap<K, Vec<usize>> = HashMap::new();
+        for (i, item) in items.iter().enumerate() {
+            series.entry(key(item)).or_default().push(i);
+        }
+        let mut keep = vec![false; items.len()];
+        for indices in series.values() {
+            let xs: Vec<f64> = indices.iter().map(|&i| x(&items[i])).collect();
+            let ys: Vec<f64> = indices.iter().map(|&i| y(&items[i])).collect();
+            let kept = match self.method {
+                Method::Lttb => lttb(&xs, &ys, self.max_points),
+                Method::MinMax => min_max(&ys, self.max_points),
+            };
+            for k in kept {
+                keep[indices[k]] = true;
+            }
+        }
+        items
+            .into_iter()
+            .zip(keep)
+            .filter_map(|(item, keep)| keep.then_some(item))
+            .collect()
+    }
+}
+
+/// Indices of the points picked by Largest-Triangle-Three-Buckets.
+pub fn lttb(xs: &[f64], ys: &[f64], threshold: usize) -> Vec<usize> {
+    let n = xs.len();
+    if threshold >= n || threshold < 3 {
+        return (0..n).collect();
+    }
+    let every = (n - 2) as f64 / (threshold - 2) as f64;
+    let mut sampled = Vec::with_capacity(threshold);
+    let mut a = 0;
+    sampled.push(a);
+    for i in 0..threshold - 2 {
+        // Average of the next bucket is the third vertex of the triangle.
+        let avg_start = ((i + 1) as f64 * every) as usize + 1;
+        let avg_end = (((i + 2) as f64 * every) as usize + 1).min(n);
+        let (avg_x, avg_y) = if avg_start < avg_end {
+            let len = (avg_end - avg_start) as f64;
+            (
+                xs[avg_start..avg_end].iter().sum::<f64>() / len,
+                ys[avg_start..avg_end].iter().sum::<f64>() / len,
+            )
+        } else {
+            (xs[n - 1], ys[n - 1])
+        };
+
+        let range_start = (i as f64 * every) as usize + 1;
+        let range_end = (((i + 1) as f64 * every) as usize + 1).min(n - 1);
+        let mut best = range_start;
+        let mut best_area = -1.0;
+        for j in range_start..range_end {
+            let area =
+                ((xs[a] - avg_x) * (ys[j] - ys[a]) - (xs[a] - xs[j]) * (avg_y - ys[a])).abs();
+            if area > best_area {
+                best_area = area;
+                best = j;
+            }
+        }
+        sampled.push(best);
+        a = best;
+    }
+    sampled.push(n - 1);
+    sampled
+}
+
+/// Indices of the minimum and maximum of each of `threshold / 2` equal buckets.
+pub fn min_max(ys: &[f64], threshold: usize) -> Vec<usize> {
+    let n = ys.len();
+    if threshold >= n || threshold < 2 {
+        return (0..n).collect();
+    }
+    let buckets = threshold / 2;
+    let mut sampled = Vec::with_capacity(buckets * 2);
+    for b in 0..buckets {
+        let start = b * n / buckets;
+        let end = ((b + 1) * n / buckets).min(n);
+        if start >= end {
+            continue;
+        }
+        let mut lo = start;
+        let mut hi = start;
+        for i in start..end {
+            if ys[i] < ys[lo] {
+                lo = i;
+            }
+            if ys[i] > ys[hi] {
+                hi = i;
+            }
+        }
+        sampled.push(lo.min(hi));
+        if lo != hi {
+            sampled.push(lo.max(hi));
+        }
+    }
+    sampled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lttb_keeps_endpoints_and_respects_threshold() {
+        let xs: Vec<f64> = (0..1000).map(|i| i as f64).collect();
+        let ys: Vec<f64> = xs.iter().map(|x| (x / 25.0).sin()).collect();
+        let kept = lttb(&xs, &ys, 100);
+        assert_eq!(kept.len(), 100);
+        assert_eq!(kept[0], 0);
+        assert_eq!(kept[99], 999);
+        assert!(kept.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn lttb_keeps_a_spike() {
+        let xs: Vec<f64> = (0..500).map(|i| i as f64).collect();
+        let mut ys = vec![1.0; 500];
+        ys[250] = 100.0;
+        assert!(lttb(&xs, &ys, 20).contains(&250));
+    }
+
+    #[test]
+    fn small_series_are_returned_untouched() {
+        let xs = [0.0, 1.0, 2.0];
+        assert_eq!(lttb(&xs, &xs, 10), vec![0, 1, 2]);
+        assert_eq!(min_max(&xs, 10), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn min_max_keeps_extremes() {
+        let ys: Vec<f64> = (0..100)
+            .map(|i| if i == 37 { -5.0 } else { i as f64 })
+            .collect();
+        let kept = min_max(&ys, 10);
+        assert!(kept.len() <= 10);
+        assert!(kept.contains(&37));
+        assert!(kept.contains(&99));
+    }
+
+    #[test]
+    fn apply_downsamples_each_series_separately() {
+        let items: Vec<(i32, f64)> = (0..200).map(|i| (i % 2, i as f64)).collect();
+        let downsample = Downsample {
+            max_points: 10,
+            method: Method::Lttb,
+        };
+        let kept = downsample.apply(items, |item| item.0, |item| item.1, |item| item.1);
+        assert_eq!(kept.iter().filter(|item| item.0 == 0).count(), 10);
+        assert_eq!(kept.iter().filter(|item| item.0 == 1).count(), 10);
+        assert!(kept.windows(2).all(|w| w[0].1 < w[1].1));
+    }
+
+    #[test]
+    fn request_validation() {
+        assert_eq!(Downsample::from_request(None, Some("lttb")).unwrap(), None);
+        assert!(Downsample::from_request(Some(2), None).is_err());
+        assert!(Downsample::from_request(Some(MAX_POINTS + 1), None).is_err());
+        assert!(Downsample::from_request(Some(MAX_POINTS), None).is_ok());
+        assert!(Downsample::from_request(Some(100), Some("avg")).is_err());
+        assert_eq!(
+            Downsample::from_request(Some(100), Some("minmax")).unwrap(),
+            Some(Downsample {
+                max_points: 100,
+                method: Method::MinMax
+            })
+        );
+    }
+}
@@ -7,7 +7,7 @@ const INTERNAL_SERVER_ERROR: &str = "Internal Server Error";
 #[derive(Debug, Error)]
 pub enum ApiError {
     #[error("SQL error: {0}")]
-    SQL(#[from] sqlx::Error),
+    Sql(#[from] sqlx::Error),
     #[error("HTTP request error: {0}")]
     Request(#[from] reqwest::Error),
     #[error("Email building failed: {0}")]
@@ -48,14 +48,19 @@ pub enum ApiError {
     TypedHeaderError(#[from] axum_extra::typed_header::TypedHeaderRejection),
     #[error("Failed to decode jwt token: {0}")]
     JWTDecodeError(#[from] jsonwebtoken::errors::Error),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("Not found: {0}")]
+    NotFound(String),
 }
 
-impl IntoResponse for ApiError {
-    fn into_response(self) -> Response {
+impl ApiError {
+    /// Status code and client-facing message, which hides internal details.
+    pub fn into_status(self) -> (StatusCode, String) {
         error!("{}", self);
 
         let response = match self {
-            Self::SQL(_) => (
+            Self::Sql(_) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 INTERNAL_SERVER_ERROR.to_string(),
             ),
@@ -78,7 +83,7 @@ impl IntoResponse for ApiError {
             ),
             Self::TokenError(_) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Code Error: {}", INTERNAL_SERVER_ERROR.to_string()),
+                format!("Code Error: {}", INTERNAL_SERVER_ERROR),
             ),
             Self::Unauthorized => (StatusCode::UNAUTHORIZED, "Unauthorized!".to_string()),
             Self::NoEmailFound => (StatusCode::NOT_FOUND, "No Email Found!".to_string()),
@@ -112,8 +117,16 @@ impl IntoResponse for ApiError {
                 INTERNAL_SERVER_ERROR.to_string(),
             ),
             Self::JWTDecodeError(_) => (StatusCode::UNAUTHORIZED, "Invalid JWT Code".to_string()),
+            Self::InvalidRequest(e) => (StatusCode::BAD_REQUEST, e),
+            Self::NotFound(e) => (StatusCode::NOT_FOUND, e),
         };
         error!("StatusCode: {}, Error Message: {}", response.0, response.1);
-        response.into_response()
+        response
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        self.into_status().into_response()
     }
 }
@@ -0,0 +1,212 @@
+use serde::Serialize;
+use sqlx::PgPool;
+use std::{collections::HashMap, sync::Arc};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, sqlx::Type)]
+#[sqlx(type_name = "varchar", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum VolumeSemantics {
+    /// `total_volume` is the volume traded since the previous row.
+    #[default]
+    PerRow,
+    /// `day_total_volume` is the exchange's own rolling 24h total.
+    #[sqlx(rename = "rolling_24h")]
+    #[serde(rename = "rolling_24h")]
+    Rolling24h,
+}
+
+/// Currency the exchange reports volumes in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, sqlx::Type)]
+#[sqlx(type_name = "varchar", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum QuoteConvention {
+    #[default]
+    Base,
+    Quote,
+}
+
+impl QuoteConvention {
+    /// Converts `volume` of a `base-quote` pair into `unit`, which must be one
+    /// of the two. `price` is the pair's price in the quote currency.
+    pub fn convert(
+        self,
+        volume: f64,
+        price: f64,
+        base: &str,
+        quote: &str,
+        unit: &str,
+    ) -> Option<f64> {
+        let factor = match self {
+            QuoteConvention::Base if unit == base => 1.0,
+            QuoteConvention::Base if unit == quote => price,
+            QuoteConvention::Quote if unit == quote => 1.0,
+            QuoteConvention::Quote if unit == base => 1.0 / price,
+            _ => return None,
+        };
+        Some(volume * factor)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Exchange {
+    pub id: i32,
+    pub name: String,
+    pub slug: String,
+    pub volume_semantics: VolumeSemantics,
+    pub quote_convention: QuoteConvention,
+    pub enabled: bool,
+}
+
+/// The `exchanges` table, loaded once at startup.
+#[derive(Debug, Clone, Default)]
+pub struct ExchangeRegistry {
+    exchanges: Arc<Vec<Exchange>>,
+    by_id: Arc<HashMap<i32, usize>>,
+}
+
+impl ExchangeRegistry {
+    pub fn new(exchanges: Vec<Exchange>) -> Self {
+        let by_id = exchanges
+            .iter()
+            .enumerate()
+            .map(|(i, exchange)| (exchange.id, i))
+            .collect();
+        ExchangeRegistry {
+            exchanges: Arc::new(exchanges),
+            by_id: Arc::new(by_id),
+        }
+    }
+
+    pub async fn load(db: &PgPool) -> Result<Self, sqlx::Error> {
+        let exchanges = sqlx::query_as::<_, Exchange>(
+            "SELECT id, name, slug, volume_semantics, quote_convention, enabled FROM exchanges ORDER BY id",
+        )
+        .fetch_all(db)
+        .await?;
+        Ok(Self::new(exchanges))
+    }
+
+    pub fn all(&self) -> &[Exchange] {
+        &self.exchanges
+    }
+
+    pub fn enabled(&self) -> impl Iterator<Item = &Exchange> {
+        self.exchanges.iter().filter(|exchange| exchange.enabled)
+    }
+
+    pub fn get(&self, id: i32) -> Option<&Exchange> {
+        self.by_id.get(&id).map(|&i| &self.exchanges[i])
+    }
+
+    pub fn name(&self, id: i32) -> Option<String> {
+        self.get(id).map(|exchange| exchange.name.clone())
+    }
+
+    /// Exchanges missing from the table are treated as per-row, base-volume feeds.
+    pub fn volume_semantics(&self, id: i32) -> VolumeSemantics {
+        self.get(id)
+            .map(|exchange| exchange.volume_semantics)
+            .unwrap_or_default()
+    }
+
+    pub fn quote_convention(&self, id: i32) -> QuoteConvention {
+        self.get(id)
+            .map(|exchange| exchange.quote_convention)
+            .unwrap_or_default()
+    }
+
+    /// Base-currency volume of a group of rows, from `total_volume` summed as
+    /// reported and divided by each row's price. `None` for exchanges that only
+    /// report rolling 24h totals, which cannot be split into buckets.
+    pub fn base_volume(&self, id: i32, volume: f64, volume_over_price: Option<f64>) -> Option<f64> {
+        if self.volume_semantics(id) == VolumeSemantics::Rolling24h {
+            return None;
+        }
+        match self.quote_convention(id) {
+            QuoteConvention::Base => Some(volume),
+            QuoteConvention::Quote => volume_over_price,
+        }
+    }
+
+    /// Quote-currency volume of a group of rows, from `total_volume` summed as
+    /// reported and multiplied by each row's price. `None` like
+    /// [`base_volume`](Self::base_volume).
+    pub fn quote_volume(&self, id: i32, volume: f64, price_volume: f64) -> Option<f64> {
+        if self.volume_semantics(id) == VolumeSemantics::Rolling24h {
+            return None;
+        }
+        match self.quote_convention(id) {
+            QuoteConvention::Base => Some(price_volume),
+            QuoteConvention::Quote => Some(volume),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_between_base_and_quote() {
+        let base = QuoteConvention::Base;
+        let quote = QuoteConvention::Quote;
+        assert_eq!(base.convert(2.0, 50.0, "BTC", "USDT", "BTC"), Some(2.0));
+        assert_eq!(base.convert(2.0, 50.0, "BTC", "USDT", "USDT"), Some(100.0));
+        assert_eq!(
+            quote.convert(100.0, 50.0, "BTC", "USDT", "USDT"),
+            Some(100.0)
+        );
+        assert_eq!(quote.convert(100.0, 50.0, "BTC", "USDT", "BTC"), Some(2.0));
+        assert_eq!(base.convert(2.0, 50.0, "BTC", "USDT", "ETH"), None);
+    }
+
+    #[test]
+    fn bucket_volumes_follow_the_exchange_conventions() {
+        let exchange = |id, volume_semantics, quote_convention| Exchange {
+            id,
+            name: format!("Exchange {}", id),
+            slug: format!("exchange-{}", id),
+            volume_semantics,
+            quote_convention,
+            enabled: true,
+        };
+        let registry = ExchangeRegistry::new(vec![
+            exchange(0, VolumeSemantics::PerRow, QuoteConvention::Base),
+            exchange(1, VolumeSemantics::Rolling24h, QuoteConvention::Quote),
+            exchange(2, VolumeSemantics::PerRow, QuoteConvention::Quote),
+        ]);
+        // Two base rows of 1 BTC at 50 and 70 USDT.
+        assert_eq!(
+            registry.base_volume(0, 2.0, Some(1.0 / 50.0 + 1.0 / 70.0)),
+            Some(2.0)
+        );
+        assert_eq!(registry.quote_volume(0, 2.0, 120.0), Some(120.0));
+        // The same trades reported in USDT.
+        assert_eq!(registry.base_volume(2, 120.0, Some(2.0)), Some(2.0));
+        assert_eq!(
+            registry.quote_volume(2, 120.0, 50.0 * 50.0 + 70.0 * 70.0),
+            Some(120.0)
+        );
+        assert_eq!(registry.base_volume(1, 120.0, Some(2.0)), None);
+        assert_eq!(registry.quote_volume(1, 120.0, 0.0), None);
+        // Unknown exchanges are per-row, base-volume feeds.
+        assert_eq!(registry.base_volume(9, 3.0, None), Some(3.0));
+    }
+
+    #[test]
+    fn unknown_exchanges_use_defaults() {
+        let registry = ExchangeRegistry::new(vec![Exchange {
+            id: 1,
+            name: "Upbit".to_string(),
+            slug: "upbit".to_string(),
+            volume_semantics: VolumeSemantics::Rolling24h,
+            quote_convention: QuoteConvention::Quote,
+            enabled: true,
+        }]);
+        assert_eq!(registry.name(1).as_deref(), Some("Upbit"));
+        assert_eq!(registry.volume_semantics(1), VolumeSemantics::Rolling24h);
+        assert_eq!(registry.name(7), None);
+        assert_eq!(registry.volume_semantics(7), VolumeSemantics::PerRow);
+        assert_eq!(registry.quote_convention(7), QuoteConvention::Base);
+    }
+}
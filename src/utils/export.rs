@@ -0,0 +1,571 @@
+use crate::utils::errors::ApiError;
+use arrow_array::{ArrayRef, Float64Array, Int64Array, RecordBatch, StringArray};
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{DataType, Field, Schema};
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+use serde::Serialize;
+use std::{future::Future, io, sync::Arc};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::error;
+
+const ARROW_BATCH_ROWS: usize = 1024;
+const CHANNEL_CAPACITY: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Json,
+    Csv,
+    MessagePack,
+    Arrow,
+}
+
+impl OutputFormat {
+    /// Picks the output format from `format=` if present, otherwise the
+    /// recognised media type in `Accept` with the highest `q` weight, the
+    /// first listed on a tie. Types with `q=0` are refused. Defaults to JSON.
+    pub fn negotiate(format: Option<&str>, headers: &HeaderMap) -> Result<Self, ApiError> {
+        if let Some(format) = format {
+            return match format.to_ascii_lowercase().as_str() {
+                "json" => Ok(OutputFormat::Json),
+                "csv" => Ok(OutputFormat::Csv),
+                "msgpack" | "messagepack" => Ok(OutputFormat::MessagePack),
+                "arrow" => Ok(OutputFormat::Arrow),
+                _ => Err(ApiError::InvalidRequest(format!(
+                    "Unknown format '{}', expected one of json, csv, msgpack, arrow",
+                    format
+                ))),
+            };
+        }
+        let accept = headers
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("");
+        let mut best: Option<(OutputFormat, f32)> = None;
+        for media_range in accept.split(',') {
+            let mut params = media_range.split(';');
+            let format = match params.next().unwrap_or("").trim() {
+                "application/json" | "*/*" => OutputFormat::Json,
+                "text/csv" => OutputFormat::Csv,
+                "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                    OutputFormat::MessagePack
+                }
+                "application/vnd.apache.arrow.stream" => OutputFormat::Arrow,
+                _ => continue,
+            };
+            let weight = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if weight > 0.0 && best.is_none_or(|(_, best_weight)| weight > best_weight) {
+                best = Some((format, weight));
+            }
+        }
+        Ok(best.map_or(OutputFormat::Json, |(format, _)| format))
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            OutputFormat::Json => "application/json",
+            OutputFormat::Csv => "text/csv; charset=utf-8",
+            OutputFormat::MessagePack => "application/msgpack",
+            OutputFormat::Arrow => "application/vnd.apache.arrow.stream",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Int,
+    Float,
+    Text,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell {
+    Int(i64),
+    Float(f64),
+    Text(Option<String>),
+    Null,
+}
+
+/// A flat row that can be written as CSV or an Arrow column set as well as
+/// through serde for JSON and MessagePack.
+pub trait ExportRow: Serialize + Send + 'static {
+    const COLUMNS: &'static [(&'static str, ColumnType)];
+    fn cells(&self) -> Vec<Cell>;
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn csv_line<I: IntoIterator<Item = String>>(fields: I) -> String {
+    let mut line = fields.into_iter().collect::<Vec<_>>().join(",");
+    line.push('\n');
+    line
+}
+
+fn arrow_schema<T: ExportRow>() -> Arc<Schema> {
+    Arc::new(Schema::new(
+        T::COLUMNS
+            .iter()
+            .map(|(name, column_type)| match column_type {
+                ColumnType::Int => Field::new(*name, DataType::Int64, true),
+                ColumnType::Float => Field::new(*name, DataType::Float64, true),
+                ColumnType::Text => Field::new(*name, DataType::Utf8, true),
+            })
+            .collect::<Vec<_>>(),
+    ))
+}
+
+fn arrow_batch<T: ExportRow>(schema: &Arc<Schema>, rows: &[Vec<Cell>]) -> io::Result<RecordBatch> {
+    let columns =
+        T::COLUMNS
+            .iter()
+            .enumerate()
+            .map(|(i, (_, column_type))| -> ArrayRef {
+                match column_type {
+                    ColumnType::Int => Arc::new(Int64Array::from_iter(rows.iter().map(|row| {
+                        match &row[i] {
+                            Cell::Int(value) => Some(*value),
+                            _ => None,
+                        }
+                    }))),
+                    ColumnType::Float => {
+                        Arc::new(Float64Array::from_iter(rows.iter().map(|row| {
+                            match &row[i] {
+                                Cell::Float(value) => Some(*value),
+                                _ => None,
+                            }
+                        })))
+                    }
+                    ColumnType::Text => Arc::new(StringArray::from_iter(rows.iter().map(|row| {
+                        match &row[i] {
+                            Cell::Text(value) => value.clone(),
+                            _ => None,
+                        }
+                    }))),
+                }
+            })
+            .collect();
+    RecordBatch::try_new(schema.clone(), columns).map_err(io::Error::other)
+}
+
+/// Incrementally encodes rows of `T`, handing back whatever bytes are ready
+/// to go on the wire after each call.
+struct Encoder<T: ExportRow> {
+    format: OutputFormat,
+    rows: usize,
+    envelope: Option<serde_json::Value>,
+    wrapped: bool,
+    arrow: Option<StreamWriter<Vec<u8>>>,
+    arrow_schema: Arc<Schema>,
+    pending: Vec<Vec<Cell>>,
+    _row: std::marker::PhantomData<T>,
+}
+
+impl<T: ExportRow> Encoder<T> {
+    fn new(format: OutputFormat, envelope: Option<serde_json::Value>) -> Self {
+        Encoder {
+            format,
+            rows: 0,
+            envelope,
+            wrapped: false,
+            arrow: None,
+            arrow_schema: arrow_schema::<T>(),
+            pending: vec![],
+            _row: std::marker::PhantomData,
+        }
+    }
+
+    fn begin(&mut self) -> io::Result<Vec<u8>> {
+        match self.format {
+            OutputFormat::Json => {
+                // `{...envelope, "data": [` or a bare `[` without an envelope.
+                self.wrapped = self.envelope.is_some();
+                Ok(match self.envelope.take() {
+                    Some(serde_json::Value::Object(map)) if !map.is_empty() => {
+                        let mut prefix = serde_json::to_string(&map)?;
+                        prefix.pop();
+                        prefix.push_str(",\"data\":[");
+                        prefix.into_bytes()
+                    }
+                    Some(_) => b"{\"data\":[".to_vec(),
+                    None => b"[".to_vec(),
+                })
+            }
+            OutputFormat::Csv => {
+                Ok(csv_line(T::COLUMNS.iter().map(|(name, _)| name.to_string())).into_bytes())
+            }
+            OutputFormat::MessagePack => Ok(vec![]),
+            OutputFormat::Arrow => {
+                let mut writer =
+                    StreamWriter::try_new(vec![], &self.arrow_schema).map_err(io::Error::other)?;
+                let bytes = std::mem::take(writer.get_mut());
+                self.arrow = Some(writer);
+                Ok(bytes)
+            }
+        }
+    }
+
+    fn push(&mut self, row: T) -> io::Result<Vec<u8>> {
+        self.rows += 1;
+        match self.format {
+            OutputFormat::Json => {
+                let mut bytes = if self.rows > 1 { vec![b','] } else { vec![] };
+                serde_json::to_writer(&mut bytes, &row)?;
+                Ok(bytes)
+            }
+            OutputFormat::Csv => Ok(csv_line(row.cells().into_iter().map(|cell| match cell {
+                Cell::Int(value) => value.to_string(),
+                Cell::Float(value) => value.to_string(),
+                Cell::Text(value) => csv_field(&value.unwrap_or_default()),
+                Cell::Null => String::new(),
+            }))
+            .into_bytes()),
+            // A MessagePack stream is a plain concatenation of one map per row.
+            OutputFormat::MessagePack => rmp_serde::to_vec_named(&row).map_err(io::Error::other),
+            OutputFormat::Arrow => {
+                self.pending.push(row.cells());
+                if self.pending.len() >= ARROW_BATCH_ROWS {
+                    self.flush_arrow()
+                } else {
+                    Ok(vec![])
+                }
+            }
+        }
+    }
+
+    fn flush_arrow(&mut self) -> io::Result<Vec<u8>> {
+        let Some(writer) = self.arrow.as_mut() else {
+            return Ok(vec![]);
+        };
+        if !self.pending.is_empty() {
+            let batch = arrow_batch::<T>(&self.arrow_schema, &self.pending)?;
+            self.pending.clear();
+            writer.write(&batch).map_err(io::Error::other)?;
+        }
+        Ok(std::mem::take(writer.get_mut()))
+    }
+
+    fn finish(&mut self) -> io::Result<Vec<u8>> {
+        match self.format {
+            OutputFormat::Json => Ok(if self.wrapped {
+                b"]}".to_vec()
+            } else {
+                b"]".to_vec()
+            }),
+            OutputFormat::Csv | OutputFormat::MessagePack => Ok(vec![]),
+            OutputFormat::Arrow => {
+                let mut bytes = self.flush_arrow()?;
+                if let Some(writer) = self.arrow.as_mut() {
+                    writer.finish().map_err(io::Error::other)?;
+                    bytes.extend(std::mem::take(writer.get_mut()));
+                }
+                Ok(bytes)
+            }
+        }
+    }
+}
+
+/// Receives rows from a producer and forwards encoded bytes to the response body.
+pub struct RowSink<T: ExportRow> {
+    encoder: Encoder<T>,
+    tx: mpsc::Sender<io::Result<Bytes>>,
+}
+
+impl<T: ExportRow> RowSink<T> {
+    /// Returns `false` once the client has gone away, so producers can stop early.
+    pub async fn send(&mut self, row: T) -> bool {
+        match self.encoder.push(row) {
+            Ok(bytes) => self.write(bytes).await,
+            Err(e) => {
+                let _ = self.tx.send(Err(e)).await;
+                false
+            }
+        }
+    }
+
+    async fn write(&mut self, bytes: Vec<u8>) -> bool {
+        if bytes.is_empty() {
+            return !self.tx.is_closed();
+        }
+        self.tx.send(Ok(Bytes::from(bytes))).await.is_ok()
+    }
+}
+
+/// Serializes a series response without its `data` field, for use as the
+/// JSON envelope of a streamed export.
+pub fn envelope<S: Serialize>(meta: &S) -> Option<serde_json::Value> {
+    let mut value = serde_json::to_value(meta).ok()?;
+    if let serde_json::Value::Object(map) = &mut value {
+        map.remove("data");
+    }
+    Some(value)
+}
+
+/// Streams rows produced by `producer` in the requested format. The producer runs
+/// on its own task and pushes rows one at a time, so nothing is buffered beyond a
+/// small channel and one Arrow batch.
+///
+/// For JSON the `envelope` object, if any, is written around the rows as
+/// `{...envelope, "data": [...]}`.
+pub fn stream_rows<T, F, Fut>(
+    format: OutputFormat,
+    envelope: Option<serde_json::Value>,
+    producer: F,
+) -> Response
+where
+    T: ExportRow,
+    F: FnOnce(RowSink<T>) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<RowSink<T>, ApiError>> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+    let abort_tx = tx.clone();
+    tokio::spawn(async move {
+        let mut sink = RowSink {
+            encoder: Encoder::new(format, envelope),
+            tx,
+        };
+        match sink.encoder.begin() {
+            Ok(bytes) => {
+                if !sink.write(bytes).await {
+                    return;
+                }
+            }
+            Err(e) => {
+                let _ = sink.tx.send(Err(e)).await;
+                return;
+            }
+        }
+        match producer(sink).await {
+            Ok(mut sink) => match sink.encoder.finish() {
+                Ok(bytes) => {
+                    sink.write(bytes).await;
+                }
+                Err(e) => {
+                    let _ = sink.tx.send(Err(e)).await;
+                }
+            },
+            Err(e) => {
+                // Headers are already out, so the best we can do is abort the body.
+                error!("Export stream failed: {}", e);
+                let _ = abort_tx.send(Err(io::Error::other(e.to_string()))).await;
+            }
+        }
+    });
+    let mut response = Body::from_stream(ReceiverStream::new(rx)).into_response();
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(format.content_type()),
+    );
+    vary_accept(response)
+}
+
+/// Marks a response of a handler that negotiates its format. Without
+/// `format=` the body depends on `Accept`, and shared caches cannot tell from
+/// the URL which case they are looking at.
+pub fn vary_accept(mut response: Response) -> Response {
+    response
+        .headers_mut()
+        .insert(header::VARY, HeaderValue::from_static("accept"));
+    response
+}
+
+/// Convenience wrapper around [`stream_rows`] for results that are already in memory.
+pub fn export_rows<T: ExportRow>(
+    format: OutputFormat,
+    envelope: Option<serde_json::Value>,
+    rows: Vec<T>,
+) -> Response {
+    stream_rows(format, envelope, move |mut sink| async move {
+        for row in rows {
+            if !sink.send(row).await {
+                break;
+            }
+        }
+        Ok(sink)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::Array;
+    use arrow_ipc::reader::StreamReader;
+    use serde::Deserialize;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Row {
+        id: i64,
+        price: Option<f64>,
+        name: Option<String>,
+    }
+
+    impl ExportRow for Row {
+        const COLUMNS: &'static [(&'static str, ColumnType)] = &[
+            ("id", ColumnType::Int),
+            ("price", ColumnType::Float),
+            ("name", ColumnType::Text),
+        ];
+        fn cells(&self) -> Vec<Cell> {
+            vec![
+                Cell::Int(self.id),
+                self.price.map_or(Cell::Null, Cell::Float),
+                Cell::Text(self.name.clone()),
+            ]
+        }
+    }
+
+    fn rows() -> Vec<Row> {
+        vec![
+            Row {
+                id: 1,
+                price: Some(1.5),
+                name: Some("BTC-USDT".to_string()),
+            },
+            Row {
+                id: 2,
+                price: None,
+                name: None,
+            },
+        ]
+    }
+
+    fn encode(
+        format: OutputFormat,
+        envelope: Option<serde_json::Value>,
+        rows: Vec<Row>,
+    ) -> Vec<u8> {
+        let mut encoder = Encoder::<Row>::new(format, envelope);
+        let mut bytes = encoder.begin().unwrap();
+        for row in rows {
+            bytes.extend(encoder.push(row).unwrap());
+        }
+        bytes.extend(encoder.finish().unwrap());
+        bytes
+    }
+
+    fn accept(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn csv_quotes_special_characters() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        let row = Row {
+            id: 3,
+            price: Some(2.0),
+            name: Some("a,\"b\"".to_string()),
+        };
+        let csv = String::from_utf8(encode(OutputFormat::Csv, None, vec![row])).unwrap();
+        assert_eq!(csv, "id,price,name\n3,2,\"a,\"\"b\"\"\"\n");
+    }
+
+    #[test]
+    fn json_envelope_wraps_rows() {
+        let meta = envelope(&serde_json::json!({"symbol": "BTC", "data": [1, 2]}));
+        let json: serde_json::Value =
+            serde_json::from_slice(&encode(OutputFormat::Json, meta, rows())).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "symbol": "BTC",
+                "data": [
+                    {"id": 1, "price": 1.5, "name": "BTC-USDT"},
+                    {"id": 2, "price": null, "name": null},
+                ],
+            })
+        );
+        let bare: serde_json::Value =
+            serde_json::from_slice(&encode(OutputFormat::Json, None, vec![])).unwrap();
+        assert_eq!(bare, serde_json::json!([]));
+    }
+
+    #[test]
+    fn msgpack_round_trip() {
+        let bytes = encode(OutputFormat::MessagePack, None, rows());
+        let mut deserializer = rmp_serde::Deserializer::new(bytes.as_slice());
+        let decoded: Vec<Row> = (0..2)
+            .map(|_| Row::deserialize(&mut deserializer).unwrap())
+            .collect();
+        assert_eq!(decoded, rows());
+    }
+
+    #[test]
+    fn arrow_round_trip() {
+        let bytes = encode(OutputFormat::Arrow, None, rows());
+        let batches: Vec<RecordBatch> = StreamReader::try_new(bytes.as_slice(), None)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(batch.schema(), arrow_schema::<Row>());
+        let ids = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        let prices = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        let names = batch
+            .column(2)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(ids.values(), &[1, 2]);
+        assert_eq!(prices.value(0), 1.5);
+        assert!(prices.is_null(1));
+        assert_eq!(names.value(0), "BTC-USDT");
+        assert!(names.is_null(1));
+    }
+
+    #[test]
+    fn negotiates_accept_header() {
+        let negotiate = |value: &str| OutputFormat::negotiate(None, &accept(value)).unwrap();
+        assert_eq!(negotiate(""), OutputFormat::Json);
+        assert_eq!(negotiate("text/html"), OutputFormat::Json);
+        assert_eq!(negotiate("text/csv"), OutputFormat::Csv);
+        assert_eq!(
+            negotiate("text/html, text/csv;charset=utf-8"),
+            OutputFormat::Csv
+        );
+        assert_eq!(
+            negotiate("application/json;q=0.5, application/vnd.apache.arrow.stream"),
+            OutputFormat::Arrow
+        );
+        assert_eq!(
+            negotiate("text/csv;q=0, application/json"),
+            OutputFormat::Json
+        );
+        assert_eq!(negotiate("text/csv;q=0"), OutputFormat::Json);
+        assert_eq!(
+            negotiate("application/x-msgpack;q=0.9, */*;q=0.1"),
+            OutputFormat::MessagePack
+        );
+        assert_eq!(
+            OutputFormat::negotiate(Some("CSV"), &accept("application/json")).unwrap(),
+            OutputFormat::Csv
+        );
+        assert!(OutputFormat::negotiate(Some("xml"), &HeaderMap::new()).is_err());
+    }
+}
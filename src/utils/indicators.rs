@@ -0,0 +1,413 @@
+use crate::utils::errors::ApiError;
+
+const MAX_INDICATORS: usize = 10;
+const MAX_PERIOD: usize = 500;
+/// Recursive indicators (EMA, RSI) are seeded this many periods before the
+/// first returned value, after which the seed's influence is negligible.
+const CONVERGENCE_PERIODS: usize = 3;
+
+/// One bucket of a price series.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bar {
+    pub close: f64,
+    /// Base-currency volume, `None` when the exchange's volume cannot be
+    /// split into buckets.
+    pub volume: Option<f64>,
+    /// Sum of price times base volume over the rows of the bucket.
+    pub price_volume: Option<f64>,
+}
+
+/// An indicator spec such as `sma:20`, `ema:50`, `vwap`, `vwap:20`, `rsi:14`
+/// or `bb:20:2`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Indicator {
+    Sma(usize),
+    Ema(usize),
+    /// Rolling over the given number of buckets, or anchored at the start of
+    /// the requested range.
+    Vwap(Option<usize>),
+    Rsi(usize),
+    /// Period and number of standard deviations.
+    Bollinger(usize, f64),
+}
+
+impl Indicator {
+    pub fn parse(spec: &str) -> Result<Self, ApiError> {
+        let invalid = |reason: &str| {
+            ApiError::InvalidRequest(format!("Invalid indicator '{}': {}", spec, reason))
+        };
+        let period = |value: Option<&str>| -> Result<usize, ApiError> {
+            let period = value
+                .ok_or_else(|| invalid("missing period"))?
+                .parse::<usize>()
+                .map_err(|_| invalid("period must be a positive integer"))?;
+            if period == 0 || period > MAX_PERIOD {
+                return Err(invalid(&format!(
+                    "period must be between 1 and {}",
+                    MAX_PERIOD
+                )));
+            }
+            Ok(period)
+        };
+        let mut parts = spec.split(':');
+        let name = parts.next().unwrap_or_default();
+        let first = parts.next();
+        let second = parts.next();
+        let indicator = match name {
+            "sma" => Indicator::Sma(period(first)?),
+            "ema" => Indicator::Ema(period(first)?),
+            "vwap" => Indicator::Vwap(first.map(|value| period(Some(value))).transpose()?),
+            "rsi" => Indicator::Rsi(period(first)?),
+            "bb" => {
+                let deviations = match second {
+                    Some(value) => value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|k| *k > 0.0 && *k <= 10.0)
+                        .ok_or_else(|| invalid("deviations must be between 0 and 10"))?,
+                    None => 2.0,
+                };
+                return match parts.next() {
+                    Some(_) => Err(invalid("too many parameters")),
+                    None => Ok(Indicator::Bollinger(period(first)?, deviations)),
+                };
+            }
+            _ => {
+                return Err(invalid(
+                    "expected one of sma:N, ema:N, vwap, vwap:N, rsi:N or bb:N:K",
+                ))
+            }
+        };
+        if second.is_some() {
+            return Err(invalid("too many parameters"));
+        }
+        Ok(indicator)
+    }
+
+    /// Parses a comma-separated list of specs, dropping duplicates.
+    pub fn parse_list(specs: &str) -> Result<Vec<Self>, ApiError> {
+        let mut indicators: Vec<Indicator> = vec![];
+        for spec in specs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let indicator = Indicator::parse(spec)?;
+            if !indicators.contains(&indicator) {
+                indicators.push(indicator);
+            }
+        }
+        if indicators.is_empty() || indicators.len() > MAX_INDICATORS {
+            return Err(ApiError::InvalidRequest(format!(
+                "Between 1 and {} indicators are required",
+                MAX_INDICATORS
+            )));
+        }
+        Ok(indicators)
+    }
+
+    /// Buckets needed before the first returned value for it to be correct.
+    pub fn warm_up(&self) -> usize {
+        match self {
+            Indicator::Sma(period) | Indicator::Bollinger(period, _) => period - 1,
+            Indicator::Vwap(period) => period.map_or(0, |period| period - 1),
+            Indicator::Ema(period) => CONVERGENCE_PERIODS * period,
+            Indicator::Rsi(period) => CONVERGENCE_PERIODS * period + 1,
+        }
+    }
+
+    /// Names of the series this indicator produces, in [`compute`](Self::compute) order.
+    pub fn names(&self) -> Vec<String> {
+        match self {
+            Indicator::Sma(period) => vec![format!("sma_{}", period)],
+            Indicator::Ema(period) => vec![format!("ema_{}", period)],
+            Indicator::Vwap(None) => vec!["vwap".to_string()],
+            Indicator::Vwap(Some(period)) => vec![format!("vwap_{}", period)],
+            Indicator::Rsi(period) => vec![format!("rsi_{}", period)],
+            Indicator::Bollinger(period, deviations) => ["middle", "upper", "lower"]
+                .iter()
+                .map(|band| format!("bb_{}_{}_{}", period, deviations, band))
+                .collect(),
+        }
+    }
+
+    /// Values for every bar, `None` where there is not enough history. `start`
+    /// is the first bar of the requested range, where anchored VWAP begins.
+    pub fn compute(&self, bars: &[Bar], start: usize) -> Vec<Vec<Option<f64>>> {
+        let closes: Vec<f64> = bars.iter().map(|bar| bar.close).collect();
+        match *self {
+            Indicator::Sma(period) => vec![sma(&closes, period)],
+            Indicator::Ema(period) => vec![ema(&closes, period)],
+            Indicator::Vwap(period) => vec![vwap(bars, period, start)],
+            Indicator::Rsi(period) => vec![rsi(&closes, period)],
+            Indicator::Bollinger(period, deviations) => {
+                let middle = sma(&closes, period);
+                let deviation = rolling(&closes, period, |window| {
+                    let mean = window.iter().sum::<f64>() / window.len() as f64;
+                    (window.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / window.len() as f64)
+                        .sqrt()
+                });
+                let band = |sign: f64| -> Vec<Option<f64>> {
+                    middle
+                        .iter()
+                        .zip(&deviation)
+                        .map(|(m, d)| Some(m.as_ref()? + sign * deviations * d.as_ref()?))
+                        .collect()
+                };
+                vec![band(0.0), band(1.0), band(-1.0)]
+            }
+        }
+    }
+}
+
+/// Applies `f` to every full window of `period` values ending at each index.
+fn rolling(values: &[f64], period: usize, f: impl Fn(&[f64]) -> f64) -> Vec<Option<f64>> {
+    (0..values.len())
+        .map(|i| (i + 1 >= period).then(|| f(&values[i + 1 - period..=i])))
+        .collect()
+}
+
+fn sma(values: &[f64], period: usize) -> Vec<Option<f64>> {
+    rolling(values, period, |window| {
+        window.iter().sum::<f64>() / window.len() as f64
+    })
+}
+
+/// Seeded with the SMA of the first `period` values.
+fn ema(values: &[f64], period: usize) -> Vec<Option<f64>> {
+    let alpha = 2.0 / (period as f64 + 1.0);
+    let mut current: Option<f64> = None;
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            current = match current {
+                Some(previous) => Some(previous + alpha * (value - previous)),
+                None if i + 1 == period => {
+                    Some(values[..period].iter().sum::<f64>() / period as f64)
+                }
+                None => None,
+            };
+            current
+        })
+        .collect()
+}
+
+/// Wilder's RSI: average gains and losses seeded over the first `period`
+/// changes and smoothed with a factor of `1 / period` afterwards.
+fn rsi(values: &[f64], period: usize) -> Vec<Option<f64>> {
+    let mut result = vec![None; values.len()];
+    if values.len() <= period {
+        return result;
+    }
+    let change = |i: usize| values[i] - values[i - 1];
+    let (mut gain, mut loss) = (1..=period).fold((0.0, 0.0), |(gain, loss), i| {
+        let change = change(i);
+        (gain + change.max(0.0), loss + (-change).max(0.0))
+    });
+    gain /= period as f64;
+    loss /= period as f64;
+    let index = |gain: f64, loss: f64| {
+        if loss == 0.0 {
+            if gain == 0.0 {
+                50.0
+            } else {
+                100.0
+            }
+        } else {
+            100.0 - 100.0 / (1.0 + gain / loss)
+        }
+    };
+    result[period] = Some(index(gain, loss));
+    for (i, value) in result.iter_mut().enumerate().skip(period + 1) {
+        let change = change(i);
+        gain = (gain * (period - 1) as f64 + change.max(0.0)) / period as f64;
+        loss = (loss * (period - 1) as f64 + (-change).max(0.0)) / period as f64;
+        *value = Some(index(gain, loss));
+    }
+    result
+}
+
+/// Volume-weighted price over the last `period` bars, or cumulatively from
+/// `start` when there is no period. `None` while no volume has traded or when
+/// a bar in the window has no volume.
+fn vwap(bars: &[Bar], period: Option<usize>, start: usize) -> Vec<Option<f64>> {
+    let ratio = |window: &[Bar]| {
+        let volume: f64 = window.iter().map(|bar| bar.volume).sum::<Option<f64>>()?;
+        let price_volume: f64 = window
+            .iter()
+            .map(|bar| bar.price_volume)
+            .sum::<Option<f64>>()?;
+        (volume != 0.0).then(|| price_volume / volume)
+    };
+    (0..bars.len())
+        .map(|i| match period {
+            Some(period) if i + 1 >= period => ratio(&bars[i + 1 - period..=i]),
+            Some(_) => None,
+            None if i >= start => ratio(&bars[start..=i]),
+            None => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bars(closes: &[f64]) -> Vec<Bar> {
+        closes
+            .iter()
+            .map(|&close| Bar {
+                close,
+                volume: Some(1.0),
+                price_volume: Some(close),
+            })
+            .collect()
+    }
+
+    fn rounded(values: &[Option<f64>]) -> Vec<Option<f64>> {
+        values
+            .iter()
+            .map(|v| v.map(|v| (v * 1e6).round() / 1e6))
+            .collect()
+    }
+
+    #[test]
+    fn parses_specs() {
+        assert_eq!(
+            Indicator::parse_list("sma:20, ema:50,vwap,vwap:10,rsi:14,bb:20:2,sma:20").unwrap(),
+            vec![
+                Indicator::Sma(20),
+                Indicator::Ema(50),
+                Indicator::Vwap(None),
+                Indicator::Vwap(Some(10)),
+                Indicator::Rsi(14),
+                Indicator::Bollinger(20, 2.0),
+            ]
+        );
+        assert_eq!(
+            Indicator::parse("bb:20").unwrap(),
+            Indicator::Bollinger(20, 2.0)
+        );
+        for invalid in [
+            "sma",
+            "sma:0",
+            "sma:x",
+            "ema:20:1",
+            "bb:20:0",
+            "bb:20:2:1",
+            "macd:12",
+        ] {
+            assert!(Indicator::parse(invalid).is_err(), "{}", invalid);
+        }
+        assert!(Indicator::parse_list(" , ").is_err());
+    }
+
+    #[test]
+    fn simple_and_exponential_moving_averages() {
+        let closes = [1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_eq!(
+            Indicator::Sma(3).compute(&bars(&closes), 0),
+            vec![vec![None, None, Some(2.0), Some(3.0), Some(4.0)]]
+        );
+        // Seeded with SMA(3) = 2, then alpha = 0.5.
+        assert_eq!(
+            Indicator::Ema(3).compute(&bars(&closes), 0),
+            vec![vec![None, None, Some(2.0), Some(3.0), Some(4.0)]]
+        );
+        assert_eq!(
+            Indicator::Ema(2).compute(&bars(&[2.0, 4.0, 10.0]), 0),
+            vec![vec![None, Some(3.0), Some(3.0 + 2.0 / 3.0 * 7.0)]]
+        );
+    }
+
+    #[test]
+    fn wilder_rsi() {
+        let closes = [10.0, 11.0, 10.0, 12.0, 12.0];
+        // Seed over 3 changes: gains 1 + 2, losses 1 -> RS = 3.
+        // Next change 0: gain 2/3, loss 2/9 -> RS = 3.
+        assert_eq!(
+            rounded(&Indicator::Rsi(3).compute(&bars(&closes), 0)[0]),
+            vec![None, None, None, Some(75.0), Some(75.0)]
+        );
+        assert_eq!(
+            Indicator::Rsi(2).compute(&bars(&[1.0, 2.0, 3.0]), 0)[0][2],
+            Some(100.0)
+        );
+        assert_eq!(
+            Indicator::Rsi(2).compute(&bars(&[1.0, 1.0, 1.0]), 0)[0][2],
+            Some(50.0)
+        );
+    }
+
+    #[test]
+    fn bollinger_bands() {
+        let series = Indicator::Bollinger(2, 2.0).compute(&bars(&[1.0, 3.0, 3.0]), 0);
+        assert_eq!(series[0], vec![None, Some(2.0), Some(3.0)]);
+        assert_eq!(series[1], vec![None, Some(4.0), Some(3.0)]);
+        assert_eq!(series[2], vec![None, Some(0.0), Some(3.0)]);
+        assert_eq!(
+            Indicator::Bollinger(20, 2.5).names(),
+            vec!["bb_20_2.5_middle", "bb_20_2.5_upper", "bb_20_2.5_lower"]
+        );
+    }
+
+    #[test]
+    fn anchored_and_rolling_vwap() {
+        let bars = vec![
+            Bar {
+                close: 10.0,
+                volume: Some(1.0),
+                price_volume: Some(10.0),
+            },
+            Bar {
+                close: 20.0,
+                volume: Some(0.0),
+                price_volume: Some(0.0),
+            },
+            Bar {
+                close: 30.0,
+                volume: Some(3.0),
+                price_volume: Some(90.0),
+            },
+        ];
+        assert_eq!(
+            Indicator::Vwap(None).compute(&bars, 1),
+            vec![vec![None, None, Some(30.0)]]
+        );
+        assert_eq!(
+            Indicator::Vwap(None).compute(&bars, 0),
+            vec![vec![Some(10.0), Some(10.0), Some(25.0)]]
+        );
+        assert_eq!(
+            Indicator::Vwap(Some(2)).compute(&bars, 0),
+            vec![vec![None, Some(10.0), Some(30.0)]]
+        );
+    }
+
+    #[test]
+    fn vwap_needs_volume_for_every_bar() {
+        let mut bars = bars(&[10.0, 20.0, 30.0]);
+        bars[1].volume = None;
+        bars[1].price_volume = None;
+        assert_eq!(
+            Indicator::Vwap(Some(2)).compute(&bars, 0),
+            vec![vec![None, None, None]]
+        );
+        assert_eq!(
+            Indicator::Vwap(None).compute(&bars, 2),
+            vec![vec![None, None, Some(30.0)]]
+        );
+    }
+
+    #[test]
+    fn warm_up_covers_the_first_value() {
+        assert_eq!(Indicator::Sma(20).warm_up(), 19);
+        assert_eq!(Indicator::Vwap(None).warm_up(), 0);
+        let closes: Vec<f64> = (0..100).map(|i| i as f64).collect();
+        for indicator in [
+            Indicator::Sma(20),
+            Indicator::Rsi(14),
+            Indicator::Bollinger(5, 2.0),
+        ] {
+            let series = indicator.compute(&bars(&closes), 0);
+            assert!(series[0][indicator.warm_up()].is_some(), "{:?}", indicator);
+        }
+    }
+}
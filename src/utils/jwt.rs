@@ -11,10 +11,10 @@ use std::{
     sync::Arc,
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
-use tracing::{error, info};
+use tracing::info;
 
-pub static DECODE_HEADER: Lazy<Validation> = Lazy::new(|| Validation::default());
-pub static ENCODE_HEADER: Lazy<Header> = Lazy::new(|| Header::default());
+pub static DECODE_HEADER: Lazy<Validation> = Lazy::new(Validation::default);
+pub static ENCODE_HEADER: Lazy<Header> = Lazy::new(Header::default);
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub struct UserClaims {
@@ -0,0 +1,216 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{postgres::PgListener, PgPool};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::{error, info, warn};
+
+/// Postgres NOTIFY channels, see `crypto_migrations/`.
+pub const VOLUME_NOTIFY_CHANNEL: &str = "volume_data";
+pub const BALANCE_NOTIFY_CHANNEL: &str = "balance_data";
+
+const LIVE_FEED_CAPACITY: usize = 4096;
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VolumeTick {
+    /// Row id, missing from notifications sent before
+    /// `20241223000000_live_notify_id.sql` was applied.
+    #[serde(default)]
+    pub id: Option<i32>,
+    pub exchange_id: i32,
+    pub token_symbol: String,
+    pub price: f64,
+    pub total_volume: f64,
+    pub day_total_volume: Option<f64>,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BalanceTick {
+    #[serde(default)]
+    pub id: Option<i32>,
+    pub exchange_id: i32,
+    pub token_symbol: String,
+    pub wallet_balance: f64,
+    pub transfer_balance: f64,
+    pub timestamp: i64,
+}
+
+/// A row that just landed in the crypto data database.
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum LiveEvent {
+    Volume(VolumeTick),
+    Balance(BalanceTick),
+}
+
+impl LiveEvent {
+    pub fn from_notification(channel: &str, payload: &str) -> Option<Self> {
+        match channel {
+            VOLUME_NOTIFY_CHANNEL => serde_json::from_str(payload).ok().map(LiveEvent::Volume),
+            BALANCE_NOTIFY_CHANNEL => serde_json::from_str(payload).ok().map(LiveEvent::Balance),
+            _ => None,
+        }
+    }
+
+    /// Subscription channel the event is published on, e.g. `volume:1:BTC-USDT`
+    /// or `balance:1`.
+    pub fn channel(&self) -> String {
+        match self {
+            LiveEvent::Volume(tick) => format!("volume:{}:{}", tick.exchange_id, tick.token_symbol),
+            LiveEvent::Balance(tick) => format!("balance:{}", tick.exchange_id),
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LiveEvent::Volume(_) => "volume",
+            LiveEvent::Balance(_) => "balance",
+        }
+    }
+
+    pub fn timestamp(&self) -> i64 {
+        match self {
+            LiveEvent::Volume(tick) => tick.timestamp,
+            LiveEvent::Balance(tick) => tick.timestamp,
+        }
+    }
+
+    pub fn id(&self) -> Option<i32> {
+        match self {
+            LiveEvent::Volume(tick) => tick.id,
+            LiveEvent::Balance(tick) => tick.id,
+        }
+    }
+
+    pub fn exchange_id(&self) -> i32 {
+        match self {
+            LiveEvent::Volume(tick) => tick.exchange_id,
+            LiveEvent::Balance(tick) => tick.exchange_id,
+        }
+    }
+}
+
+/// Checks a subscription pattern: `volume:{exchange_id}:{symbol}` or
+/// `balance:{exchange_id}`, where any segment after the kind may be `*`.
+pub fn is_valid_channel(pattern: &str) -> bool {
+    let parts: Vec<&str> = pattern.split(':').collect();
+    let exchange_ok = |part: &str| part == "*" || part.parse::<i32>().is_ok();
+    match parts.as_slice() {
+        ["volume", exchange, symbol] => exchange_ok(exchange) && !symbol.is_empty(),
+        ["balance", exchange] => exchange_ok(exchange),
+        _ => false,
+    }
+}
+
+pub fn channel_matches(pattern: &str, channel: &str) -> bool {
+    let pattern: Vec<&str> = pattern.split(':').collect();
+    let channel: Vec<&str> = channel.split(':').collect();
+    pattern.len() == channel.len()
+        && pattern
+            .iter()
+            .zip(&channel)
+            .all(|(p, c)| *p == "*" || p == c)
+}
+
+/// One LISTEN connection on the crypto data database, fanned out to every
+/// subscriber through a broadcast channel.
+#[derive(Clone)]
+pub struct LiveFeed {
+    sender: broadcast::Sender<LiveEvent>,
+}
+
+impl LiveFeed {
+    pub fn start(pool: PgPool) -> Self {
+        let (sender, _) = broadcast::channel(LIVE_FEED_CAPACITY);
+        tokio::spawn(listen(pool, sender.clone()));
+        LiveFeed { sender }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<LiveEvent> {
+        self.sender.subscribe()
+    }
+}
+
+async fn listen(pool: PgPool, sender: broadcast::Sender<LiveEvent>) {
+    loop {
+        let mut listener = match PgListener::connect_with(&pool).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("💥 Error to connect the live feed listener: {}", e);
+                tokio::time::sleep(RECONNECT_DELAY).await;
+                continue;
+            }
+        };
+        if let Err(e) = listener
+            .listen_all([VOLUME_NOTIFY_CHANNEL, BALANCE_NOTIFY_CHANNEL])
+            .await
+        {
+            error!("💥 Error to listen for live feed notifications: {}", e);
+            tokio::time::sleep(RECONNECT_DELAY).await;
+            continue;
+        }
+        info!("✔ Listening for live feed notifications");
+        loop {
+            match listener.recv().await {
+                Ok(notification) => {
+                    match LiveEvent::from_notification(
+                        notification.channel(),
+                        notification.payload(),
+                    ) {
+                        // Sending only fails when nobody is subscribed.
+                        Some(event) => {
+                            let _ = sender.send(event);
+                        }
+                        None => warn!(
+                            "Ignoring malformed notification on {}",
+                            notification.channel()
+                        ),
+                    }
+                }
+                Err(e) => {
+                    error!("💥 Live feed listener failed: {}", e);
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notifications_are_parsed_into_channels() {
+        let event = LiveEvent::from_notification(
+            VOLUME_NOTIFY_CHANNEL,
+            r#"{"id":7,"exchange_id":1,"token_symbol":"BTC-USDT","price":1.5,"total_volume":2.0,"day_total_volume":null,"timestamp":1730764800}"#,
+        )
+        .unwrap();
+        assert_eq!(event.channel(), "volume:1:BTC-USDT");
+        assert_eq!(event.id(), Some(7));
+        let event = LiveEvent::from_notification(
+            BALANCE_NOTIFY_CHANNEL,
+            r#"{"exchange_id":3,"token_symbol":"BTC","wallet_balance":1.0,"transfer_balance":0.5,"timestamp":1730764800}"#,
+        )
+        .unwrap();
+        assert_eq!(event.channel(), "balance:3");
+        assert_eq!(event.id(), None);
+        assert!(LiveEvent::from_notification(BALANCE_NOTIFY_CHANNEL, "{}").is_none());
+    }
+
+    #[test]
+    fn channel_patterns() {
+        assert!(is_valid_channel("volume:1:BTC-USDT"));
+        assert!(is_valid_channel("volume:*:BTC-USDT"));
+        assert!(is_valid_channel("balance:*"));
+        assert!(!is_valid_channel("balance:x"));
+        assert!(!is_valid_channel("volume:1"));
+        assert!(!is_valid_channel("trades:1"));
+        assert!(channel_matches("volume:*:BTC-USDT", "volume:2:BTC-USDT"));
+        assert!(!channel_matches("volume:1:BTC-USDT", "volume:2:BTC-USDT"));
+        assert!(!channel_matches("balance:*", "volume:1:BTC-USDT"));
+    }
+}
@@ -1,7 +1,21 @@
+pub mod alerts;
+pub mod anomalies;
+pub mod cache;
 pub mod config;
+pub mod conversion;
+pub mod downsample;
 pub mod errors;
+pub mod exchanges;
+pub mod export;
+pub mod indicators;
 pub mod jwt;
+pub mod live;
 pub mod oauth;
+pub mod pagination;
 pub mod redis;
+pub mod rollup;
 pub mod session;
 pub mod smtp;
+pub mod symbol;
+pub mod time_window;
+pub mod webhooks;
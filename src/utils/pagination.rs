@@ -0,0 +1,169 @@
+use crate::utils::errors::ApiError;
+use axum::{http::HeaderValue, response::Response};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use sqlx::{postgres::PgRow, Postgres, QueryBuilder, Row};
+
+pub const DEFAULT_PAGE_LIMIT: usize = 1000;
+pub const MAX_PAGE_LIMIT: usize = 10000;
+pub const NEXT_CURSOR_HEADER: &str = "x-next-cursor";
+
+/// Keyset position `(time_interval, exchange_id)` of the last row on a page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    pub time: DateTime<Utc>,
+    pub exchange_id: i32,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(format!("v1:{}:{}", self.time.timestamp(), self.exchange_id))
+    }
+
+    pub fn decode(value: &str) -> Result<Self, ApiError> {
+        let invalid = || ApiError::InvalidRequest("Invalid cursor".to_string());
+        let raw = URL_SAFE_NO_PAD.decode(value).map_err(|_| invalid())?;
+        let raw = String::from_utf8(raw).map_err(|_| invalid())?;
+        let mut parts = raw.split(':');
+        if parts.next() != Some("v1") {
+            return Err(invalid());
+        }
+        let secs = parts
+            .next()
+            .and_then(|secs| secs.parse::<i64>().ok())
+            .ok_or_else(invalid)?;
+        let exchange_id = parts
+            .next()
+            .and_then(|id| id.parse::<i32>().ok())
+            .ok_or_else(invalid)?;
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(Cursor {
+            time: DateTime::from_timestamp(secs, 0).ok_or_else(invalid)?,
+            exchange_id,
+        })
+    }
+}
+
+/// One page of a keyset-paginated series query. Series queries are wrapped as
+/// `SELECT * FROM (<series>) page WHERE (time_interval, exchange_id) > cursor`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Page {
+    pub after: Option<Cursor>,
+    pub limit: usize,
+}
+
+impl Page {
+    /// Returns `None` when neither `cursor` nor `limit` was given.
+    pub fn from_request(
+        cursor: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Option<Self>, ApiError> {
+        if cursor.is_none() && limit.is_none() {
+            return Ok(None);
+        }
+        let limit = limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+        if limit == 0 || limit > MAX_PAGE_LIMIT {
+            return Err(ApiError::InvalidRequest(format!(
+                "limit must be between 1 and {}",
+                MAX_PAGE_LIMIT
+            )));
+        }
+        Ok(Some(Page {
+            after: cursor.map(Cursor::decode).transpose()?,
+            limit,
+        }))
+    }
+
+    pub fn begin() -> QueryBuilder<'static, Postgres> {
+        QueryBuilder::new("SELECT * FROM (")
+    }
+
+    /// Lets the inner query skip raw rows that can only fall in earlier buckets.
+    pub fn push_filter(&self, query_builder: &mut QueryBuilder<'_, Postgres>, column: &str) {
+        if let Some(after) = self.after {
+            query_builder.push(format!(" AND {}>=", column));
+            query_builder.push_bind(after.time);
+        }
+    }
+
+    /// Closes the subquery opened by [`Page::begin`]. One extra row is fetched
+    /// to tell whether there is a next page.
+    pub fn finish(&self, query_builder: &mut QueryBuilder<'_, Postgres>) {
+        query_builder.push(") page");
+        if let Some(after) = self.after {
+            query_builder.push(" WHERE (time_interval, exchange_id) > (");
+            query_builder.push_bind(after.time);
+            query_builder.push(", ");
+            query_builder.push_bind(after.exchange_id);
+            query_builder.push(")");
+        }
+        query_builder.push(" ORDER BY time_interval ASC, exchange_id ASC LIMIT ");
+        query_builder.push_bind((self.limit + 1) as i64);
+    }
+
+    /// Drops the look-ahead row, returning the page and the cursor for the next one.
+    pub fn split(&self, mut rows: Vec<PgRow>) -> (Vec<PgRow>, Option<String>) {
+        if rows.len() <= self.limit {
+            return (rows, None);
+        }
+        rows.truncate(self.limit);
+        let next_cursor = rows.last().map(|row| {
+            Cursor {
+                time: row.get::<DateTime<Utc>, _>("time_interval"),
+                exchange_id: row.get::<i32, _>("exchange_id"),
+            }
+            .encode()
+        });
+        (rows, next_cursor)
+    }
+}
+
+pub fn with_next_cursor(mut response: Response, next_cursor: Option<String>) -> Response {
+    if let Some(value) = next_cursor.and_then(|cursor| HeaderValue::from_str(&cursor).ok()) {
+        response.headers_mut().insert(NEXT_CURSOR_HEADER, value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_round_trip() {
+        let cursor = Cursor {
+            time: DateTime::from_timestamp(1730764800, 0).unwrap(),
+            exchange_id: 3,
+        };
+        assert_eq!(Cursor::decode(&cursor.encode()).unwrap(), cursor);
+    }
+
+    #[test]
+    fn malformed_cursors_are_rejected() {
+        for value in [
+            "",
+            "not base64!",
+            &URL_SAFE_NO_PAD.encode("v2:1:1"),
+            &URL_SAFE_NO_PAD.encode("v1:1"),
+        ] {
+            assert!(
+                Cursor::decode(value).is_err(),
+                "{} should be rejected",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn page_limits() {
+        assert_eq!(Page::from_request(None, None).unwrap(), None);
+        assert_eq!(
+            Page::from_request(None, Some(50)).unwrap().unwrap().limit,
+            50
+        );
+        assert!(Page::from_request(None, Some(0)).is_err());
+        assert!(Page::from_request(None, Some(MAX_PAGE_LIMIT + 1)).is_err());
+    }
+}
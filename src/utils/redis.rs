@@ -7,6 +7,7 @@ pub trait RedisClientBuilder: Sized {
     fn build_from_config(redis_url: &str) -> Result<Self, RedisError>;
 }
 
+#[allow(dead_code)]
 pub trait RedisClientExt: RedisClientBuilder {
     fn ping(&self) -> impl std::future::Future<Output = Result<Option<String>, RedisError>>;
     fn set(
@@ -26,7 +27,7 @@ pub trait RedisClientExt: RedisClientBuilder {
 
 impl RedisClientBuilder for RedisClient {
     fn build_from_config(redis_url: &str) -> Result<Self, RedisError> {
-        Ok(redis::Client::open(redis_url)?)
+        redis::Client::open(redis_url)
     }
 }
 
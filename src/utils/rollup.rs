@@ -0,0 +1,430 @@
+use crate::utils::time_window::{Bucket, TimeWindow};
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{PgPool, Postgres, QueryBuilder, Row};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+use tracing::error;
+
+/// Rollup resolutions, coarsest first.
+const RESOLUTIONS: [Bucket; 3] = [Bucket::OneDay, Bucket::OneHour, Bucket::FiveMinutes];
+/// Buckets materialized per transaction while catching up on history.
+const CHUNK_BUCKETS: i32 = 2016;
+/// Chunks per table and tick; the rest of a backfill waits for the next tick.
+const MAX_CHUNKS_PER_REFRESH: usize = 50;
+/// How far before the previous refresh late rows are looked for again.
+/// `ingested_at` is the start of the inserting transaction, so a row can
+/// commit after a refresh that already moved past its `ingested_at`.
+const INGEST_OVERLAP_MINUTES: i64 = 10;
+
+/// Raw table a rollup is built from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RollupSource {
+    Volume,
+    Balance,
+}
+
+impl RollupSource {
+    const ALL: [RollupSource; 2] = [RollupSource::Volume, RollupSource::Balance];
+
+    pub fn raw_table(self) -> &'static str {
+        match self {
+            RollupSource::Volume => "volume_data",
+            RollupSource::Balance => "balance_data",
+        }
+    }
+
+    fn table(self, resolution: Bucket) -> String {
+        format!("{}_{}", self.raw_table(), resolution.as_str())
+    }
+
+    /// Partial-aggregate columns of a rollup row.
+    fn columns(self) -> &'static [&'static str] {
+        match self {
+            RollupSource::Volume => &[
+                "total_volume",
+                "price_sum",
+                "price_count",
+                "day_volume_max",
+                "day_volume_sum",
+                "day_volume_count",
+            ],
+            RollupSource::Balance => &[
+                "first_wallet",
+                "first_transfer",
+                "first_at",
+                "last_wallet",
+                "last_transfer",
+                "last_at",
+                "wallet_sum",
+                "transfer_sum",
+                "sample_count",
+                "wallet_min",
+                "wallet_max",
+                "transfer_min",
+                "transfer_max",
+            ],
+        }
+    }
+
+    /// Aggregates raw rows `r` into the [`columns`](Self::columns) of one rollup row.
+    fn aggregates(self) -> &'static str {
+        match self {
+            RollupSource::Volume => {
+                "SUM(r.total_volume), SUM(r.price), COUNT(*), MAX(r.day_total_volume), \
+                 COALESCE(SUM(r.day_total_volume), 0), COUNT(r.day_total_volume)"
+            }
+            RollupSource::Balance => {
+                "(array_agg(r.wallet_balance ORDER BY r.timestamp ASC))[1], \
+                 (array_agg(r.transfer_balance ORDER BY r.timestamp ASC))[1], MIN(r.timestamp), \
+                 (array_agg(r.wallet_balance ORDER BY r.timestamp DESC))[1], \
+                 (array_agg(r.transfer_balance ORDER BY r.timestamp DESC))[1], MAX(r.timestamp), \
+                 SUM(r.wallet_balance), SUM(r.transfer_balance), COUNT(*), \
+                 MIN(r.wallet_balance), MAX(r.wallet_balance), \
+                 MIN(r.transfer_balance), MAX(r.transfer_balance)"
+            }
+        }
+    }
+
+    /// Raw rows shaped like rollup rows of a single sample.
+    fn raw_columns(self) -> &'static str {
+        match self {
+            RollupSource::Volume => {
+                "exchange_id, token_symbol, timestamp AS ts, total_volume, price AS price_sum, \
+                 1::BIGINT AS price_count, day_total_volume AS day_volume_max, \
+                 COALESCE(day_total_volume, 0) AS day_volume_sum, \
+                 (day_total_volume IS NOT NULL)::INT::BIGINT AS day_volume_count"
+            }
+            RollupSource::Balance => {
+                "exchange_id, token_symbol, timestamp AS ts, \
+                 wallet_balance AS first_wallet, transfer_balance AS first_transfer, timestamp AS first_at, \
+                 wallet_balance AS last_wallet, transfer_balance AS last_transfer, timestamp AS last_at, \
+                 wallet_balance AS wallet_sum, transfer_balance AS transfer_sum, 1::BIGINT AS sample_count, \
+                 wallet_balance AS wallet_min, wallet_balance AS wallet_max, \
+                 transfer_balance AS transfer_min, transfer_balance AS transfer_max"
+            }
+        }
+    }
+}
+
+fn floor_to(time: DateTime<Utc>, step: Duration) -> DateTime<Utc> {
+    let secs = time.timestamp();
+    DateTime::from_timestamp(secs - secs.rem_euclid(step.num_seconds()), 0).unwrap_or(time)
+}
+
+fn ceil_to(time: DateTime<Utc>, step: Duration) -> DateTime<Utc> {
+    let floor = floor_to(time, step);
+    if floor == time {
+        floor
+    } else {
+        floor + step
+    }
+}
+
+/// Whether buckets of `bucket` are unions of whole `resolution` buckets.
+fn covers(resolution: Bucket, bucket: Bucket) -> bool {
+    bucket >= resolution
+        && (bucket == Bucket::OneWeek
+            || bucket.duration().num_seconds() % resolution.duration().num_seconds() == 0)
+}
+
+/// Part of a window served from a rollup table: whole `resolution` buckets in
+/// `[from, to)`. Raw rows cover whatever the window holds outside of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RollupPlan {
+    pub resolution: Bucket,
+    pub from: Option<DateTime<Utc>>,
+    pub to: DateTime<Utc>,
+}
+
+/// Picks the coarsest rollup whose buckets tile `window.bucket` and that
+/// covers some of the window below its high-water mark.
+fn plan(
+    window: &TimeWindow,
+    marks: impl Fn(Bucket) -> Option<DateTime<Utc>>,
+) -> Option<RollupPlan> {
+    RESOLUTIONS
+        .into_iter()
+        .filter(|resolution| covers(*resolution, window.bucket))
+        .find_map(|resolution| {
+            let step = resolution.duration();
+            let mark = marks(resolution)?;
+            let from = window.from.map(|from| ceil_to(from, step));
+            let to = window.to.map_or(mark, |to| floor_to(to, step).min(mark));
+            from.is_none_or(|from| from < to).then_some(RollupPlan {
+                resolution,
+                from,
+                to,
+            })
+        })
+}
+
+/// High-water marks of the rollup tables, refreshed in the background.
+#[derive(Debug, Clone, Default)]
+pub struct Rollups {
+    marks: Arc<RwLock<HashMap<String, DateTime<Utc>>>>,
+}
+
+impl Rollups {
+    /// Keeps the rollup tables current every `interval`. When disabled no
+    /// marks are ever loaded and every query reads the raw tables.
+    pub fn start(db: PgPool, enabled: bool, interval: std::time::Duration) -> Self {
+        let rollups = Rollups::default();
+        if !enabled {
+            return rollups;
+        }
+        let refreshed = rollups.clone();
+        tokio::spawn(async move {
+            loop {
+                for source in RollupSource::ALL {
+                    for resolution in RESOLUTIONS {
+                        for _ in 0..MAX_CHUNKS_PER_REFRESH {
+                            match refresh(&db, source, resolution, Utc::now()).await {
+                                Ok(true) => break,
+                                Ok(false) => continue,
+                                Err(e) => {
+                                    error!(
+                                        "💥 Error to refresh {}: {}",
+                                        source.table(resolution),
+                                        e
+                                    );
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                }
+                match load_marks(&db).await {
+                    Ok(marks) => {
+                        if let Ok(mut current) = refreshed.marks.write() {
+                            *current = marks;
+                        }
+                    }
+                    Err(e) => error!("💥 Error to load the rollup marks: {}", e),
+                }
+                tokio::time::sleep(interval).await;
+            }
+        });
+        rollups
+    }
+
+    fn plan(&self, source: RollupSource, window: &TimeWindow) -> Option<RollupPlan> {
+        let marks = self.marks.read().ok()?;
+        plan(window, |resolution| {
+            marks.get(&source.table(resolution)).copied()
+        })
+    }
+
+    /// Pushes a `source` subquery with one row per raw row or rollup bucket,
+    /// using the rollup column names and `ts` for the time. `filters` pushes
+    /// the `WHERE` clause given the name of the time column; time bounds other
+    /// than the window itself must fall on starts of the requested buckets.
+    pub fn push_source(
+        &self,
+        query_builder: &mut QueryBuilder<'static, Postgres>,
+        source: RollupSource,
+        window: &TimeWindow,
+        filters: impl Fn(&mut QueryBuilder<'static, Postgres>, &str),
+    ) {
+        query_builder.push("(SELECT ");
+        query_builder.push(source.raw_columns());
+        query_builder.push(" FROM ");
+        query_builder.push(source.raw_table());
+        filters(query_builder, "timestamp");
+        if let Some(plan) = self.plan(source, window) {
+            query_builder.push(" AND NOT (timestamp<");
+            query_builder.push_bind(plan.to);
+            if let Some(from) = plan.from {
+                query_builder.push(" AND timestamp>=");
+                query_builder.push_bind(from);
+            }
+            query_builder
+                .push(") UNION ALL SELECT exchange_id, token_symbol, bucket_start AS ts, ");
+            query_builder.push(source.columns().join(", "));
+            query_builder.push(" FROM ");
+            query_builder.push(source.table(plan.resolution));
+            filters(query_builder, "bucket_start");
+            query_builder.push(" AND bucket_start<");
+            query_builder.push_bind(plan.to);
+            if let Some(from) = plan.from {
+                query_builder.push(" AND bucket_start>=");
+                query_builder.push_bind(from);
+            }
+        }
+        query_builder.push(") source");
+    }
+}
+
+async fn load_marks(db: &PgPool) -> Result<HashMap<String, DateTime<Utc>>, sqlx::Error> {
+    let rows = sqlx::query("SELECT table_name, high_water_mark FROM rollup_state")
+        .fetch_all(db)
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| {
+            (
+                row.get::<String, _>("table_name"),
+                row.get::<DateTime<Utc>, _>("high_water_mark"),
+            )
+        })
+        .collect())
+}
+
+/// Advances one rollup table by at most [`CHUNK_BUCKETS`] buckets towards
+/// `now`, and recomputes older buckets that raw rows were ingested into since
+/// shortly before the last refresh. Returns whether the table has caught up.
+async fn refresh(
+    db: &PgPool,
+    source: RollupSource,
+    resolution: Bucket,
+    now: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    let raw_table = source.raw_table();
+    let table = source.table(resolution);
+    let step = resolution.duration();
+    let mut tx = db.begin().await?;
+    // Another server is refreshing this table.
+    let locked: bool = sqlx::query_scalar("SELECT pg_try_advisory_xact_lock(hashtext($1))")
+        .bind(&table)
+        .fetch_one(&mut *tx)
+        .await?;
+    if !locked {
+        return Ok(true);
+    }
+    // Compared with `ingested_at`, so it has to come from the database clock.
+    let ingested_now: DateTime<Utc> = sqlx::query_scalar("SELECT NOW()")
+        .fetch_one(&mut *tx)
+        .await?;
+    let state: Option<(DateTime<Utc>, DateTime<Utc>)> = sqlx::query_as(
+        "SELECT ingested_through, high_water_mark FROM rollup_state WHERE table_name = $1",
+    )
+    .bind(&table)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let (ingested_through, mark) = match state {
+        Some(state) => state,
+        None => {
+            let first: Option<DateTime<Utc>> =
+                sqlx::query_scalar(&format!("SELECT MIN(timestamp) FROM {}", raw_table))
+                    .fetch_one(&mut *tx)
+                    .await?;
+            match first {
+                Some(first) => (ingested_now, floor_to(first, step)),
+                None => return Ok(true),
+            }
+        }
+    };
+    let target = floor_to(now, step);
+    let next = target.min(mark + step * CHUNK_BUCKETS).max(mark);
+
+    let columns = source.columns();
+    let updates = columns
+        .iter()
+        .map(|column| format!("{} = EXCLUDED.{}", column, column))
+        .collect::<Vec<_>>()
+        .join(", ");
+    sqlx::query(&format!(
+        "WITH dirty AS ( \
+             SELECT DISTINCT exchange_id, token_symbol, {bucket} AS bucket_start FROM {raw_table} \
+             WHERE (timestamp >= $2 AND timestamp < $3) OR (ingested_at > $1 AND timestamp < $2) \
+         ) \
+         INSERT INTO {table} (exchange_id, token_symbol, bucket_start, {columns}) \
+         SELECT d.exchange_id, d.token_symbol, d.bucket_start, {aggregates} \
+         FROM dirty d JOIN {raw_table} r ON r.exchange_id = d.exchange_id AND r.token_symbol = d.token_symbol \
+             AND r.timestamp >= d.bucket_start AND r.timestamp < d.bucket_start + $4 \
+         GROUP BY d.exchange_id, d.token_symbol, d.bucket_start \
+         ON CONFLICT (token_symbol, exchange_id, bucket_start) DO UPDATE SET {updates}",
+        bucket = resolution.sql_expr("timestamp"),
+        columns = columns.join(", "),
+        aggregates = source.aggregates(),
+    ))
+    .bind(ingested_through - Duration::minutes(INGEST_OVERLAP_MINUTES))
+    .bind(mark)
+    .bind(next)
+    .bind(step)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(
+        "INSERT INTO rollup_state (table_name, ingested_through, high_water_mark) VALUES ($1, $2, $3) \
+         ON CONFLICT (table_name) DO UPDATE SET ingested_through = EXCLUDED.ingested_through, \
+         high_water_mark = EXCLUDED.high_water_mark, refreshed_at = NOW()",
+    )
+    .bind(&table)
+    .bind(ingested_now)
+    .bind(next)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(next == target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn window(from: Option<&str>, to: Option<&str>, bucket: Bucket) -> TimeWindow {
+        TimeWindow {
+            from: from.map(at),
+            to: to.map(at),
+            bucket,
+        }
+    }
+
+    #[test]
+    fn picks_the_coarsest_rollup_that_tiles_the_bucket() {
+        let mark = at("2024-12-10T12:00:00Z");
+        let marks = |_| Some(mark);
+        let from = Some("2024-12-01T00:00:00Z");
+        let resolution = |bucket| plan(&window(from, None, bucket), marks).map(|p| p.resolution);
+        assert_eq!(resolution(Bucket::OneMinute), None);
+        assert_eq!(resolution(Bucket::FiveMinutes), Some(Bucket::FiveMinutes));
+        assert_eq!(
+            resolution(Bucket::FifteenMinutes),
+            Some(Bucket::FiveMinutes)
+        );
+        assert_eq!(resolution(Bucket::FourHours), Some(Bucket::OneHour));
+        assert_eq!(resolution(Bucket::OneDay), Some(Bucket::OneDay));
+        assert_eq!(resolution(Bucket::OneWeek), Some(Bucket::OneDay));
+    }
+
+    #[test]
+    fn aligns_the_rollup_range_inside_the_window_and_below_the_mark() {
+        let marks = |_| Some(at("2024-12-10T12:00:00Z"));
+        let window = window(
+            Some("2024-12-01T00:20:00Z"),
+            Some("2024-12-11T00:00:00Z"),
+            Bucket::OneHour,
+        );
+        assert_eq!(
+            plan(&window, marks),
+            Some(RollupPlan {
+                resolution: Bucket::OneHour,
+                from: Some(at("2024-12-01T01:00:00Z")),
+                to: at("2024-12-10T12:00:00Z"),
+            })
+        );
+    }
+
+    #[test]
+    fn falls_back_to_finer_rollups_or_raw_rows() {
+        let marks = |resolution| match resolution {
+            Bucket::OneDay => Some(at("2024-12-10T00:00:00Z")),
+            Bucket::OneHour => Some(at("2024-12-10T12:00:00Z")),
+            _ => None,
+        };
+        let today = window(Some("2024-12-10T03:00:00Z"), None, Bucket::OneDay);
+        assert_eq!(
+            plan(&today, marks).map(|p| p.resolution),
+            Some(Bucket::OneHour)
+        );
+        let recent = window(Some("2024-12-10T12:30:00Z"), None, Bucket::OneDay);
+        assert_eq!(plan(&recent, marks), None);
+    }
+}
@@ -43,6 +43,8 @@ pub struct UUIDKey {
     pub uuid: String,
 }
 
+// The variant name is part of the stored key format (see `Display`), so keep it as-is.
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq, Clone)]
 pub enum SessionKey {
     Email(EmailKey),
@@ -72,7 +74,7 @@ where
     let value =
         serde_json::to_string(value).map_err(|e| format!("serde to_string error: {}", e))?;
     client
-        .set(&key.to_string(), &value, K::EXPIRE_TIME)
+        .set(&key.to_string(), &value, key.expire())
         .await
         .map_err(|e| format!("Redis client set error: {}", e))?;
     Ok(())
@@ -82,13 +84,13 @@ pub async fn get<K>(client: &RedisClient, key: &K) -> Result<Option<K::Value>, S
 where
     K: RedisKey,
 {
-    Ok(client
+    client
         .get(&key.to_string())
         .await
         .map_err(|e| format!("Redis client get error: {}", e))?
         .map(|v| serde_json::from_str::<K::Value>(&v))
         .transpose()
-        .map_err(|e| format!("Redis transpose error: {}", e))?)
+        .map_err(|e| format!("Redis transpose error: {}", e))
 }
 pub async fn del(client: &RedisClient, key: &impl RedisKey) -> Result<bool, String> {
     client
@@ -97,9 +99,10 @@ pub async fn del(client: &RedisClient, key: &impl RedisKey) -> Result<bool, Stri
         .map_err(|e| format!("Redis client del error: {}", e))
 }
 
+#[allow(dead_code)]
 pub async fn check_exist_key(redis: &RedisClient, key: &impl RedisKey) -> Result<bool, String> {
-    Ok(redis
+    redis
         .exist(&key.to_string())
         .await
-        .map_err(|e| format!("Redis client check existing error: {}", e))?)
+        .map_err(|e| format!("Redis client check existing error: {}", e))
 }
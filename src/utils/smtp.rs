@@ -99,6 +99,50 @@ pub fn send_confirmation_code(
     </html>  
     "#.replace("{{CONFIRMATION_CODE}}", &confirmation_code);
 
+    send_email(
+        &destination,
+        "Confirmation Code to Sign Up",
+        String::from("Hello from Lettre! A mailer library for Rust"),
+        html_content,
+        &state,
+    )
+}
+
+/// Notifies a user that one of their alert rules fired.
+pub fn send_alert_email(
+    destination: String,
+    message: String,
+    state: Arc<AppState>,
+) -> Result<(), ApiError> {
+    let html_content = format!(
+        r#"<!DOCTYPE html>
+    <html lang="en">
+    <body style="font-family: 'Segoe UI', Tahoma, Geneva, Verdana, sans-serif;">
+        <h2 style="color: #3d8bbe;">Alert triggered</h2>
+        <p>{}</p>
+    </body>
+    </html>"#,
+        message
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    );
+    send_email(
+        &destination,
+        "InMacro alert triggered",
+        message,
+        html_content,
+        &state,
+    )
+}
+
+fn send_email(
+    destination: &str,
+    subject: &str,
+    text_content: String,
+    html_content: String,
+    state: &AppState,
+) -> Result<(), ApiError> {
     let email = Message::builder()
         .from(
             format!("InMacro <{}>", state.env.smtp_sender_email)
@@ -106,18 +150,18 @@ pub fn send_confirmation_code(
                 .unwrap(),
         )
         .to(format!("Receiver <{destination}>").parse().unwrap())
-        .subject("Confirmation Code to Sign Up")
+        .subject(subject)
         .multipart(
             MultiPart::alternative() // This is composed of two parts.
                 .singlepart(
                     SinglePart::builder()
                         .header(header::ContentType::TEXT_PLAIN)
-                        .body(String::from("Hello from Lettre! A mailer library for Rust")), // Every message should have a plain text fallback.
+                        .body(text_content), // Every message should have a plain text fallback.
                 )
                 .singlepart(
                     SinglePart::builder()
                         .header(header::ContentType::TEXT_HTML)
-                        .body(String::from(html_content)),
+                        .body(html_content),
                 ),
         )?;
     let creds = Credentials::new(
@@ -131,7 +175,7 @@ pub fn send_confirmation_code(
     match mailer.send(&email) {
         Err(e) => {
             info!("{:?}", e);
-            return Err(ApiError::EmailSendError(e));
+            Err(ApiError::EmailSendError(e))
         }
         Ok(_) => Ok(()),
     }
@@ -0,0 +1,198 @@
+use crate::{
+    dto::response::{ExchangeCoverage, SymbolCoverage, SymbolInfo, SymbolListResponse},
+    utils::{errors::ApiError, exchanges::ExchangeRegistry},
+};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tracing::error;
+
+/// Splits a `BASE-QUOTE` token symbol such as `BTC-USDT` into its two halves.
+pub fn split_symbol(symbol: &str) -> Option<(&str, &str)> {
+    symbol.split_once('-')
+}
+
+/// Parses a comma-separated `symbols` parameter, dropping blanks and duplicates.
+pub fn parse_symbols(symbols: &str, max: usize) -> Result<Vec<String>, ApiError> {
+    let mut parsed: Vec<String> = vec![];
+    for symbol in symbols.split(',').map(str::trim) {
+        if !symbol.is_empty() && !parsed.iter().any(|s| s == symbol) {
+            parsed.push(symbol.to_string());
+        }
+    }
+    if parsed.is_empty() || parsed.len() > max {
+        return Err(ApiError::InvalidRequest(format!(
+            "Between 1 and {} symbols are required",
+            max
+        )));
+    }
+    Ok(parsed)
+}
+
+/// `(token_symbol, exchange_id, first timestamp, last timestamp, rows)` of one table.
+type CoverageRow = (String, i32, DateTime<Utc>, DateTime<Utc>, i64);
+
+fn coverage_query(table: &str) -> String {
+    format!(
+        "SELECT token_symbol, exchange_id, MIN(timestamp), MAX(timestamp), COUNT(*) FROM {} GROUP BY token_symbol, exchange_id ORDER BY token_symbol, exchange_id",
+        table
+    )
+}
+
+fn coverage(rows: &[&CoverageRow], exchanges: &ExchangeRegistry) -> Option<SymbolCoverage> {
+    let exchanges: Vec<ExchangeCoverage> = rows
+        .iter()
+        .map(|(_, exchange_id, first, last, count)| ExchangeCoverage {
+            exchange_id: *exchange_id,
+            exchange: exchanges.name(*exchange_id),
+            first_timestamp: first.timestamp(),
+            last_timestamp: last.timestamp(),
+            rows: *count,
+        })
+        .collect();
+    Some(SymbolCoverage {
+        first_timestamp: exchanges.iter().map(|e| e.first_timestamp).min()?,
+        last_timestamp: exchanges.iter().map(|e| e.last_timestamp).max()?,
+        rows: exchanges.iter().map(|e| e.rows).sum(),
+        exchanges,
+    })
+}
+
+/// Merges the per-exchange coverage of both tables into one entry per symbol,
+/// sorted by symbol.
+fn build_symbols(
+    volume: &[CoverageRow],
+    balance: &[CoverageRow],
+    exchanges: &ExchangeRegistry,
+) -> Vec<SymbolInfo> {
+    let mut by_symbol: BTreeMap<&str, (Vec<&CoverageRow>, Vec<&CoverageRow>)> = BTreeMap::new();
+    for row in volume {
+        by_symbol.entry(&row.0).or_default().0.push(row);
+    }
+    for row in balance {
+        by_symbol.entry(&row.0).or_default().1.push(row);
+    }
+    by_symbol
+        .into_iter()
+        .map(|(symbol, (volume, balance))| {
+            let (base, quote) = match split_symbol(symbol) {
+                Some((base, quote)) => (Some(base.to_string()), Some(quote.to_string())),
+                None => (None, None),
+            };
+            SymbolInfo {
+                symbol: symbol.to_string(),
+                base,
+                quote,
+                volume: coverage(&volume, exchanges),
+                balance: coverage(&balance, exchanges),
+            }
+        })
+        .collect()
+}
+
+pub async fn query_symbols(
+    db: &PgPool,
+    exchanges: &ExchangeRegistry,
+) -> Result<SymbolListResponse, sqlx::Error> {
+    let volume = sqlx::query_as::<_, CoverageRow>(&coverage_query("volume_data"))
+        .fetch_all(db)
+        .await?;
+    let balance = sqlx::query_as::<_, CoverageRow>(&coverage_query("balance_data"))
+        .fetch_all(db)
+        .await?;
+    Ok(SymbolListResponse {
+        refreshed_at: Some(Utc::now().timestamp()),
+        data: build_symbols(&volume, &balance, exchanges),
+    })
+}
+
+/// `/api/v1/symbols` scans both tables, so the list is kept in memory and
+/// rebuilt in the background every `interval`.
+#[derive(Clone, Default)]
+pub struct SymbolCatalog {
+    current: Arc<RwLock<Arc<SymbolListResponse>>>,
+}
+
+impl SymbolCatalog {
+    pub fn start(db: PgPool, exchanges: ExchangeRegistry, interval: Duration) -> Self {
+        let catalog = SymbolCatalog::default();
+        let refreshed = catalog.clone();
+        tokio::spawn(async move {
+            loop {
+                match query_symbols(&db, &exchanges).await {
+                    Ok(symbols) => refreshed.replace(symbols),
+                    Err(e) => error!("💥 Error to refresh the symbol list: {}", e),
+                }
+                tokio::time::sleep(interval).await;
+            }
+        });
+        catalog
+    }
+
+    /// `None` until the first refresh has finished.
+    pub fn get(&self) -> Option<Arc<SymbolListResponse>> {
+        let current = self.current.read().ok()?.clone();
+        current.refreshed_at.is_some().then_some(current)
+    }
+
+    pub fn replace(&self, symbols: SymbolListResponse) {
+        if let Ok(mut current) = self.current.write() {
+            *current = Arc::new(symbols);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(symbol: &str, exchange_id: i32, first: i64, last: i64, rows: i64) -> CoverageRow {
+        (
+            symbol.to_string(),
+            exchange_id,
+            DateTime::from_timestamp(first, 0).unwrap(),
+            DateTime::from_timestamp(last, 0).unwrap(),
+            rows,
+        )
+    }
+
+    #[test]
+    fn parses_symbol_lists() {
+        assert_eq!(
+            parse_symbols("BTC-USDT, ETH-USDT,,BTC-USDT", 10).unwrap(),
+            vec!["BTC-USDT", "ETH-USDT"]
+        );
+        assert!(parse_symbols(" , ", 10).is_err());
+        assert!(parse_symbols("A,B,C", 2).is_err());
+    }
+
+    #[test]
+    fn symbols_merge_both_tables() {
+        let volume = vec![
+            row("BTC-USDT", 0, 100, 500, 10),
+            row("BTC-USDT", 2, 50, 400, 5),
+        ];
+        let balance = vec![row("BTC", 0, 10, 20, 2)];
+        let symbols = build_symbols(&volume, &balance, &ExchangeRegistry::default());
+        assert_eq!(symbols.len(), 2);
+        assert_eq!(symbols[0].symbol, "BTC");
+        assert_eq!(symbols[0].base, None);
+        assert!(symbols[0].volume.is_none());
+        assert_eq!(symbols[0].balance.as_ref().unwrap().rows, 2);
+
+        let btc_usdt = &symbols[1];
+        assert_eq!(btc_usdt.base.as_deref(), Some("BTC"));
+        assert_eq!(btc_usdt.quote.as_deref(), Some("USDT"));
+        let volume = btc_usdt.volume.as_ref().unwrap();
+        assert_eq!(
+            (volume.first_timestamp, volume.last_timestamp, volume.rows),
+            (50, 500, 15)
+        );
+        assert_eq!(volume.exchanges.len(), 2);
+        assert!(btc_usdt.balance.is_none());
+    }
+}
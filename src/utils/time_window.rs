@@ -0,0 +1,623 @@
+use crate::utils::errors::ApiError;
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, NaiveTime, Utc, Weekday};
+use sqlx::{Postgres, QueryBuilder};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Bucket {
+    OneMinute,
+    FiveMinutes,
+    FifteenMinutes,
+    OneHour,
+    FourHours,
+    OneDay,
+    OneWeek,
+}
+
+impl Bucket {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Bucket::OneMinute => "1m",
+            Bucket::FiveMinutes => "5m",
+            Bucket::FifteenMinutes => "15m",
+            Bucket::OneHour => "1h",
+            Bucket::FourHours => "4h",
+            Bucket::OneDay => "1d",
+            Bucket::OneWeek => "1w",
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        match self {
+            Bucket::OneMinute => Duration::minutes(1),
+            Bucket::FiveMinutes => Duration::minutes(5),
+            Bucket::FifteenMinutes => Duration::minutes(15),
+            Bucket::OneHour => Duration::hours(1),
+            Bucket::FourHours => Duration::hours(4),
+            Bucket::OneDay => Duration::days(1),
+            Bucket::OneWeek => Duration::weeks(1),
+        }
+    }
+
+    /// Start of the bucket `time` falls in, matching [`sql_expr`](Self::sql_expr).
+    pub fn floor(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            Bucket::OneWeek => {
+                let monday = time.date_naive()
+                    - Duration::days(time.weekday().num_days_from_monday() as i64);
+                monday.and_time(NaiveTime::MIN).and_utc()
+            }
+            _ => {
+                let secs = self.duration().num_seconds();
+                DateTime::from_timestamp(time.timestamp().div_euclid(secs) * secs, 0)
+                    .unwrap_or(time)
+            }
+        }
+    }
+
+    /// SQL expression that truncates `column` to the start of its bucket.
+    /// Weeks start on Monday; everything else is aligned to the Unix epoch.
+    pub fn sql_expr(&self, column: &str) -> String {
+        match self {
+            Bucket::OneWeek => format!("date_trunc('week', {})", column),
+            _ => {
+                let secs = self.duration().num_seconds();
+                format!(
+                    "to_timestamp(floor(EXTRACT(EPOCH FROM {}) / {}) * {})",
+                    column, secs, secs
+                )
+            }
+        }
+    }
+
+    /// Pushes `WITH RECURSIVE ... starts AS (...) ` for the caller's query to
+    /// follow. `starts` has one `(exchange_id, token_symbol, warm_from)` row
+    /// per series, where `warm_from` is the start of the `count`-th latest
+    /// non-empty bucket of `table` before `before`, however far back it is, or
+    /// `NULL` without earlier rows. Each step back is an index lookup of the
+    /// latest earlier row, so gaps in the data cost nothing.
+    pub fn push_warm_up_starts(
+        &self,
+        query_builder: &mut QueryBuilder<'static, Postgres>,
+        table: &str,
+        series: &[(i32, String)],
+        before: DateTime<Utc>,
+        count: usize,
+    ) {
+        let latest_bucket = |before: &str| {
+            format!(
+                "(SELECT {} FROM {} r WHERE r.token_symbol=s.token_symbol AND r.exchange_id=s.exchange_id \
+                 AND r.timestamp < {} ORDER BY r.timestamp DESC LIMIT 1)",
+                self.sql_expr("r.timestamp"),
+                table,
+                before
+            )
+        };
+        let (exchange_ids, symbols): (Vec<i32>, Vec<String>) = series.iter().cloned().unzip();
+        query_builder.push("WITH RECURSIVE seed AS (SELECT exchange_id, token_symbol, ");
+        query_builder.push_bind(before);
+        query_builder.push("::TIMESTAMPTZ AS before FROM unnest(");
+        query_builder.push_bind(exchange_ids);
+        query_builder.push("::INT[], ");
+        query_builder.push_bind(symbols);
+        query_builder.push(format!(
+            "::VARCHAR[]) AS series(exchange_id, token_symbol)), steps AS ( \
+             SELECT s.exchange_id, s.token_symbol, 1 AS n, {first} AS bucket_start FROM seed s \
+             UNION ALL \
+             SELECT s.exchange_id, s.token_symbol, s.n + 1, {next} FROM steps s \
+             WHERE s.bucket_start IS NOT NULL AND s.n < ",
+            first = latest_bucket("s.before"),
+            next = latest_bucket("s.bucket_start"),
+        ));
+        query_builder.push_bind(count as i32);
+        query_builder.push(
+            "), starts AS (SELECT exchange_id, token_symbol, MIN(bucket_start) AS warm_from \
+             FROM steps GROUP BY exchange_id, token_symbol) ",
+        );
+    }
+}
+
+impl FromStr for Bucket {
+    type Err = ApiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1m" => Ok(Bucket::OneMinute),
+            "5m" => Ok(Bucket::FiveMinutes),
+            "15m" => Ok(Bucket::FifteenMinutes),
+            "1h" => Ok(Bucket::OneHour),
+            "4h" => Ok(Bucket::FourHours),
+            "1d" => Ok(Bucket::OneDay),
+            "1w" => Ok(Bucket::OneWeek),
+            _ => Err(ApiError::InvalidRequest(format!(
+                "Unknown bucket '{}', expected one of 1m, 5m, 15m, 1h, 4h, 1d, 1w",
+                s
+            ))),
+        }
+    }
+}
+
+/// Accepts either Unix seconds or an RFC 3339 timestamp.
+pub fn parse_timestamp(value: &str) -> Result<DateTime<Utc>, ApiError> {
+    if let Ok(secs) = value.parse::<i64>() {
+        return DateTime::from_timestamp(secs, 0)
+            .ok_or_else(|| ApiError::InvalidRequest(format!("Timestamp out of range: {}", value)));
+    }
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|_| ApiError::InvalidRequest(format!("Invalid timestamp: {}", value)))
+}
+
+/// Parses a positive duration such as `30m`, `24h`, `7d` or `2w`.
+pub fn parse_duration(value: &str) -> Result<Duration, ApiError> {
+    let invalid = || {
+        ApiError::InvalidRequest(format!(
+            "Invalid duration '{}', expected a number followed by m, h, d or w",
+            value
+        ))
+    };
+    let split = value.char_indices().last().map_or(0, |(i, _)| i);
+    let (amount, unit) = value.split_at(split);
+    let amount = amount.parse::<i64>().map_err(|_| invalid())?;
+    if amount <= 0 {
+        return Err(invalid());
+    }
+    match unit {
+        "m" => Duration::try_minutes(amount),
+        "h" => Duration::try_hours(amount),
+        "d" => Duration::try_days(amount),
+        "w" => Duration::try_weeks(amount),
+        _ => None,
+    }
+    .ok_or_else(invalid)
+}
+
+/// Resolved `[from, to)` range and bucket size for a series query.
+/// An open `from` means "since the beginning", an open `to` means "until now".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeWindow {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub bucket: Bucket,
+}
+
+impl TimeWindow {
+    /// Builds a window from the request parameters. Explicit `from`/`to`/`bucket`
+    /// override whatever the `interval` preset would have picked.
+    pub fn resolve(
+        interval: Option<&str>,
+        from: Option<&str>,
+        to: Option<&str>,
+        bucket: Option<&str>,
+    ) -> Result<Self, ApiError> {
+        Self::resolve_at(Utc::now(), interval, from, to, bucket)
+    }
+
+    fn resolve_at(
+        now: DateTime<Utc>,
+        interval: Option<&str>,
+        from: Option<&str>,
+        to: Option<&str>,
+        bucket: Option<&str>,
+    ) -> Result<Self, ApiError> {
+        let (mut window, has_preset) = match interval {
+            Some(interval) => (preset(interval, now)?, true),
+            None => (
+                TimeWindow {
+                    from: None,
+                    to: None,
+                    bucket: Bucket::OneHour,
+                },
+                false,
+            ),
+        };
+        if let Some(from) = from {
+            window.from = Some(parse_timestamp(from)?);
+        } else if !has_preset {
+            return Err(ApiError::InvalidRequest(
+                "Either interval or from is required".to_string(),
+            ));
+        }
+        if let Some(to) = to {
+            window.to = Some(parse_timestamp(to)?);
+        }
+        if let Some(bucket) = bucket {
+            window.bucket = bucket.parse()?;
+        }
+        if let (Some(from), Some(to)) = (window.from, window.to) {
+            if from >= to {
+                return Err(ApiError::InvalidRequest(
+                    "from must be earlier than to".to_string(),
+                ));
+            }
+        }
+        Ok(window)
+    }
+
+    /// Appends ` AND column >= from AND column < to` for the bounds that are set.
+    pub fn push_filter(&self, query_builder: &mut QueryBuilder<'_, Postgres>, column: &str) {
+        if let Some(from) = self.from {
+            query_builder.push(format!(" AND {}>=", column));
+            query_builder.push_bind(from);
+        }
+        if let Some(to) = self.to {
+            query_builder.push(format!(" AND {}<", column));
+            query_builder.push_bind(to);
+        }
+    }
+}
+
+/// A named calendar period such as `2024-W45`, `2024-11`, `2024-Q4` or `2024`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Week { year: i32, week: u32 },
+    Month { year: i32, month: u32 },
+    Quarter { year: i32, quarter: u32 },
+    Year(i32),
+}
+
+impl Period {
+    pub fn parse(value: &str) -> Option<Self> {
+        let (year, rest) = match value.split_once('-') {
+            Some((year, rest)) => (year, Some(rest)),
+            None => (value, None),
+        };
+        if year.len() != 4 {
+            return None;
+        }
+        let year = year.parse::<i32>().ok()?;
+        let period = match rest {
+            None => Period::Year(year),
+            Some(rest) => {
+                if let Some(week) = rest.strip_prefix('W') {
+                    Period::Week {
+                        year,
+                        week: week.parse().ok()?,
+                    }
+                } else if let Some(quarter) = rest.strip_prefix('Q') {
+                    Period::Quarter {
+                        year,
+                        quarter: quarter.parse().ok()?,
+                    }
+                } else if rest.len() == 2 {
+                    Period::Month {
+                        year,
+                        month: rest.parse().ok()?,
+                    }
+                } else {
+                    return None;
+                }
+            }
+        };
+        // Rejects out-of-range weeks, months and quarters.
+        period.bounds().map(|_| period)
+    }
+
+    /// Half-open `[start, end)` date range covered by the period.
+    pub fn bounds(&self) -> Option<(NaiveDate, NaiveDate)> {
+        let start = match *self {
+            Period::Week { year, week } => NaiveDate::from_isoywd_opt(year, week, Weekday::Mon)?,
+            Period::Month { year, month } => NaiveDate::from_ymd_opt(year, month, 1)?,
+            Period::Quarter { year, quarter } if (1..=4).contains(&quarter) => {
+                NaiveDate::from_ymd_opt(year, (quarter - 1) * 3 + 1, 1)?
+            }
+            Period::Quarter { .. } => return None,
+            Period::Year(year) => NaiveDate::from_ymd_opt(year, 1, 1)?,
+        };
+        let end = match self {
+            Period::Week { .. } => start.checked_add_signed(Duration::weeks(1))?,
+            Period::Month { .. } => start.checked_add_months(Months::new(1))?,
+            Period::Quarter { .. } => start.checked_add_months(Months::new(3))?,
+            Period::Year(_) => start.checked_add_months(Months::new(12))?,
+        };
+        Some((start, end))
+    }
+}
+
+/// Same day `months` months earlier, clamped to the end of the target month
+/// (Mar 31 minus one month is Feb 28 or Feb 29).
+pub fn months_before(date: NaiveDate, months: u32) -> NaiveDate {
+    date.checked_sub_months(Months::new(months))
+        .unwrap_or(NaiveDate::MIN)
+}
+
+/// Monday of the ISO week containing `date`.
+pub fn start_of_week(date: NaiveDate) -> NaiveDate {
+    date - Duration::days(date.weekday().num_days_from_monday() as i64)
+}
+
+pub fn start_of_month(date: NaiveDate) -> NaiveDate {
+    date.with_day(1).unwrap_or(date)
+}
+
+pub fn start_of_quarter(date: NaiveDate) -> NaiveDate {
+    NaiveDate::from_ymd_opt(date.year(), date.month0() / 3 * 3 + 1, 1).unwrap_or(date)
+}
+
+pub fn start_of_year(date: NaiveDate) -> NaiveDate {
+    NaiveDate::from_ymd_opt(date.year(), 1, 1).unwrap_or(date)
+}
+
+fn midnight(date: NaiveDate) -> DateTime<Utc> {
+    date.and_time(NaiveTime::MIN).and_utc()
+}
+
+/// Maps an `interval` value to a window relative to `now`.
+///
+/// Trailing presets: `1D`, `7D`, `1M`, `3M`, `1Y`, `All`.
+/// Calendar-to-date presets: `WTD`, `MTD`, `QTD`, `YTD`.
+/// Named periods: `2024-W45`, `2024-11`, `2024-Q4`, `2024`.
+fn preset(interval: &str, now: DateTime<Utc>) -> Result<TimeWindow, ApiError> {
+    let today = now.date_naive();
+    let (from, to, bucket) = match interval {
+        "1D" => (Some(now - Duration::days(1)), None, Bucket::FiveMinutes),
+        "7D" => (Some(now - Duration::days(7)), None, Bucket::FiveMinutes),
+        "1M" => (
+            Some(midnight(months_before(today, 1))),
+            None,
+            Bucket::OneHour,
+        ),
+        "3M" => (
+            Some(midnight(months_before(today, 3))),
+            None,
+            Bucket::OneHour,
+        ),
+        "1Y" => (
+            Some(midnight(months_before(today, 12))),
+            None,
+            Bucket::OneHour,
+        ),
+        "All" => (None, None, Bucket::OneHour),
+        "WTD" => (
+            Some(midnight(start_of_week(today))),
+            None,
+            Bucket::FiveMinutes,
+        ),
+        "MTD" => (Some(midnight(start_of_month(today))), None, Bucket::OneHour),
+        "QTD" => (
+            Some(midnight(start_of_quarter(today))),
+            None,
+            Bucket::OneHour,
+        ),
+        "YTD" => (Some(midnight(start_of_year(today))), None, Bucket::OneHour),
+        _ => {
+            let period = Period::parse(interval).ok_or_else(|| {
+                ApiError::InvalidRequest(format!(
+                    "Unknown interval '{}', expected one of 1D, 7D, 1M, 3M, 1Y, All, WTD, MTD, QTD, YTD or a period like 2024-W45, 2024-11, 2024-Q4, 2024",
+                    interval
+                ))
+            })?;
+            let (start, end) = period.bounds().ok_or_else(|| {
+                ApiError::InvalidRequest(format!("Period out of range: {}", interval))
+            })?;
+            let bucket = match period {
+                Period::Week { .. } => Bucket::FiveMinutes,
+                _ => Bucket::OneHour,
+            };
+            (Some(midnight(start)), Some(midnight(end)), bucket)
+        }
+    };
+    Ok(TimeWindow { from, to, bucket })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn buckets_floor_like_their_sql() {
+        let time = at(2024, 11, 14, 13, 47);
+        assert_eq!(Bucket::FiveMinutes.floor(time), at(2024, 11, 14, 13, 45));
+        assert_eq!(Bucket::FourHours.floor(time), at(2024, 11, 14, 12, 0));
+        assert_eq!(Bucket::OneDay.floor(time), at(2024, 11, 14, 0, 0));
+        // 2024-11-14 is a Thursday.
+        assert_eq!(Bucket::OneWeek.floor(time), at(2024, 11, 11, 0, 0));
+        assert_eq!(
+            Bucket::OneWeek.floor(at(2024, 11, 11, 0, 0)),
+            at(2024, 11, 11, 0, 0)
+        );
+    }
+
+    #[test]
+    fn warm_up_starts_step_back_per_series() {
+        let mut query_builder = QueryBuilder::new("");
+        Bucket::OneHour.push_warm_up_starts(
+            &mut query_builder,
+            "balance_data",
+            &[(1, "BTC".to_string()), (2, "ETH".to_string())],
+            at(2024, 11, 14, 0, 0),
+            6,
+        );
+        let sql = query_builder.sql();
+        assert!(sql.starts_with("WITH RECURSIVE seed AS ("));
+        assert!(sql.contains("unnest($2::INT[], $3::VARCHAR[])"));
+        assert!(sql.contains("FROM balance_data r WHERE"));
+        assert!(sql.contains("s.n < $4"));
+        assert!(sql.ends_with("GROUP BY exchange_id, token_symbol) "));
+    }
+
+    #[test]
+    fn months_before_clamps_to_month_end() {
+        assert_eq!(months_before(date(2024, 3, 31), 1), date(2024, 2, 29));
+        assert_eq!(months_before(date(2023, 3, 31), 1), date(2023, 2, 28));
+        assert_eq!(months_before(date(2024, 5, 31), 1), date(2024, 4, 30));
+        assert_eq!(months_before(date(2024, 7, 31), 3), date(2024, 4, 30));
+        assert_eq!(months_before(date(2024, 5, 31), 3), date(2024, 2, 29));
+    }
+
+    #[test]
+    fn months_before_crosses_year_boundary() {
+        assert_eq!(months_before(date(2024, 1, 15), 1), date(2023, 12, 15));
+        assert_eq!(months_before(date(2024, 1, 31), 1), date(2023, 12, 31));
+        assert_eq!(months_before(date(2024, 2, 10), 3), date(2023, 11, 10));
+    }
+
+    #[test]
+    fn months_before_handles_leap_day() {
+        assert_eq!(months_before(date(2024, 2, 29), 12), date(2023, 2, 28));
+        assert_eq!(months_before(date(2028, 2, 29), 48), date(2024, 2, 29));
+        assert_eq!(months_before(date(2025, 3, 1), 12), date(2024, 3, 1));
+        assert_eq!(months_before(date(2100, 3, 29), 1), date(2100, 2, 28));
+        assert_eq!(months_before(date(2000, 3, 29), 1), date(2000, 2, 29));
+    }
+
+    #[test]
+    fn start_of_week_is_monday() {
+        assert_eq!(start_of_week(date(2024, 11, 4)), date(2024, 11, 4));
+        assert_eq!(start_of_week(date(2024, 11, 10)), date(2024, 11, 4));
+        assert_eq!(start_of_week(date(2025, 1, 1)), date(2024, 12, 30));
+    }
+
+    #[test]
+    fn start_of_calendar_periods() {
+        assert_eq!(start_of_month(date(2024, 2, 29)), date(2024, 2, 1));
+        assert_eq!(start_of_quarter(date(2024, 3, 31)), date(2024, 1, 1));
+        assert_eq!(start_of_quarter(date(2024, 4, 1)), date(2024, 4, 1));
+        assert_eq!(start_of_quarter(date(2024, 11, 5)), date(2024, 10, 1));
+        assert_eq!(start_of_quarter(date(2024, 12, 31)), date(2024, 10, 1));
+        assert_eq!(start_of_year(date(2024, 12, 31)), date(2024, 1, 1));
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("30m").unwrap(), Duration::minutes(30));
+        assert_eq!(parse_duration("24h").unwrap(), Duration::hours(24));
+        assert_eq!(parse_duration("7d").unwrap(), Duration::days(7));
+        assert_eq!(parse_duration("2w").unwrap(), Duration::weeks(2));
+        for invalid in ["", "h", "0h", "-1d", "1y", "1.5h", "24 h", "1é"] {
+            assert!(parse_duration(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn trailing_presets() {
+        let now = at(2024, 3, 31, 15, 30);
+        let window = preset("1D", now).unwrap();
+        assert_eq!(window.from, Some(at(2024, 3, 30, 15, 30)));
+        assert_eq!(window.to, None);
+        assert_eq!(window.bucket, Bucket::FiveMinutes);
+
+        let window = preset("7D", now).unwrap();
+        assert_eq!(window.from, Some(at(2024, 3, 24, 15, 30)));
+
+        let window = preset("1M", now).unwrap();
+        assert_eq!(window.from, Some(at(2024, 2, 29, 0, 0)));
+        assert_eq!(window.bucket, Bucket::OneHour);
+
+        let window = preset("3M", now).unwrap();
+        assert_eq!(window.from, Some(at(2023, 12, 31, 0, 0)));
+
+        let window = preset("All", now).unwrap();
+        assert_eq!(window.from, None);
+        assert_eq!(window.to, None);
+    }
+
+    #[test]
+    fn one_year_preset_on_leap_day_does_not_panic() {
+        let window = preset("1Y", at(2024, 2, 29, 12, 0)).unwrap();
+        assert_eq!(window.from, Some(at(2023, 2, 28, 0, 0)));
+    }
+
+    #[test]
+    fn to_date_presets() {
+        let now = at(2024, 11, 10, 8, 0);
+        assert_eq!(
+            preset("WTD", now).unwrap().from,
+            Some(at(2024, 11, 4, 0, 0))
+        );
+        assert_eq!(
+            preset("MTD", now).unwrap().from,
+            Some(at(2024, 11, 1, 0, 0))
+        );
+        assert_eq!(
+            preset("QTD", now).unwrap().from,
+            Some(at(2024, 10, 1, 0, 0))
+        );
+        assert_eq!(preset("YTD", now).unwrap().from, Some(at(2024, 1, 1, 0, 0)));
+        assert_eq!(preset("YTD", now).unwrap().to, None);
+    }
+
+    #[test]
+    fn named_periods() {
+        let now = at(2024, 11, 10, 8, 0);
+        let window = preset("2024-02", now).unwrap();
+        assert_eq!(window.from, Some(at(2024, 2, 1, 0, 0)));
+        assert_eq!(window.to, Some(at(2024, 3, 1, 0, 0)));
+
+        let window = preset("2024-Q4", now).unwrap();
+        assert_eq!(window.from, Some(at(2024, 10, 1, 0, 0)));
+        assert_eq!(window.to, Some(at(2025, 1, 1, 0, 0)));
+
+        let window = preset("2024", now).unwrap();
+        assert_eq!(window.from, Some(at(2024, 1, 1, 0, 0)));
+        assert_eq!(window.to, Some(at(2025, 1, 1, 0, 0)));
+
+        let window = preset("2024-W01", now).unwrap();
+        assert_eq!(window.from, Some(at(2024, 1, 1, 0, 0)));
+        assert_eq!(window.to, Some(at(2024, 1, 8, 0, 0)));
+        assert_eq!(window.bucket, Bucket::FiveMinutes);
+
+        let window = preset("2020-W53", now).unwrap();
+        assert_eq!(window.from, Some(at(2020, 12, 28, 0, 0)));
+        assert_eq!(window.to, Some(at(2021, 1, 4, 0, 0)));
+    }
+
+    #[test]
+    fn invalid_periods_are_rejected() {
+        for value in [
+            "2021-W53", "2024-W00", "2024-13", "2024-00", "2024-Q0", "2024-Q5", "24-01", "2024-1",
+            "1W", "",
+        ] {
+            assert!(
+                Period::parse(value).is_none(),
+                "{} should be rejected",
+                value
+            );
+            assert!(preset(value, at(2024, 1, 1, 0, 0)).is_err());
+        }
+    }
+
+    #[test]
+    fn bucket_parsing() {
+        assert_eq!("15m".parse::<Bucket>().unwrap(), Bucket::FifteenMinutes);
+        assert_eq!("1w".parse::<Bucket>().unwrap(), Bucket::OneWeek);
+        assert!("2h".parse::<Bucket>().is_err());
+    }
+
+    #[test]
+    fn resolve_overrides_preset_with_explicit_values() {
+        let now = at(2024, 11, 10, 8, 0);
+        let window = TimeWindow::resolve_at(
+            now,
+            Some("1Y"),
+            Some("1730764800"),
+            Some("2024-11-06T00:00:00Z"),
+            Some("4h"),
+        )
+        .unwrap();
+        assert_eq!(window.from, Some(at(2024, 11, 5, 0, 0)));
+        assert_eq!(window.to, Some(at(2024, 11, 6, 0, 0)));
+        assert_eq!(window.bucket, Bucket::FourHours);
+    }
+
+    #[test]
+    fn resolve_rejects_bad_input() {
+        let now = at(2024, 11, 10, 8, 0);
+        assert!(TimeWindow::resolve_at(now, None, None, None, None).is_err());
+        assert!(TimeWindow::resolve_at(now, None, Some("yesterday"), None, None).is_err());
+        assert!(
+            TimeWindow::resolve_at(now, None, Some("1730851200"), Some("1730764800"), None)
+                .is_err()
+        );
+    }
+}
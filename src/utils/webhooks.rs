@@ -0,0 +1,607 @@
+use crate::{dto::response::SymbolListResponse, AppState};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use sqlx::PgPool;
+use std::{
+    collections::BTreeSet,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use tracing::{error, info};
+
+pub const ALERT_TRIGGERED: &str = "alert.triggered";
+/// A symbol starts reporting data on an exchange it had no rows for.
+pub const DATA_AVAILABLE: &str = "data.available";
+pub const EVENT_TYPES: [&str; 2] = [ALERT_TRIGGERED, DATA_AVAILABLE];
+
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+pub const DELIVERY_HEADER: &str = "X-Webhook-Delivery";
+
+const MAX_ATTEMPTS: i32 = 8;
+const BASE_BACKOFF_SECS: i64 = 30;
+const MAX_BACKOFF_SECS: i64 = 6 * 3600;
+const BATCH_SIZE: i64 = 50;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a claimed delivery is hidden from other dispatchers.
+const CLAIM_LEASE_SECS: i64 = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, sqlx::Type)]
+#[sqlx(type_name = "varchar", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    Pending,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Webhook {
+    pub id: i64,
+    pub user_id: i64,
+    pub url: String,
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub event_types: Vec<String>,
+    pub enabled: bool,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub webhook_id: i64,
+    pub event_type: String,
+    pub payload: Value,
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    pub response_status: Option<i32>,
+    pub last_error: Option<String>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub next_attempt_at: DateTime<Utc>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "chrono::serde::ts_seconds_option")]
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+/// Hex encoded HMAC-SHA256 of `{timestamp}.{body}`, sent as `sha256=<hex>`.
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Delay before the next attempt once `attempts` attempts have failed.
+pub fn backoff(attempts: i32) -> ChronoDuration {
+    let exponent = attempts.saturating_sub(1).clamp(0, 20) as u32;
+    ChronoDuration::seconds((BASE_BACKOFF_SECS << exponent).min(MAX_BACKOFF_SECS))
+}
+
+/// Queues `payload` for every enabled webhook subscribed to `event_type`
+/// (an empty filter subscribes to all of them), limited to the webhooks of `user_id` when given.
+pub async fn enqueue(
+    db: &PgPool,
+    user_id: Option<i64>,
+    event_type: &str,
+    payload: &Value,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        "INSERT INTO webhook_deliveries (webhook_id, event_type, payload) \
+         SELECT id, $1, $2 FROM webhooks \
+         WHERE enabled AND (cardinality(event_types) = 0 OR $1 = ANY(event_types)) \
+         AND ($3::BIGINT IS NULL OR user_id = $3)",
+    )
+    .bind(event_type)
+    .bind(payload)
+    .bind(user_id)
+    .execute(db)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Queues a new delivery with the payload of an earlier one.
+pub async fn redeliver(
+    db: &PgPool,
+    webhook_id: i64,
+    delivery_id: i64,
+) -> Result<Option<WebhookDelivery>, sqlx::Error> {
+    sqlx::query_as::<_, WebhookDelivery>(
+        "INSERT INTO webhook_deliveries (webhook_id, event_type, payload) \
+         SELECT webhook_id, event_type, payload FROM webhook_deliveries WHERE id = $1 AND webhook_id = $2 \
+         RETURNING *",
+    )
+    .bind(delivery_id)
+    .bind(webhook_id)
+    .fetch_optional(db)
+    .await
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_multicast()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        // 0.0.0.0/8, carrier-grade NAT 100.64.0.0/10, benchmarking
+        // 198.18.0.0/15 and the reserved 240.0.0.0/4.
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 198 && (18..20).contains(&b))
+        || a >= 240)
+}
+
+/// Whether webhooks may be delivered to `ip`. Loopback, private, link-local,
+/// unspecified, multicast and other non-global addresses are refused so that
+/// webhooks cannot reach the server's own network.
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_ipv4(ip);
+            }
+            let [first, second, ..] = ip.segments();
+            // NAT64 addresses embed an IPv4 address in their last 32 bits.
+            if ip.segments()[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                let [.., a, b, c, d] = ip.octets();
+                return is_public_ipv4(Ipv4Addr::new(a, b, c, d));
+            }
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                // Unique local fc00::/7, link-local fe80::/10, documentation
+                // 2001:db8::/32.
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+                || (first == 0x2001 && second == 0x0db8)
+                || ip == Ipv6Addr::from([0u16; 8]))
+        }
+    }
+}
+
+/// The host of `url`, either an IP literal or a domain name.
+fn url_host(url: &reqwest::Url) -> Option<Result<IpAddr, &str>> {
+    let host = url.host_str()?;
+    Some(
+        host.trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse()
+            .map_err(|_| host),
+    )
+}
+
+/// Parses a webhook URL and refuses the ones that name a local or non-public
+/// host. Host names are checked again when they are resolved for delivery.
+pub fn check_url(url: &str) -> Result<reqwest::Url, String> {
+    let url = reqwest::Url::parse(url).map_err(|e| format!("Invalid url: {}", e))?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err("url must use http or https".to_string());
+    }
+    let public = match url_host(&url) {
+        Some(Ok(ip)) => is_public_ip(ip),
+        Some(Err(domain)) => {
+            let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+            domain != "localhost" && !domain.ends_with(".localhost")
+        }
+        None => false,
+    };
+    if !public {
+        return Err("url must point to a public host".to_string());
+    }
+    Ok(url)
+}
+
+/// A client for one delivery that can only connect to the public addresses
+/// `url` resolves to right now. Pinning them means a DNS answer that changes
+/// after the check cannot send the request to an internal address, and
+/// redirects are not followed for the same reason.
+async fn delivery_client(url: &str) -> Result<reqwest::Client, String> {
+    let url = check_url(url)?;
+    let builder = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none());
+    let builder = match url_host(&url) {
+        Some(Err(domain)) => {
+            let port = url.port_or_known_default().unwrap_or(80);
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((domain, port))
+                .await
+                .map_err(|e| format!("Failed to resolve {}: {}", domain, e))?
+                .collect();
+            if addrs.is_empty() || !addrs.iter().all(|addr| is_public_ip(addr.ip())) {
+                return Err(format!("{} does not resolve to a public address", domain));
+            }
+            builder.resolve_to_addrs(domain, &addrs)
+        }
+        _ => builder,
+    };
+    builder.build().map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttemptOutcome {
+    Delivered(u16),
+    Failed { status: Option<u16>, error: String },
+}
+
+/// POSTs one signed delivery. Any 2xx response counts as delivered.
+pub async fn attempt(
+    client: &reqwest::Client,
+    url: &str,
+    secret: &str,
+    delivery: &WebhookDelivery,
+    timestamp: i64,
+) -> AttemptOutcome {
+    let body = json!({
+        "delivery_id": delivery.id,
+        "event": delivery.event_type,
+        "timestamp": timestamp,
+        "data": delivery.payload,
+    })
+    .to_string();
+    let signature = sign(secret, timestamp, body.as_bytes());
+    let response = client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(SIGNATURE_HEADER, format!("sha256={}", signature))
+        .header(TIMESTAMP_HEADER, timestamp.to_string())
+        .header(EVENT_HEADER, &delivery.event_type)
+        .header(DELIVERY_HEADER, delivery.id.to_string())
+        .body(body)
+        .send()
+        .await;
+    match response {
+        Ok(response) if response.status().is_success() => {
+            AttemptOutcome::Delivered(response.status().as_u16())
+        }
+        Ok(response) => AttemptOutcome::Failed {
+            status: Some(response.status().as_u16()),
+            error: format!("Endpoint responded with {}", response.status()),
+        },
+        Err(e) => AttemptOutcome::Failed {
+            status: None,
+            error: e.to_string(),
+        },
+    }
+}
+
+async fn record(
+    db: &PgPool,
+    delivery: &WebhookDelivery,
+    outcome: &AttemptOutcome,
+    now: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    let attempts = delivery.attempts + 1;
+    let (status, response_status, error, delivered_at) = match outcome {
+        AttemptOutcome::Delivered(code) => {
+            (DeliveryStatus::Succeeded, Some(*code), None, Some(now))
+        }
+        AttemptOutcome::Failed { status, error } if attempts >= MAX_ATTEMPTS => {
+            (DeliveryStatus::Failed, *status, Some(error.clone()), None)
+        }
+        AttemptOutcome::Failed { status, error } => {
+            (DeliveryStatus::Pending, *status, Some(error.clone()), None)
+        }
+    };
+    sqlx::query(
+        "UPDATE webhook_deliveries SET status = $2, attempts = $3, response_status = $4, last_error = $5, \
+         next_attempt_at = $6, delivered_at = $7 WHERE id = $1",
+    )
+    .bind(delivery.id)
+    .bind(status)
+    .bind(attempts)
+    .bind(response_status.map(i32::from))
+    .bind(error)
+    .bind(now + backoff(attempts))
+    .bind(delivered_at)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Attempts the due deliveries of enabled webhooks. Returns how many were attempted.
+pub async fn dispatch_due(db: &PgPool) -> Result<usize, sqlx::Error> {
+    let deliveries = sqlx::query_as::<_, WebhookDelivery>(
+        "UPDATE webhook_deliveries SET next_attempt_at = NOW() + make_interval(secs => $1) \
+         WHERE id IN (SELECT d.id FROM webhook_deliveries d JOIN webhooks w ON w.id = d.webhook_id \
+         WHERE w.enabled AND d.status = 'pending' AND d.next_attempt_at <= NOW() \
+         ORDER BY d.next_attempt_at LIMIT $2 FOR UPDATE OF d SKIP LOCKED) \
+         RETURNING *",
+    )
+    .bind(CLAIM_LEASE_SECS as f64)
+    .bind(BATCH_SIZE)
+    .fetch_all(db)
+    .await?;
+    if deliveries.is_empty() {
+        return Ok(0);
+    }
+    let webhook_ids: Vec<i64> = deliveries.iter().map(|d| d.webhook_id).collect();
+    let webhooks = sqlx::query_as::<_, Webhook>("SELECT * FROM webhooks WHERE id = ANY($1)")
+        .bind(webhook_ids)
+        .fetch_all(db)
+        .await?;
+    let attempts = deliveries.iter().filter_map(|delivery| {
+        let webhook = webhooks.iter().find(|w| w.id == delivery.webhook_id)?;
+        Some(async move {
+            let now = Utc::now();
+            let outcome = match delivery_client(&webhook.url).await {
+                Ok(client) => {
+                    attempt(
+                        &client,
+                        &webhook.url,
+                        &webhook.secret,
+                        delivery,
+                        now.timestamp(),
+                    )
+                    .await
+                }
+                Err(error) => AttemptOutcome::Failed {
+                    status: None,
+                    error,
+                },
+            };
+            if let AttemptOutcome::Failed { error, .. } = &outcome {
+                info!("Webhook delivery {} failed: {}", delivery.id, error);
+            }
+            if let Err(e) = record(db, delivery, &outcome, now).await {
+                error!("💥 Error to record webhook delivery {}: {}", delivery.id, e);
+            }
+        })
+    });
+    Ok(futures::future::join_all(attempts).await.len())
+}
+
+/// `(symbol, table, exchange_id)` of every series in the catalog.
+pub fn coverage_keys(symbols: &SymbolListResponse) -> BTreeSet<(String, &'static str, i32)> {
+    let mut keys = BTreeSet::new();
+    for info in &symbols.data {
+        for (table, coverage) in [("volume", &info.volume), ("balance", &info.balance)] {
+            for exchange in coverage.iter().flat_map(|c| &c.exchanges) {
+                keys.insert((info.symbol.clone(), table, exchange.exchange_id));
+            }
+        }
+    }
+    keys
+}
+
+/// Queues `data.available` for series that appeared since the last refresh
+/// of the symbol catalog. Nothing is sent for the first catalog seen.
+async fn announce_new_data(
+    state: &AppState,
+    seen: &mut Option<BTreeSet<(String, &'static str, i32)>>,
+) -> Result<(), sqlx::Error> {
+    let Some(symbols) = state.symbols.get() else {
+        return Ok(());
+    };
+    let keys = coverage_keys(&symbols);
+    if let Some(previous) = seen.as_ref() {
+        for (symbol, table, exchange_id) in keys.difference(previous) {
+            let payload = json!({
+                "symbol": symbol,
+                "table": table,
+                "exchange_id": exchange_id,
+                "exchange": state.exchanges.name(*exchange_id),
+            });
+            enqueue(&state.db, None, DATA_AVAILABLE, &payload).await?;
+        }
+    }
+    *seen = Some(keys);
+    Ok(())
+}
+
+pub fn start_webhook_dispatcher(state: Arc<AppState>, interval: Duration) {
+    tokio::spawn(async move {
+        let mut seen = None;
+        loop {
+            if let Err(e) = announce_new_data(&state, &mut seen).await {
+                error!("💥 Error to queue data webhooks: {}", e);
+            }
+            if let Err(e) = dispatch_due(&state.db).await {
+                error!("💥 Error to dispatch webhooks: {}", e);
+            }
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dto::response::{ExchangeCoverage, SymbolCoverage, SymbolInfo};
+    use axum::{body::Bytes, extract::State, http::HeaderMap, http::StatusCode, routing::post};
+    use std::sync::{
+        atomic::{AtomicU16, Ordering},
+        Mutex,
+    };
+
+    #[derive(Default)]
+    struct StandIn {
+        status: AtomicU16,
+        received: Mutex<Vec<(HeaderMap, Bytes)>>,
+    }
+
+    async fn receive(
+        State(stand_in): State<Arc<StandIn>>,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> StatusCode {
+        stand_in.received.lock().unwrap().push((headers, body));
+        StatusCode::from_u16(stand_in.status.load(Ordering::SeqCst)).unwrap()
+    }
+
+    async fn serve(stand_in: Arc<StandIn>) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let router = axum::Router::new()
+            .route("/hook", post(receive))
+            .with_state(stand_in);
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        format!("http://{}/hook", address)
+    }
+
+    fn delivery() -> WebhookDelivery {
+        let now = DateTime::from_timestamp(1_730_764_800, 0).unwrap();
+        WebhookDelivery {
+            id: 7,
+            webhook_id: 1,
+            event_type: ALERT_TRIGGERED.to_string(),
+            payload: json!({ "rule_id": 3, "value": 42.0 }),
+            status: DeliveryStatus::Pending,
+            attempts: 0,
+            response_status: None,
+            last_error: None,
+            next_attempt_at: now,
+            created_at: now,
+            delivered_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn deliveries_are_signed() {
+        let stand_in = Arc::new(StandIn::default());
+        stand_in.status.store(204, Ordering::SeqCst);
+        let url = serve(stand_in.clone()).await;
+        let client = reqwest::Client::new();
+
+        let outcome = attempt(&client, &url, "s3cret", &delivery(), 1_730_764_800).await;
+        assert_eq!(outcome, AttemptOutcome::Delivered(204));
+        let (headers, body) = stand_in.received.lock().unwrap()[0].clone();
+        let signature = format!("sha256={}", sign("s3cret", 1_730_764_800, &body));
+        assert_eq!(headers[SIGNATURE_HEADER], signature.as_str());
+        assert_eq!(headers[TIMESTAMP_HEADER], "1730764800");
+        assert_eq!(headers[EVENT_HEADER], ALERT_TRIGGERED);
+        assert_eq!(headers[DELIVERY_HEADER], "7");
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["data"]["rule_id"], 3);
+        assert_ne!(
+            signature,
+            format!("sha256={}", sign("other", 1_730_764_800, b"{}"))
+        );
+    }
+
+    #[tokio::test]
+    async fn failures_are_reported_for_retry() {
+        let stand_in = Arc::new(StandIn::default());
+        stand_in.status.store(503, Ordering::SeqCst);
+        let url = serve(stand_in.clone()).await;
+        let client = reqwest::Client::new();
+
+        match attempt(&client, &url, "s3cret", &delivery(), 0).await {
+            AttemptOutcome::Failed { status, .. } => assert_eq!(status, Some(503)),
+            outcome => panic!("unexpected outcome {:?}", outcome),
+        }
+        let unreachable = url.replace("/hook", "/missing");
+        match attempt(&client, &unreachable, "s3cret", &delivery(), 0).await {
+            AttemptOutcome::Failed { status, .. } => assert_eq!(status, Some(404)),
+            outcome => panic!("unexpected outcome {:?}", outcome),
+        }
+        match attempt(&client, "http://127.0.0.1:1/hook", "s3cret", &delivery(), 0).await {
+            AttemptOutcome::Failed { status, .. } => assert_eq!(status, None),
+            outcome => panic!("unexpected outcome {:?}", outcome),
+        }
+    }
+
+    #[test]
+    fn internal_targets_are_refused() {
+        for url in [
+            "http://localhost:8080/hook",
+            "http://api.localhost/hook",
+            "http://LOCALHOST./hook",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://127.0.0.1:5432/",
+            "http://2130706433/",
+            "http://10.0.0.8/hook",
+            "http://172.16.3.4/hook",
+            "http://192.168.1.1/hook",
+            "http://100.64.0.1/hook",
+            "http://0.0.0.0/hook",
+            "http://224.0.0.1/hook",
+            "http://[::1]/hook",
+            "http://[::ffff:127.0.0.1]/hook",
+            "http://[fd00::1]/hook",
+            "http://[fe80::1]/hook",
+            "http://[64:ff9b::a9fe:a9fe]/hook",
+            "ftp://example.com/hook",
+        ] {
+            assert!(check_url(url).is_err(), "{} was accepted", url);
+        }
+        for url in [
+            "https://example.com/hook",
+            "http://93.184.215.14:8080/hook",
+            "https://[2606:4700::1111]/hook",
+        ] {
+            assert!(check_url(url).is_ok(), "{} was refused", url);
+        }
+    }
+
+    #[tokio::test]
+    async fn resolved_addresses_are_checked_before_delivery() {
+        let error = delivery_client("http://localhost:8080/hook")
+            .await
+            .unwrap_err();
+        assert!(error.contains("public host"), "{}", error);
+        let error = delivery_client("http://169.254.169.254/")
+            .await
+            .unwrap_err();
+        assert!(error.contains("public host"), "{}", error);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_a_cap() {
+        assert_eq!(backoff(1), ChronoDuration::seconds(30));
+        assert_eq!(backoff(2), ChronoDuration::seconds(60));
+        assert_eq!(backoff(4), ChronoDuration::seconds(240));
+        assert_eq!(backoff(30), ChronoDuration::seconds(MAX_BACKOFF_SECS));
+    }
+
+    #[test]
+    fn coverage_keys_list_each_series() {
+        let coverage = |exchange_ids: &[i32]| SymbolCoverage {
+            first_timestamp: 0,
+            last_timestamp: 0,
+            rows: 1,
+            exchanges: exchange_ids
+                .iter()
+                .map(|&exchange_id| ExchangeCoverage {
+                    exchange_id,
+                    exchange: None,
+                    first_timestamp: 0,
+                    last_timestamp: 0,
+                    rows: 1,
+                })
+                .collect(),
+        };
+        let symbols = SymbolListResponse {
+            refreshed_at: Some(0),
+            data: vec![SymbolInfo {
+                symbol: "BTC".to_string(),
+                base: None,
+                quote: None,
+                volume: None,
+                balance: Some(coverage(&[0, 2])),
+            }],
+        };
+        let keys: Vec<_> = coverage_keys(&symbols).into_iter().collect();
+        assert_eq!(
+            keys,
+            vec![
+                ("BTC".to_string(), "balance", 0),
+                ("BTC".to_string(), "balance", 2)
+            ]
+        );
+    }
+}